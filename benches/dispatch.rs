@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use zmach::{ZMachine, ZScreen};
+
+struct NullScreen;
+
+impl ZScreen for NullScreen {
+    fn newline(&self) {}
+    fn print(&self, _str: String) {}
+    fn read(&self) -> char { ' ' }
+    fn readline(&self) -> String { String::new() }
+    fn exit(&self) {}
+    fn set_status(&self, _status: String) {}
+    fn get_width(&self) {}
+    fn get_height(&self) {}
+    fn restart(&self) {}
+    fn save(&self, _state: Vec<u8>) {}
+    fn restore(&self) -> Vec<u8> { vec![] }
+    fn set_window(&self, _num: u16) {}
+    fn split_window(&self, _height: u16) {}
+    fn erase_window(&self, _num: u16) {}
+    fn move_cursor(&self, _x: u8, _y: u8) {}
+    fn print_number(&self, _num: u16) {}
+    fn print_char(&self, _char: char) {}
+}
+
+const LOOPS: u16 = 10_000;
+const INSTRUCTIONS_PER_LOOP: u64 = 5;
+
+// A V5 story which runs a tight arithmetic loop LOOPS times, then quits
+fn synthetic_story() -> Vec<u8> {
+    let mut data = vec![0u8; 0x800];
+    data[0x00] = 5;
+    data[0x06] = 0x04; // PC at 0x400
+    data[0x0C] = 0x01; // Globals at 0x100
+    data[0x0E] = 0x04; // Static memory at 0x400
+    data[0x04] = 0x04; // High memory at 0x400
+
+    #[rustfmt::skip]
+    let code = [
+        // 0x400: add G01 3 -> G01
+        0x54, 0x11, 0x03, 0x11,
+        // 0x404: mul G01 G02 -> G03
+        0x76, 0x11, 0x12, 0x13,
+        // 0x408: push G01
+        0xE8, 0xBF, 0x11,
+        // 0x40B: pull G04
+        0xE9, 0x7F, 0x14,
+        // 0x40E: inc_chk G00 LOOPS ?~0x400 (back to the start until the counter passes LOOPS)
+        0xC5, 0x4F, 0x10, (LOOPS >> 8) as u8, LOOPS as u8, 0x3F, 0xED,
+        // 0x415: quit
+        0xBA,
+    ];
+    data[0x400..0x400 + code.len()].copy_from_slice(&code);
+    data
+}
+
+fn dispatch(c: &mut Criterion) {
+    let story = synthetic_story();
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(LOOPS as u64 * INSTRUCTIONS_PER_LOOP));
+    group.bench_function("arithmetic loop", |b| {
+        b.iter(|| {
+            let mut machine = ZMachine::new(black_box(&story), Box::new(NullScreen)).unwrap();
+            machine.run().unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
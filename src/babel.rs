@@ -0,0 +1,107 @@
+use crate::blorb::Blorb;
+use crate::error::Error;
+use crate::memory::Memory;
+
+/*
+ * Story identification following the Treaty of Babel (https://babel.ifarchive.org/).
+ * A story's IFID is an embedded UUID://...// string if it has one, otherwise it is
+ * built from the header as ZCODE-release-serial, with the checksum appended for
+ * stories which aren't Infocom-era releases. Bibliographic details come from the
+ * iFiction record in a Blorb's IFmd chunk.
+ */
+
+/// Identification and bibliographic details of a story file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StoryInfo {
+    pub ifid: String,
+    pub version: u8,
+    pub release: u16,
+    pub serial: String,
+    pub checksum: u16,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub headline: Option<String>,
+}
+
+impl StoryInfo {
+    /// Identifies a raw story file or a Blorb containing one. Fails for anything else
+    /// too short to have a story header.
+    pub fn identify(data: &[u8]) -> Result<StoryInfo, Error> {
+        if Blorb::is_blorb(data) {
+            StoryInfo::from_blorb(&Blorb::parse(data)?)
+        } else {
+            StoryInfo::from_story(data)
+        }
+    }
+
+    pub fn from_story(story: &[u8]) -> Result<StoryInfo, Error> {
+        Memory::check_header(story)?;
+        Ok(StoryInfo::from_memory(&Memory::new(story), None))
+    }
+
+    pub fn from_blorb(blorb: &Blorb) -> Result<StoryInfo, Error> {
+        let story = blorb.story()?;
+        Memory::check_header(story)?;
+        Ok(StoryInfo::from_memory(&Memory::new(story), blorb.metadata.as_deref()))
+    }
+
+    pub(crate) fn from_memory(mem: &Memory, ifiction: Option<&str>) -> StoryInfo {
+        let mut info = StoryInfo {
+            ifid: ifid(mem),
+            version: mem.zmachine_version(),
+            release: mem.release(),
+            serial: mem.serial(),
+            checksum: mem.checksum(),
+            ..Default::default()
+        };
+        if let Some(xml) = ifiction {
+            let biblio = element(xml, "bibliographic").unwrap_or(xml);
+            info.title = element(biblio, "title").map(unescape);
+            info.author = element(biblio, "author").map(unescape);
+            info.headline = element(biblio, "headline").map(unescape);
+        }
+        info
+    }
+}
+
+pub(crate) fn ifid(mem: &Memory) -> String {
+    if let Some(uuid) = embedded_uuid(mem) {
+        return uuid;
+    }
+    let serial = mem.serial();
+    let mut ifid = format!("ZCODE-{}-{}", mem.release(), serial);
+    if serial != "000000" && serial.starts_with(|c: char| c.is_ascii_digit() && c != '8') {
+        ifid.push_str(&format!("-{:04X}", mem.checksum()));
+    }
+    ifid
+}
+
+//Inform 6 and later compilers embed UUID://xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx// in the story
+fn embedded_uuid(mem: &Memory) -> Option<String> {
+    const PREFIX: &[u8] = b"UUID://";
+    mem.windows(PREFIX.len() + 38)
+        .filter(|w| w.starts_with(PREFIX) && w.ends_with(b"//"))
+        .map(|w| &w[PREFIX.len()..PREFIX.len() + 36])
+        .find(|uuid| uuid.iter().all(|b| b.is_ascii_hexdigit() || *b == b'-'))
+        .map(|uuid| String::from_utf8_lossy(uuid).to_ascii_uppercase())
+}
+
+//text content of the first <tag>...</tag> in `xml`
+fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(xml[start..end].trim())
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod babel_tests;
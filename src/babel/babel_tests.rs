@@ -0,0 +1,72 @@
+use super::*;
+
+fn create_story(release: u16, serial: &[u8; 6], checksum: u16) -> Vec<u8> {
+    let mut data = vec![0u8; 512];
+    data[0x00] = 3;
+    data[0x02..0x04].copy_from_slice(&release.to_be_bytes());
+    data[0x12..0x18].copy_from_slice(serial);
+    data[0x1C..0x1E].copy_from_slice(&checksum.to_be_bytes());
+    data
+}
+
+#[test]
+fn test_infocom_ifid() {
+    // Infocom-era serials (starting with 8) have no checksum
+    let info = StoryInfo::from_story(&create_story(88, b"840726", 0xA129)).unwrap();
+    assert_eq!(info.ifid, "ZCODE-88-840726");
+    assert_eq!(info.release, 88);
+    assert_eq!(info.serial, "840726");
+    assert_eq!(info.checksum, 0xA129);
+    assert_eq!(info.version, 3);
+}
+
+#[test]
+fn test_modern_ifid() {
+    let info = StoryInfo::from_story(&create_story(1, b"050914", 0x0c3f)).unwrap();
+    assert_eq!(info.ifid, "ZCODE-1-050914-0C3F");
+
+    let info = StoryInfo::from_story(&create_story(2, b"000000", 0x1234)).unwrap();
+    assert_eq!(info.ifid, "ZCODE-2-000000");
+}
+
+#[test]
+fn test_embedded_uuid() {
+    let mut story = create_story(1, b"050914", 0x0c3f);
+    let uuid = b"UUID://1a2b3c4d-0000-4000-8000-abcdefabcdef//";
+    story[0x100..0x100 + uuid.len()].copy_from_slice(uuid);
+    let info = StoryInfo::from_story(&story).unwrap();
+    assert_eq!(info.ifid, "1A2B3C4D-0000-4000-8000-ABCDEFABCDEF");
+}
+
+#[test]
+fn test_ifiction_metadata() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ifindex version="1.0" xmlns="http://babel.ifarchive.org/protocol/iFiction/">
+  <story>
+    <identification><ifid>ZCODE-1-050914-0C3F</ifid></identification>
+    <bibliographic>
+      <title>Curses &amp; More</title>
+      <author>Graham Nelson</author>
+      <headline>An Interactive Diversion</headline>
+    </bibliographic>
+  </story>
+</ifindex>"#;
+    let mem = Memory::new(&create_story(1, b"050914", 0x0c3f));
+    let info = StoryInfo::from_memory(&mem, Some(xml));
+    assert_eq!(info.title.as_deref(), Some("Curses & More"));
+    assert_eq!(info.author.as_deref(), Some("Graham Nelson"));
+    assert_eq!(info.headline.as_deref(), Some("An Interactive Diversion"));
+}
+
+#[test]
+fn test_identify_raw_story() {
+    let info = StoryInfo::identify(&create_story(5, b"871125", 0)).unwrap();
+    assert_eq!(info.ifid, "ZCODE-5-871125");
+    assert!(info.title.is_none());
+}
+
+#[test]
+fn test_identify_not_a_story() {
+    assert!(StoryInfo::identify(&[3u8; 10]).is_err());
+    assert!(StoryInfo::from_story(&[]).is_err());
+}
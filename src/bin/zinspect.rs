@@ -0,0 +1,95 @@
+use std::process::ExitCode;
+use zmach::inspect::{Report, Section};
+use zmach::Blorb;
+
+/*
+ * Prints an infodump-style report on a story file, or a Blorb holding one. With no
+ * options every section is printed; otherwise just the sections asked for, in order.
+ */
+
+const USAGE: &str = "usage: zinspect [-h] [-a] [-o] [-t] [-d] [-m] [--object N] <story>
+  -h          header
+  -a          abbreviations
+  -o          objects
+  -t          object tree
+  -d          dictionary
+  -m          memory map
+  --object N  object N only";
+
+fn main() -> ExitCode {
+    let mut sections = vec![];
+    let mut objects = vec![];
+    let mut path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" => sections.push(Section::Header),
+            "-a" => sections.push(Section::Abbreviations),
+            "-o" => sections.push(Section::Objects),
+            "-t" => sections.push(Section::Tree),
+            "-d" => sections.push(Section::Dictionary),
+            "-m" => sections.push(Section::MemoryMap),
+            "--object" => match args.next().and_then(|n| n.parse::<u16>().ok()) {
+                Some(n) => objects.push(n),
+                None => return usage(),
+            },
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => return usage(),
+        }
+    }
+    let Some(path) = path else {
+        return usage();
+    };
+
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("zinspect: {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let blorb;
+    let story = if Blorb::is_blorb(&data) {
+        blorb = Blorb::parse(&data);
+        match blorb.as_ref().map_err(|e| e.to_string()).and_then(|b| b.story().map_err(|e| e.to_string())) {
+            Ok(story) => story,
+            Err(e) => {
+                eprintln!("zinspect: {}: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        &data[..]
+    };
+
+    let report = match Report::new(story) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("zinspect: {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if sections.is_empty() && objects.is_empty() {
+        sections.extend(Section::ALL);
+    }
+    print!("{}", report.render(&sections));
+    for n in objects {
+        match report.object(n) {
+            Some(obj) => {
+                let mut out = String::new();
+                report.write_object(&mut out, obj);
+                print!("{}", out);
+            }
+            None => {
+                eprintln!("zinspect: no object {} (the story has {})", n, report.objects.len());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
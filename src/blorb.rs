@@ -0,0 +1,205 @@
+use crate::error::Error;
+use std::collections::BTreeMap;
+
+/*
+ * Blorb is an IFF container (FORM type IFRS) bundling a story file with its pictures,
+ * sounds and metadata. The RIdx chunk indexes every resource by usage and number and
+ * points at the chunk holding it. See https://www.eblong.com/zarf/blorb/blorb.html
+ */
+
+/// What a resource is used for, from the `RIdx` chunk.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Usage {
+    Pict,
+    Snd,
+    Exec,
+    Data,
+}
+
+impl Usage {
+    fn from_id(id: &[u8]) -> Option<Usage> {
+        match id {
+            b"Pict" => Some(Usage::Pict),
+            b"Snd " => Some(Usage::Snd),
+            b"Exec" => Some(Usage::Exec),
+            b"Data" => Some(Usage::Data),
+            _ => None,
+        }
+    }
+}
+
+/// A single indexed resource: the chunk type (eg `PNG `, `JPEG`, `AIFF`, `OGGV`, `ZCOD`) and its data.
+/// An `AIFF` sound's data is its whole `FORM` chunk, header included, as an AIFF file holds.
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub usage: Usage,
+    pub number: u32,
+    pub chunk_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Blorb {
+    resources: BTreeMap<(Usage, u32), Resource>,
+    descriptions: BTreeMap<(Usage, u32), String>,
+    /// Picture number of the cover art, from the `Fspc` chunk.
+    pub frontispiece: Option<u32>,
+    /// The iFiction XML from the `IFmd` chunk.
+    pub metadata: Option<String>,
+    /// The release number from the `RelN` chunk.
+    pub release: Option<u16>,
+}
+
+struct Chunk<'a> {
+    id: &'a [u8],
+    data: &'a [u8],
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::InvalidBlorb(msg.to_string())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    offset
+        .checked_add(4)
+        .and_then(|end| data.get(offset..end))
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("unexpected end of data"))
+}
+
+//the chunk whose header starts at `offset`
+fn chunk_at(data: &[u8], offset: usize) -> Result<Chunk<'_>, Error> {
+    let id = offset
+        .checked_add(4)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| invalid("chunk offset out of range"))?;
+    let len = read_u32(data, offset + 4)? as usize;
+    let data = (offset + 8)
+        .checked_add(len)
+        .and_then(|end| data.get(offset + 8..end))
+        .ok_or_else(|| invalid("chunk extends past end of file"))?;
+    Ok(Chunk { id, data })
+}
+
+impl Blorb {
+    pub fn is_blorb(data: &[u8]) -> bool {
+        data.len() >= 12 && &data[0..4] == b"FORM" && &data[8..12] == b"IFRS"
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Blorb, Error> {
+        if !Blorb::is_blorb(data) {
+            return Err(invalid("not an IFRS FORM"));
+        }
+        let form_len = read_u32(data, 4)? as usize;
+        let end = form_len.saturating_add(8).min(data.len());
+
+        let mut blorb = Blorb::default();
+        let mut index = None;
+
+        //walk the top level chunks, which are padded to even lengths
+        let mut offset = 12;
+        while offset + 8 <= end {
+            let chunk = chunk_at(data, offset)?;
+            match chunk.id {
+                b"RIdx" => index = Some(chunk.data),
+                b"Fspc" => blorb.frontispiece = Some(read_u32(chunk.data, 0)?),
+                b"IFmd" => blorb.metadata = Some(String::from_utf8_lossy(chunk.data).into_owned()),
+                b"RelN" if chunk.data.len() >= 2 => {
+                    blorb.release = Some(u16::from_be_bytes([chunk.data[0], chunk.data[1]]))
+                }
+                b"RDes" => blorb.read_descriptions(chunk.data)?,
+                _ => {}
+            }
+            offset += 8 + chunk.data.len() + (chunk.data.len() & 1);
+        }
+
+        let index = index.ok_or_else(|| invalid("missing RIdx chunk"))?;
+        let count = read_u32(index, 0)? as usize;
+        for i in 0..count {
+            let entry = 4 + i * 12;
+            let usage = index
+                .get(entry..entry + 4)
+                .ok_or_else(|| invalid("truncated RIdx chunk"))?;
+            let number = read_u32(index, entry + 4)?;
+            let start = read_u32(index, entry + 8)? as usize;
+            let usage = match Usage::from_id(usage) {
+                Some(usage) => usage,
+                None => continue,
+            };
+            let chunk = chunk_at(data, start)?;
+            //an AIFF sound is a FORM chunk of its own, kept whole so it can be played as a
+            //file, with its type after the header
+            let (chunk_type, bytes) = match chunk.data.get(0..4) {
+                Some(form_type) if chunk.id == b"FORM" => {
+                    (form_type, &data[start..start + 8 + chunk.data.len()])
+                }
+                _ => (chunk.id, chunk.data),
+            };
+            blorb.resources.insert(
+                (usage, number),
+                Resource {
+                    usage,
+                    number,
+                    chunk_type: String::from_utf8_lossy(chunk_type).into_owned(),
+                    data: bytes.to_vec(),
+                },
+            );
+        }
+        Ok(blorb)
+    }
+
+    fn read_descriptions(&mut self, data: &[u8]) -> Result<(), Error> {
+        let count = read_u32(data, 0)? as usize;
+        let mut offset = 4;
+        for _ in 0..count {
+            let usage = data
+                .get(offset..offset + 4)
+                .ok_or_else(|| invalid("truncated RDes chunk"))?;
+            let number = read_u32(data, offset + 4)?;
+            let len = read_u32(data, offset + 8)? as usize;
+            let text = (offset + 12)
+                .checked_add(len)
+                .and_then(|end| data.get(offset + 12..end))
+                .ok_or_else(|| invalid("truncated RDes chunk"))?;
+            if let Some(usage) = Usage::from_id(usage) {
+                self.descriptions
+                    .insert((usage, number), String::from_utf8_lossy(text).into_owned());
+            }
+            offset += 12 + len;
+        }
+        Ok(())
+    }
+
+    /// The Z-code story file: the `ZCOD` chunk of executable resource 0.
+    pub fn story(&self) -> Result<&[u8], Error> {
+        match self.resources.get(&(Usage::Exec, 0)) {
+            Some(exec) if exec.chunk_type == "ZCOD" => Ok(&exec.data),
+            Some(exec) => Err(Error::InvalidBlorb(format!(
+                "executable is {} rather than Z-code",
+                exec.chunk_type.trim_end()
+            ))),
+            None => Err(invalid("no executable resource")),
+        }
+    }
+
+    pub fn picture(&self, number: u32) -> Option<&Resource> {
+        self.resources.get(&(Usage::Pict, number))
+    }
+
+    pub fn sound(&self, number: u32) -> Option<&Resource> {
+        self.resources.get(&(Usage::Snd, number))
+    }
+
+    /// All resources, ordered by usage then number.
+    pub fn resources(&self) -> impl Iterator<Item = &Resource> {
+        self.resources.values()
+    }
+
+    /// The textual description of a resource for accessibility, from the `RDes` chunk.
+    pub fn description(&self, usage: Usage, number: u32) -> Option<&str> {
+        self.descriptions.get(&(usage, number)).map(|s| s.as_str())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod blorb_tests;
@@ -0,0 +1,106 @@
+use super::*;
+
+// An IFF chunk: its id, length and data, padded to an even length
+pub(crate) fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
+// A Blorb with a story, two pictures and two sounds, indexed by RIdx
+fn create_blorb() -> Vec<u8> {
+    let story = chunk(b"ZCOD", &[5, 0, 0, 1, 0xAB]);
+    let pict1 = chunk(b"PNG ", b"png-data");
+    let pict2 = chunk(b"JPEG", b"jpeg");
+    let snd = chunk(b"OGGV", b"ogg");
+    let aiff = chunk(b"FORM", b"AIFFCOMM");
+
+    let mut descriptions = vec![];
+    descriptions.extend_from_slice(&1u32.to_be_bytes());
+    descriptions.extend_from_slice(b"Pict");
+    descriptions.extend_from_slice(&1u32.to_be_bytes());
+    descriptions.extend_from_slice(&5u32.to_be_bytes());
+    descriptions.extend_from_slice(b"A map");
+
+    let others = [
+        chunk(b"Fspc", &1u32.to_be_bytes()),
+        chunk(b"RelN", &7u16.to_be_bytes()),
+        chunk(b"IFmd", b"<ifindex/>"),
+        chunk(b"RDes", &descriptions),
+    ];
+
+    let index_len = 8 + 4 + 5 * 12;
+    let mut offset = 12 + index_len;
+    let mut entries = vec![];
+    let mut body = vec![];
+    for (usage, number, c) in [
+        (b"Exec", 0u32, &story),
+        (b"Pict", 1, &pict1),
+        (b"Pict", 3, &pict2),
+        (b"Snd ", 3, &snd),
+        (b"Snd ", 4, &aiff),
+    ] {
+        entries.extend_from_slice(usage);
+        entries.extend_from_slice(&number.to_be_bytes());
+        entries.extend_from_slice(&(offset as u32).to_be_bytes());
+        offset += c.len();
+        body.extend_from_slice(c);
+    }
+    let mut index = 5u32.to_be_bytes().to_vec();
+    index.extend_from_slice(&entries);
+
+    let mut form = b"IFRS".to_vec();
+    form.extend_from_slice(&chunk(b"RIdx", &index));
+    form.extend_from_slice(&body);
+    for c in others.iter() {
+        form.extend_from_slice(c);
+    }
+    let mut data = b"FORM".to_vec();
+    data.extend_from_slice(&(form.len() as u32).to_be_bytes());
+    data.extend_from_slice(&form);
+    data
+}
+
+#[test]
+fn test_parse_blorb() {
+    let blorb = Blorb::parse(&create_blorb()).unwrap();
+    assert_eq!(blorb.story().unwrap(), &[5, 0, 0, 1, 0xAB]);
+
+    let pict = blorb.picture(1).unwrap();
+    assert_eq!(pict.chunk_type, "PNG ");
+    assert_eq!(pict.data, b"png-data");
+    assert_eq!(blorb.picture(3).unwrap().chunk_type, "JPEG");
+    assert!(blorb.picture(2).is_none());
+    assert_eq!(blorb.sound(3).unwrap().data, b"ogg");
+    assert_eq!(blorb.resources().count(), 5);
+
+    assert_eq!(blorb.frontispiece, Some(1));
+    assert_eq!(blorb.release, Some(7));
+    assert_eq!(blorb.metadata.as_deref(), Some("<ifindex/>"));
+    assert_eq!(blorb.description(Usage::Pict, 1), Some("A map"));
+}
+
+#[test]
+fn test_aiff_sound() {
+    let blorb = Blorb::parse(&create_blorb()).unwrap();
+    let aiff = blorb.sound(4).unwrap();
+    assert_eq!(aiff.chunk_type, "AIFF");
+    assert_eq!(aiff.data, b"FORM\0\0\0\x08AIFFCOMM");
+}
+
+#[test]
+fn test_not_blorb() {
+    assert!(!Blorb::is_blorb(&[3, 0, 0, 0]));
+    assert!(Blorb::parse(b"FORM\0\0\0\x04AIFF").is_err());
+}
+
+#[test]
+fn test_truncated_blorb() {
+    let mut data = create_blorb();
+    data.truncate(40);
+    assert!(Blorb::parse(&data).is_err());
+}
@@ -0,0 +1,243 @@
+use crate::instruction::{Instruction, OpTable};
+use crate::memory::Memory;
+
+/*
+ * Decoding an instruction without executing it: its opcode, the kinds and raw values of
+ * its operands, and the store variable, branch and inline text which follow them. The
+ * interpreter keeps decoded instructions in a cache keyed by PC, since story code in
+ * high and static memory never changes. Code in dynamic memory can be rewritten by the
+ * game, so decodes of it are only reused while memory is unchanged.
+ */
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum OperandKind {
+    #[default]
+    Large,
+    Small,
+    Variable,
+}
+
+/// Where a branch goes if its condition matches `on_true`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BranchTarget {
+    ReturnFalse,
+    ReturnTrue,
+    Address(u32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Branch {
+    pub on_true: bool,
+    pub target: BranchTarget,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Decoded {
+    pub pc: u32,
+    pub opcode: u8,
+    pub ext: Option<u8>, //the second byte of EXT (0xBE) instructions
+    pub kinds: [OperandKind; 8],
+    pub values: [u16; 8], //constants, or variable numbers for Variable operands
+    pub count: usize,
+    pub operands_end: u32, //address of the store/branch bytes, if any
+    pub store: Option<u8>,
+    pub branch: Option<Branch>,
+    pub text: Option<u32>, //address of the inline string of print and print_ret
+    pub next_pc: u32,      //address of the following instruction
+}
+
+impl Decoded {
+    pub fn operands(&self) -> impl Iterator<Item = (OperandKind, u16)> + '_ {
+        self.kinds[..self.count]
+            .iter()
+            .copied()
+            .zip(self.values[..self.count].iter().copied())
+    }
+}
+
+struct Reader<'a> {
+    mem: &'a Memory,
+    pc: u32,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> u8 {
+        let val = self.mem.get(self.pc as usize).copied().unwrap_or(0);
+        self.pc += 1;
+        val
+    }
+
+    fn u16(&mut self) -> u16 {
+        (self.u8() as u16) << 8 | self.u8() as u16
+    }
+
+    fn operand(&mut self, kind: OperandKind) -> u16 {
+        match kind {
+            OperandKind::Large => self.u16(),
+            _ => self.u8() as u16,
+        }
+    }
+
+    fn tail(&mut self, d: &mut Decoded, instruction: &dyn Instruction, version: u8) {
+        if instruction.stores(version) {
+            d.store = Some(self.u8());
+        }
+        if instruction.branches(version) {
+            let first = self.u8();
+            let offset = if first & 0x40 != 0 {
+                (first & 0x3F) as i16
+            } else {
+                // 14 bit signed
+                let raw = ((first as u16 & 0x3F) << 8) | self.u8() as u16;
+                ((raw << 2) as i16) >> 2
+            };
+            let target = match offset {
+                0 => BranchTarget::ReturnFalse,
+                1 => BranchTarget::ReturnTrue,
+                _ => BranchTarget::Address((self.pc as i64 + offset as i64 - 2) as u32),
+            };
+            d.branch = Some(Branch {
+                on_true: first & 0x80 != 0,
+                target,
+            });
+        }
+        if instruction.inline_text() {
+            d.text = Some(self.pc);
+            // the string ends with the word which has its top bit set
+            while self.u16() & 0x8000 == 0 && (self.pc as usize) < self.mem.len() {}
+        }
+    }
+}
+
+// operand kinds from VAR-style types bytes; `types` holds 8 two-bit fields, left to right
+fn var_kinds(types: u16, out: &mut [OperandKind; 8]) -> usize {
+    for (i, kind) in out.iter_mut().enumerate() {
+        *kind = match (types >> (14 - i * 2)) & 0x03 {
+            0 => OperandKind::Large,
+            1 => OperandKind::Small,
+            2 => OperandKind::Variable,
+            _ => return i,
+        };
+    }
+    8
+}
+
+/// Decodes the instruction at `pc`.
+pub fn decode(mem: &Memory, ops: &OpTable, pc: u32, version: u8) -> Decoded {
+    let mut r = Reader { mem, pc };
+    let mut d = Decoded {
+        pc,
+        opcode: r.u8(),
+        ..Decoded::default()
+    };
+    let op = d.opcode;
+
+    match op >> 4 {
+        // 2OP long form: bit 6 and 5 give the kinds of the two operands
+        0x00..=0x07 => {
+            d.kinds[0] = if op & 0x40 != 0 { OperandKind::Variable } else { OperandKind::Small };
+            d.kinds[1] = if op & 0x20 != 0 { OperandKind::Variable } else { OperandKind::Small };
+            d.count = 2;
+        }
+        0x08..=0x0A => {
+            d.kinds[0] = match op >> 4 {
+                0x08 => OperandKind::Large,
+                0x09 => OperandKind::Small,
+                _ => OperandKind::Variable,
+            };
+            d.count = 1;
+        }
+        0x0B if op == 0xBE && version >= 5 => {
+            d.ext = Some(r.u8());
+            let types = (r.u8() as u16) << 8 | 0xFF;
+            d.count = var_kinds(types, &mut d.kinds);
+        }
+        0x0B => {}
+        _ => {
+            // call_vs2 and call_vn2 have a second types byte, for up to 8 operands
+            let types = if op == 0xEC || op == 0xFA {
+                r.u16()
+            } else {
+                (r.u8() as u16) << 8 | 0xFF
+            };
+            d.count = var_kinds(types, &mut d.kinds);
+        }
+    }
+
+    for i in 0..d.count {
+        d.values[i] = r.operand(d.kinds[i]);
+    }
+    d.operands_end = r.pc;
+
+    // what follows the operands depends on the instruction; illegal ones have nothing
+    if let Some(op) = ops.get(op, d.ext) {
+        r.tail(&mut d, op.instruction, version);
+    }
+    d.next_pc = r.pc;
+    d
+}
+
+/// Decodes what follows the operands of `instruction` at `pc`: the store variable,
+/// branch and inline text it has in `version`.
+pub fn decode_tail(mem: &Memory, pc: u32, instruction: &dyn Instruction, version: u8) -> Decoded {
+    let mut r = Reader { mem, pc };
+    let mut d = Decoded {
+        pc,
+        operands_end: pc,
+        ..Decoded::default()
+    };
+    r.tail(&mut d, instruction, version);
+    d.next_pc = r.pc;
+    d
+}
+
+/// Decoded instructions by PC.
+pub struct InstructionCache {
+    slots: Vec<u32>, //index into entries + 1 for each address, 0 if not decoded
+    entries: Vec<(Decoded, u64)>, //with the memory generation, for code the game can write
+    outside: Decoded,             //the last decode of a PC past the end of the story
+}
+
+impl InstructionCache {
+    pub fn new(mem: &Memory) -> InstructionCache {
+        InstructionCache {
+            slots: vec![0; mem.len()],
+            entries: vec![],
+            outside: Decoded::default(),
+        }
+    }
+
+    /// The instruction at `pc`, decoding it on first use or if memory has been written since
+    /// and the game could have written it: code in dynamic memory, or anywhere when permissive.
+    pub fn get(&mut self, mem: &Memory, ops: &OpTable, pc: u32, version: u8) -> &Decoded {
+        let slot = match self.slots.get(pc as usize) {
+            Some(&slot) if slot > 0 => slot as usize - 1,
+            Some(_) => {
+                self.entries.push((decode(mem, ops, pc, version), mem.generation()));
+                self.slots[pc as usize] = self.entries.len() as u32;
+                self.entries.len() - 1
+            }
+            //outside the story: nothing worth keeping, but decode it for the error
+            None => {
+                self.outside = decode(mem, ops, pc, version);
+                return &self.outside;
+            }
+        };
+
+        let (decoded, generation) = &mut self.entries[slot];
+        let writable = mem.permissive || pc < mem.static_memory() as u32;
+        if *generation != mem.generation() && writable {
+            *decoded = decode(mem, ops, pc, version);
+            *generation = mem.generation();
+        }
+        decoded
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|s| *s = 0);
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod decode_tests;
@@ -0,0 +1,161 @@
+use super::*;
+use crate::zmachine::op_table;
+
+// V5 memory with static memory from 0x200 and `code` at `addr`
+fn create_memory(addr: usize, code: &[u8]) -> Memory {
+    let mut data = vec![0u8; 0x400];
+    data[0x00] = 5;
+    data[0x0E] = 0x02;
+    data[addr..addr + code.len()].copy_from_slice(code);
+    Memory::new(&data)
+}
+
+#[test]
+fn test_long_form_store() {
+    // add G01 #03 -> sp
+    let mem = create_memory(0x300, &[0x54, 0x11, 0x03, 0x00]);
+    let d = decode(&mem, &op_table(5), 0x300, 5);
+    assert_eq!(d.opcode, 0x54);
+    assert_eq!(
+        d.operands().collect::<Vec<_>>(),
+        vec![(OperandKind::Variable, 0x11), (OperandKind::Small, 3)]
+    );
+    assert_eq!(d.operands_end, 0x303);
+    assert_eq!(d.store, Some(0x00));
+    assert_eq!(d.branch, None);
+    assert_eq!(d.next_pc, 0x304);
+}
+
+#[test]
+fn test_branches() {
+    // jz L01 ?~rtrue, then je #1 #2 with a long backwards branch
+    let mem = create_memory(0x300, &[0xA0, 0x01, 0x41, 0x01, 0x01, 0x02, 0xBF, 0xF0]);
+    let d = decode(&mem, &op_table(5), 0x300, 5);
+    assert_eq!(
+        d.branch,
+        Some(Branch {
+            on_true: false,
+            target: BranchTarget::ReturnTrue
+        })
+    );
+    assert_eq!(d.next_pc, 0x303);
+
+    let d = decode(&mem, &op_table(5), 0x303, 5);
+    assert_eq!(
+        d.branch,
+        Some(Branch {
+            on_true: true,
+            target: BranchTarget::Address(0x308 - 16 - 2)
+        })
+    );
+}
+
+#[test]
+fn test_var_form_and_call_vs2() {
+    // call_vs 0x1234 #5 L02 -> G00
+    let mem = create_memory(0x300, &[0xE0, 0x1B, 0x12, 0x34, 0x05, 0x02, 0x10]);
+    let d = decode(&mem, &op_table(5), 0x300, 5);
+    assert_eq!(d.count, 3);
+    assert_eq!(d.values[..3], [0x1234, 5, 2]);
+    assert_eq!(d.store, Some(0x10));
+
+    // call_vs2 with 5 small operands
+    let mem = create_memory(0x300, &[0xEC, 0x55, 0x7F, 1, 2, 3, 4, 5, 0x00]);
+    let d = decode(&mem, &op_table(5), 0x300, 5);
+    assert_eq!(d.count, 5);
+    assert_eq!(d.values[..5], [1, 2, 3, 4, 5]);
+    assert_eq!(d.next_pc, 0x309);
+}
+
+#[test]
+fn test_ext_and_inline_text() {
+    // set_font #4 -> sp
+    let mem = create_memory(0x300, &[0xBE, 0x04, 0x7F, 0x04, 0x00]);
+    let d = decode(&mem, &op_table(5), 0x300, 5);
+    assert_eq!(d.ext, Some(0x04));
+    assert_eq!(d.store, Some(0));
+    assert_eq!(d.next_pc, 0x305);
+
+    // print, with a two word string
+    let mem = create_memory(0x300, &[0xB2, 0x11, 0x22, 0x80, 0x00, 0xBA]);
+    let d = decode(&mem, &op_table(5), 0x300, 5);
+    assert_eq!(d.text, Some(0x301));
+    assert_eq!(d.next_pc, 0x305);
+}
+
+#[test]
+fn test_version_differences() {
+    // save branches in V1-3 and stores in V4
+    let mem = create_memory(0x300, &[0xB5, 0xC5]);
+    let d = decode(&mem, &op_table(3), 0x300, 3);
+    assert_eq!((d.store, d.next_pc), (None, 0x302));
+    assert!(d.branch.is_some());
+    let d = decode(&mem, &op_table(4), 0x300, 4);
+    assert_eq!((d.store, d.branch, d.next_pc), (Some(0xC5), None, 0x302));
+
+    // sread only stores the terminating character from V5
+    let mem = create_memory(0x300, &[0xE4, 0x5F, 0x10, 0x20, 0x00]);
+    assert_eq!(decode(&mem, &op_table(4), 0x300, 4).next_pc, 0x304);
+    assert_eq!(decode(&mem, &op_table(5), 0x300, 5).store, Some(0));
+}
+
+#[test]
+fn test_per_version_tables() {
+    // pop in V4, catch -> sp in V5
+    let mem = create_memory(0x300, &[0xB9, 0x00]);
+    assert_eq!(decode(&mem, &op_table(4), 0x300, 4).next_pc, 0x301);
+    assert_eq!(decode(&mem, &op_table(5), 0x300, 5).store, Some(0));
+
+    // not L01 -> sp in V4, call_1n L01 in V5
+    let mem = create_memory(0x300, &[0xAF, 0x01, 0x00]);
+    assert_eq!(decode(&mem, &op_table(4), 0x300, 4).store, Some(0));
+    assert_eq!(decode(&mem, &op_table(5), 0x300, 5).store, None);
+
+    // 0xBE only starts an EXT instruction in V5+
+    let mem = create_memory(0x300, &[0xBE, 0x04, 0x7F, 0x04, 0x00]);
+    let d = decode(&mem, &op_table(4), 0x300, 4);
+    assert_eq!((d.ext, d.next_pc), (None, 0x301));
+
+    let v3 = op_table(3);
+    assert_eq!(v3.get(0xBC, None).map(|op| op.instruction.name()), Some("SHOW_STATUS"));
+    assert!(v3.get(0x88, None).is_none() && v3.get(0xF8, None).is_none());
+    assert_eq!(op_table(5).get(0xBC, None).map(|op| op.instruction.name()), Some("SHOW_STATUS"));
+    let v5 = op_table(5);
+    assert!(v5.get(0xB5, None).is_none() && v5.get(0xB6, None).is_none());
+    assert_eq!(v5.get(0xF8, None).map(|op| op.instruction.name()), Some("NOT"));
+    assert_eq!(v5.get(0xBE, Some(0x00)).map(|op| op.instruction.name()), Some("SAVE"));
+}
+
+#[test]
+fn test_illegal_opcode_has_no_tail() {
+    let mem = create_memory(0x300, &[0x1F, 0x01, 0x02, 0x00]);
+    let d = decode(&mem, &op_table(5), 0x300, 5);
+    assert_eq!((d.store, d.branch, d.next_pc), (None, None, 0x303));
+}
+
+#[test]
+fn test_cache_static_code() {
+    let mut mem = create_memory(0x300, &[0xB0]);
+    let ops = op_table(5);
+    let mut cache = InstructionCache::new(&mem);
+    assert_eq!(cache.get(&mem, &ops, 0x300, 5).opcode, 0xB0);
+    // Code outside dynamic memory is never decoded again
+    mem.write_u8(0x300, 0xB1);
+    assert_eq!(cache.get(&mem, &ops, 0x300, 5).opcode, 0xB0);
+
+    // Unless a permissive game may have written it
+    mem.permissive = true;
+    mem.checked_write_u8(0x300, 0xB1).unwrap();
+    assert_eq!(cache.get(&mem, &ops, 0x300, 5).opcode, 0xB1);
+}
+
+#[test]
+fn test_cache_dynamic_code() {
+    let mut mem = create_memory(0x100, &[0xB0]);
+    let ops = op_table(5);
+    let mut cache = InstructionCache::new(&mem);
+    assert_eq!(cache.get(&mem, &ops, 0x100, 5).opcode, 0xB0);
+    assert_eq!(cache.get(&mem, &ops, 0x100, 5).opcode, 0xB0);
+    mem.write_u8(0x100, 0xB1);
+    assert_eq!(cache.get(&mem, &ops, 0x100, 5).opcode, 0xB1);
+}
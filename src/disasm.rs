@@ -0,0 +1,282 @@
+use crate::decode::{decode, BranchTarget, Decoded, OperandKind};
+use crate::error::Error;
+use crate::instruction::OpTable;
+use crate::memory::Memory;
+use crate::zmachine::op_table;
+use crate::zscii::Zscii;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/*
+ * Reading the code a story runs, without running it. Instructions are decoded the same
+ * way the interpreter decodes them, with the dispatch table for the story's version
+ * giving mnemonics and what follows each instruction's operands. Routines are found by
+ * starting from the main routine and following every call to a constant address, then
+ * listed in the style of txd: variables as sp, L00-L0E and G00-GEF, constants as #nn,
+ * call targets as unpacked addresses and branches as [TRUE]/[FALSE] and their target.
+ */
+
+//mnemonics of the instructions which never fall through to the next one
+const TERMINATORS: [&str; 8] = [
+    "RET", "RTRUE", "RFALSE", "RET_POPPED", "PRINT_RET", "JUMP", "QUIT", "RESTART",
+];
+
+/// One decoded instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u32,
+    pub mnemonic: String,
+    pub operands: String, //operands, store, branch and inline text
+    pub next_pc: u32,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.operands.is_empty() {
+            write!(f, "{:05X}:  {}", self.addr, self.mnemonic)
+        } else {
+            write!(f, "{:05X}:  {:<16}{}", self.addr, self.mnemonic, self.operands)
+        }
+    }
+}
+
+/// A routine: its header, if it has one, and its code up to the last instruction which
+/// can be reached by falling through or branching forwards.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Routine {
+    pub addr: u32,
+    pub main: bool,       //the main routine before V6 has no header
+    pub locals: Vec<u16>, //initial values, which are all 0 from V5
+    pub lines: Vec<Line>,
+    pub calls: Vec<u32>, //addresses of the routines it calls
+}
+
+impl fmt::Display for Routine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let locals: Vec<String> = self.locals.iter().map(|l| format!("{:04X}", l)).collect();
+        let kind = if self.main { "Main routine" } else { "Routine" };
+        writeln!(
+            f,
+            "{} {:05X}, {} locals ({})",
+            kind,
+            self.addr,
+            self.locals.len(),
+            locals.join(", ")
+        )?;
+        writeln!(f)?;
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Disassembler {
+    mem: Memory,
+    ops: OpTable,
+    version: u8,
+}
+
+impl Disassembler {
+    /// Fails if the story is too short to have a header.
+    pub fn new(story: &[u8]) -> Result<Disassembler, Error> {
+        Memory::check_header(story)?;
+        let mem = Memory::new(story);
+        let version = mem.zmachine_version();
+        Ok(Disassembler {
+            ops: op_table(version),
+            mem,
+            version,
+        })
+    }
+
+    /// Decodes the instruction at `addr`.
+    pub fn instruction(&self, addr: u32) -> Line {
+        self.line(&decode(&self.mem, &self.ops, addr, self.version))
+    }
+
+    fn line(&self, d: &Decoded) -> Line {
+        let mnemonic = match (self.ops.get(d.opcode, d.ext), d.ext) {
+            (Some(op), _) => op.instruction.name().to_string(),
+            (None, Some(ext)) => format!("ILLEGAL_EXT_{:02X}", ext),
+            (None, None) => format!("ILLEGAL_{:02X}", d.opcode),
+        };
+        Line {
+            addr: d.pc,
+            operands: self.operands(d, &mnemonic),
+            mnemonic,
+            next_pc: d.next_pc,
+        }
+    }
+
+    fn operands(&self, d: &Decoded, mnemonic: &str) -> String {
+        let parts: Vec<String> = d
+            .operands()
+            .enumerate()
+            .map(|(i, (kind, value))| match kind {
+                OperandKind::Variable => variable(value as u8),
+                //where calls and jumps go, rather than the packed address or offset
+                OperandKind::Large if i == 0 && is_call(mnemonic) => {
+                    format!("{:05X}", self.mem.unpack_routine(value))
+                }
+                OperandKind::Large if mnemonic == "JUMP" => format!("{:05X}", jump_target(d)),
+                OperandKind::Large => format!("#{:04X}", value),
+                OperandKind::Small => format!("#{:02X}", value),
+            })
+            .collect();
+
+        let mut text = parts.join(",");
+        if let Some(var) = d.store {
+            text.push_str(&format!(" -> {}", variable(var)));
+        }
+        if let Some(branch) = d.branch {
+            let target = match branch.target {
+                BranchTarget::ReturnFalse => "RFALSE".to_string(),
+                BranchTarget::ReturnTrue => "RTRUE".to_string(),
+                BranchTarget::Address(addr) => format!("{:05X}", addr),
+            };
+            text.push_str(&format!(" [{}] {}", if branch.on_true { "TRUE" } else { "FALSE" }, target));
+        }
+        if let Some(addr) = d.text {
+            //a string running off the end of the story can't be shown
+            let last = self.mem.get(d.next_pc as usize - 2);
+            if (d.next_pc as usize) <= self.mem.len() && last.is_some_and(|b| b & 0x80 != 0) {
+                if let Ok(s) = Zscii::new(&self.mem).get_string(addr) {
+                    text.push_str(&format!("\"{}\"", s.replace('\r', "\n")));
+                }
+            }
+        }
+        text.trim_start().to_string()
+    }
+
+    /// Decodes the routine with its header at `addr`. Decoding stops after an instruction
+    /// which doesn't fall through once nothing has branched past it, or at an illegal one.
+    pub fn routine(&self, addr: u32) -> Routine {
+        let count = self.mem.get(addr as usize).copied().unwrap_or(0).min(15) as u32;
+        let locals = if self.version <= 4 {
+            (0..count)
+                .map(|i| {
+                    let at = (addr + 1 + i * 2) as usize;
+                    match self.mem.get(at..at + 2) {
+                        Some(b) => (b[0] as u16) << 8 | b[1] as u16,
+                        None => 0,
+                    }
+                })
+                .collect()
+        } else {
+            vec![0; count as usize]
+        };
+        let start = addr + 1 + if self.version <= 4 { count * 2 } else { 0 };
+        let mut routine = self.code(start);
+        routine.addr = addr;
+        routine.locals = locals;
+        routine
+    }
+
+    //the main routine before V6 starts straight at the initial PC
+    fn main(&self) -> Routine {
+        let pc = self.mem.initial_pc();
+        if self.version == 6 {
+            let mut routine = self.routine(self.mem.unpack_routine(pc));
+            routine.main = true;
+            return routine;
+        }
+        let mut routine = self.code(pc as u32);
+        routine.main = true;
+        routine
+    }
+
+    fn code(&self, start: u32) -> Routine {
+        let mut lines = vec![];
+        let mut calls = vec![];
+        let mut furthest = start; //the furthest forward branch target so far
+        let mut pc = start;
+        while (pc as usize) < self.mem.len() {
+            let d = decode(&self.mem, &self.ops, pc, self.version);
+            let line = self.line(&d);
+            pc = line.next_pc;
+
+            if line.mnemonic.starts_with("ILLEGAL") {
+                lines.push(line);
+                break;
+            }
+            if is_call(&line.mnemonic) {
+                if let Some((OperandKind::Large, packed)) = d.operands().next() {
+                    if packed != 0 {
+                        calls.push(self.mem.unpack_routine(packed));
+                    }
+                }
+            }
+            if let Some(branch) = d.branch {
+                if let BranchTarget::Address(target) = branch.target {
+                    furthest = furthest.max(target);
+                }
+            }
+            if line.mnemonic == "JUMP" && d.kinds[0] != OperandKind::Variable {
+                furthest = furthest.max(jump_target(&d));
+            }
+
+            let ends = TERMINATORS.contains(&line.mnemonic.as_str());
+            lines.push(line);
+            if ends && pc > furthest {
+                break;
+            }
+        }
+        Routine {
+            addr: start,
+            main: false,
+            locals: vec![],
+            lines,
+            calls,
+        }
+    }
+
+    /// The main routine and every routine reachable from it through calls to constant
+    /// addresses, in address order.
+    pub fn routines(&self) -> Vec<Routine> {
+        let main = self.main();
+        let mut seen = BTreeSet::new();
+        let mut queue = main.calls.clone();
+        let mut routines = vec![main];
+        while let Some(addr) = queue.pop() {
+            if (addr as usize) >= self.mem.len() || !seen.insert(addr) {
+                continue;
+            }
+            let routine = self.routine(addr);
+            queue.extend(&routine.calls);
+            routines.push(routine);
+        }
+        routines.sort_by_key(|r| r.addr);
+        routines
+    }
+
+    /// A txd-style listing of every routine found.
+    pub fn listing(&self) -> String {
+        self.routines()
+            .iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn is_call(mnemonic: &str) -> bool {
+    matches!(mnemonic, "CALL" | "CALL_N")
+}
+
+//jump's operand is an offset from after the instruction, less 2
+fn jump_target(d: &Decoded) -> u32 {
+    (d.next_pc as i64 + d.values[0] as i16 as i64 - 2) as u32
+}
+
+//variable names as txd shows them
+fn variable(var: u8) -> String {
+    match var {
+        0 => "sp".to_string(),
+        1..=15 => format!("L{:02X}", var - 1),
+        _ => format!("G{:02X}", var - 16),
+    }
+}
+
+#[cfg(test)]
+mod disasm_tests;
@@ -0,0 +1,70 @@
+use super::*;
+
+// V5 story whose main routine at 0x100 calls the routine at 0x200, prints and quits
+fn create_story() -> Vec<u8> {
+    let mut data = vec![0u8; 0x400];
+    data[0x00] = 5;
+    data[0x06] = 0x01; // PC at 0x100
+    data[0x0C] = 0x03; // Globals at 0x300
+    data[0x0E] = 0x03; // Static memory at 0x300
+    // call_vs 0x200 #05 -> sp; print "hi"; quit
+    data[0x100..0x10A].copy_from_slice(&[0xE0, 0x1F, 0x00, 0x80, 0x05, 0x00, 0xB2, 0xB5, 0xC5, 0xBA]);
+    // one local; je L00 #05 ?~rfalse; add L00 #01 -> G00; jz L00 ?0x20D; rtrue; rfalse
+    data[0x200..0x20E].copy_from_slice(&[
+        0x01, 0x41, 0x01, 0x05, 0x40, 0x54, 0x01, 0x01, 0x10, 0xA0, 0x01, 0xC3, 0xB0, 0xB1,
+    ]);
+    data
+}
+
+#[test]
+fn test_instruction() {
+    let disasm = Disassembler::new(&create_story()).unwrap();
+    let line = disasm.instruction(0x100);
+    assert_eq!(line.mnemonic, "CALL");
+    assert_eq!(line.operands, "00200,#05 -> sp");
+    assert_eq!(line.next_pc, 0x106);
+    assert_eq!(disasm.instruction(0x106).to_string(), "00106:  PRINT           \"hi\"");
+    assert_eq!(disasm.instruction(0x109).to_string(), "00109:  QUIT");
+    assert_eq!(disasm.instruction(0x201).operands, "L00,#05 [FALSE] RFALSE");
+    assert_eq!(disasm.instruction(0x205).operands, "L00,#01 -> G00");
+    assert_eq!(disasm.instruction(0x209).operands, "L00 [TRUE] 0020D");
+}
+
+#[test]
+fn test_illegal_instruction() {
+    let mut story = create_story();
+    story[0x100] = 0xB5; // save is EXT only in V5
+    let disasm = Disassembler::new(&story).unwrap();
+    assert_eq!(disasm.instruction(0x100).mnemonic, "ILLEGAL_B5");
+    let main = &disasm.routines()[0];
+    assert_eq!(main.lines.len(), 1);
+}
+
+#[test]
+fn test_routine_ends_after_forward_branches() {
+    let disasm = Disassembler::new(&create_story()).unwrap();
+    let routine = disasm.routine(0x200);
+    assert_eq!(routine.locals, vec![0]);
+    // rtrue doesn't end it, as jz branches past it
+    let addrs: Vec<u32> = routine.lines.iter().map(|l| l.addr).collect();
+    assert_eq!(addrs, vec![0x201, 0x205, 0x209, 0x20C, 0x20D]);
+}
+
+#[test]
+fn test_routine_discovery_and_listing() {
+    let disasm = Disassembler::new(&create_story()).unwrap();
+    let routines = disasm.routines();
+    assert_eq!(routines.len(), 2);
+    assert!(routines[0].main);
+    assert_eq!(routines[0].calls, vec![0x200]);
+
+    let listing = disasm.listing();
+    assert!(listing.starts_with("Main routine 00100, 0 locals ()\n\n00100:  CALL "));
+    assert!(listing.contains("\nRoutine 00200, 1 locals (0000)\n\n00201:  JE "));
+    assert!(listing.ends_with("0020D:  RFALSE\n"));
+}
+
+#[test]
+fn test_story_without_header() {
+    assert!(Disassembler::new(&[5; 4]).is_err());
+}
@@ -1,7 +1,130 @@
+use std::fmt;
 use thiserror::Error;
 
+/// Where the machine was when an error happened: the address and first byte of the
+/// faulting instruction, and the addresses of the routines on the call stack, innermost
+/// first. Filled in by the interpreter loop, so errors raised outside it have an empty one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Context {
+    pub pc: u32,
+    pub opcode: u8,
+    pub backtrace: Vec<u32>,
+}
+
+impl Context {
+    pub fn is_empty(&self) -> bool {
+        *self == Context::default()
+    }
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return Ok(());
+        }
+        write!(f, " at PC 0x{:05X} (opcode 0x{:02X})", self.pc, self.opcode)?;
+        if !self.backtrace.is_empty() {
+            write!(f, ", call stack:")?;
+            for routine in &self.backtrace {
+                write!(f, " 0x{:05X}", routine)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Error, Debug, Clone)]
 pub enum Error {
-    #[error("Z-Machine internal error.")]
-    ZMachineError(String)
-}
\ No newline at end of file
+    /// An opcode the story's version doesn't have. For EXT instructions, `opcode` is the
+    /// second byte.
+    #[error("Illegal opcode 0x{opcode:02X} for version {version}{context}")]
+    IllegalOpcode {
+        opcode: u8,
+        version: u8,
+        context: Context,
+    },
+    #[error("Stack underflow{context}")]
+    StackUnderflow { context: Context },
+    #[error("Stack overflow{context}")]
+    StackOverflow { context: Context },
+    #[error("Division by zero{context}")]
+    DivisionByZero { context: Context },
+    #[error("Bad object {object}{context}")]
+    BadObject { object: u16, context: Context },
+    /// An attribute past the last one the version has: 31 in V1-3, 47 from V4.
+    #[error("Bad attribute {attr}{context}")]
+    BadAttribute { attr: u16, context: Context },
+    #[error("Illegal memory access at 0x{addr:04X}: {reason}{context}")]
+    OutOfBounds {
+        addr: u32,
+        reason: String,
+        context: Context,
+    },
+    #[error("Bad variable 0x{var:02X}{context}")]
+    BadVariable { var: u8, context: Context },
+    #[error("Unsupported Z-machine version {version}{context}")]
+    UnsupportedVersion { version: u8, context: Context },
+    /// Anything else the game asked for which can't be done, eg. an illegal window.
+    #[error("{message}{context}")]
+    Illegal { message: String, context: Context },
+    #[error("Invalid Blorb file: {0}")]
+    InvalidBlorb(String),
+}
+
+impl Error {
+    pub fn illegal(message: impl Into<String>) -> Error {
+        Error::Illegal {
+            message: message.into(),
+            context: Context::default(),
+        }
+    }
+
+    pub fn out_of_bounds(addr: u32, reason: impl Into<String>) -> Error {
+        Error::OutOfBounds {
+            addr,
+            reason: reason.into(),
+            context: Context::default(),
+        }
+    }
+
+    /// Where the machine was when the error happened, for errors raised while running.
+    pub fn context(&self) -> Option<&Context> {
+        match self {
+            Error::IllegalOpcode { context, .. }
+            | Error::StackUnderflow { context }
+            | Error::StackOverflow { context }
+            | Error::DivisionByZero { context }
+            | Error::BadObject { context, .. }
+            | Error::BadAttribute { context, .. }
+            | Error::OutOfBounds { context, .. }
+            | Error::BadVariable { context, .. }
+            | Error::UnsupportedVersion { context, .. }
+            | Error::Illegal { context, .. } => Some(context),
+            Error::InvalidBlorb(_) => None,
+        }
+    }
+
+    //attaches the context, unless a more specific one is already there
+    pub(crate) fn with_context(mut self, new: Context) -> Error {
+        let slot = match &mut self {
+            Error::IllegalOpcode { context, .. }
+            | Error::StackUnderflow { context }
+            | Error::StackOverflow { context }
+            | Error::DivisionByZero { context }
+            | Error::BadObject { context, .. }
+            | Error::BadAttribute { context, .. }
+            | Error::OutOfBounds { context, .. }
+            | Error::BadVariable { context, .. }
+            | Error::UnsupportedVersion { context, .. }
+            | Error::Illegal { context, .. } => context,
+            Error::InvalidBlorb(_) => return self,
+        };
+        if slot.is_empty() {
+            *slot = new;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod error_tests;
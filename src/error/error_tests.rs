@@ -0,0 +1,131 @@
+use super::*;
+use crate::zscreen::ZScreen;
+use crate::ZMachine;
+
+struct NullScreen;
+
+impl ZScreen for NullScreen {
+    fn newline(&self) {}
+    fn print(&self, _str: String) {}
+    fn read(&self) -> char { ' ' }
+    fn readline(&self) -> String { String::new() }
+    fn exit(&self) {}
+    fn set_status(&self, _status: String) {}
+    fn get_width(&self) {}
+    fn get_height(&self) {}
+    fn restart(&self) {}
+    fn save(&self, _state: Vec<u8>) {}
+    fn restore(&self) -> Vec<u8> { vec![] }
+    fn set_window(&self, _num: u16) {}
+    fn split_window(&self, _height: u16) {}
+    fn erase_window(&self, _num: u16) {}
+    fn move_cursor(&self, _x: u8, _y: u8) {}
+    fn print_number(&self, _num: u16) {}
+    fn print_char(&self, _char: char) {}
+}
+
+// V5 story which calls the routine at 0x200, which divides by zero
+fn create_story() -> Vec<u8> {
+    let mut data = vec![0u8; 1024];
+    data[0x00] = 5;
+    data[0x06] = 0x01; // PC at 0x100
+    data[0x0C] = 0x03; // Globals at 0x300
+    data[0x0E] = 0x04; // Static memory at 0x400
+    // call_vs 0x200 -> G00
+    data[0x100..0x105].copy_from_slice(&[0xE0, 0x3F, 0x00, 0x80, 0x10]);
+    // no locals; div 1 0 -> G00
+    data[0x200..0x205].copy_from_slice(&[0x00, 0x17, 0x01, 0x00, 0x10]);
+    data
+}
+
+#[test]
+fn test_runtime_error_context() {
+    let story = create_story();
+    let mut machine = ZMachine::new(&story, Box::new(NullScreen)).unwrap();
+    let err = machine.run().unwrap_err();
+    assert!(matches!(err, Error::DivisionByZero { .. }));
+
+    let context = err.context().unwrap();
+    assert_eq!(context.pc, 0x201);
+    assert_eq!(context.opcode, 0x17);
+    assert_eq!(context.backtrace, vec![0x200, 0x100]);
+    assert_eq!(
+        err.to_string(),
+        "Division by zero at PC 0x00201 (opcode 0x17), call stack: 0x00200 0x00100"
+    );
+}
+
+#[test]
+fn test_wrong_operand_count() {
+    let mut story = create_story();
+    // add #1 -> G00, with the second operand missing
+    story[0x100..0x104].copy_from_slice(&[0xD4, 0x7F, 0x01, 0x10]);
+    let mut machine = ZMachine::new(&story, Box::new(NullScreen)).unwrap();
+    let err = machine.run().unwrap_err();
+    assert!(matches!(err, Error::Illegal { .. }));
+    assert_eq!(err.to_string(), "ADD given 1 operands at PC 0x00100 (opcode 0xD4), call stack: 0x00100");
+}
+
+#[test]
+fn test_illegal_opcode_for_version() {
+    let mut story = create_story();
+    // save is an EXT instruction from V5
+    story[0x100] = 0xB5;
+    let mut machine = ZMachine::new(&story, Box::new(NullScreen)).unwrap();
+    let err = machine.run().unwrap_err();
+    assert!(matches!(err, Error::IllegalOpcode { opcode: 0xB5, version: 5, .. }));
+    assert_eq!(err.to_string(), "Illegal opcode 0xB5 for version 5 at PC 0x00100 (opcode 0xB5), call stack: 0x00100");
+}
+
+#[test]
+fn test_unsupported_version() {
+    let mut story = create_story();
+    story[0x00] = 9;
+    let mut machine = ZMachine::new(&story, Box::new(NullScreen)).unwrap();
+    assert!(matches!(
+        machine.run(),
+        Err(Error::UnsupportedVersion { version: 9, .. })
+    ));
+}
+
+#[test]
+fn test_story_too_short() {
+    assert!(matches!(
+        ZMachine::new(&[3u8; 10], Box::new(NullScreen)),
+        Err(Error::OutOfBounds { addr: 10, .. })
+    ));
+}
+
+#[test]
+fn test_context_kept_once_set() {
+    let first = Context {
+        pc: 0x123,
+        opcode: 0xB0,
+        backtrace: vec![],
+    };
+    let err = Error::illegal("Illegal window 9").with_context(first.clone());
+    let err = err.with_context(Context {
+        pc: 0x456,
+        ..Context::default()
+    });
+    assert_eq!(err.context(), Some(&first));
+    assert_eq!(err.to_string(), "Illegal window 9 at PC 0x00123 (opcode 0xB0)");
+}
+
+#[test]
+fn test_display_without_context() {
+    assert_eq!(
+        Error::out_of_bounds(0x1234, "write outside dynamic memory").to_string(),
+        "Illegal memory access at 0x1234: write outside dynamic memory"
+    );
+    assert_eq!(
+        Error::IllegalOpcode {
+            opcode: 0xBE,
+            version: 4,
+            context: Context::default()
+        }
+        .to_string(),
+        "Illegal opcode 0xBE for version 4"
+    );
+    assert!(Error::InvalidBlorb("bad".to_string()).context().is_none());
+}
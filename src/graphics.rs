@@ -0,0 +1,280 @@
+use crate::blorb::{Blorb, Resource};
+use crate::error::Error;
+use crate::window::{Window, WINDOW_COUNT};
+use std::collections::HashMap;
+
+/*
+ * Software rendering for the V6 picture opcodes. Pictures from the Blorb file are
+ * decoded to RGBA and composited into an in-memory framebuffer per window, so a
+ * frontend only has to blit finished buffers - and tests can inspect frames without
+ * any display at all.
+ */
+
+/// A decoded picture, 8 bit RGBA, row major.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<u8>,
+}
+
+fn image_error(number: u32, err: impl std::fmt::Display) -> Error {
+    Error::illegal(format!("Unable to decode picture {}: {}", number, err))
+}
+
+impl Image {
+    /// Decodes a `PNG ` or `JPEG` picture resource.
+    pub fn decode(resource: &Resource) -> Result<Image, Error> {
+        match resource.chunk_type.as_str() {
+            "PNG " => Image::decode_png(resource),
+            "JPEG" => Image::decode_jpeg(resource),
+            other => Err(image_error(resource.number, format!("unsupported format {}", other))),
+        }
+    }
+
+    fn decode_png(resource: &Resource) -> Result<Image, Error> {
+        let mut decoder = png::Decoder::new(resource.data.as_slice());
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| image_error(resource.number, e))?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buf)
+            .map_err(|e| image_error(resource.number, e))?;
+        buf.truncate(info.buffer_size());
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buf,
+            png::ColorType::Rgb => rgba_from(&buf, 3, |p| [p[0], p[1], p[2], 255]),
+            png::ColorType::GrayscaleAlpha => rgba_from(&buf, 2, |p| [p[0], p[0], p[0], p[1]]),
+            png::ColorType::Grayscale => rgba_from(&buf, 1, |p| [p[0], p[0], p[0], 255]),
+            png::ColorType::Indexed => {
+                return Err(image_error(resource.number, "unexpanded palette"));
+            }
+        };
+        Ok(Image {
+            width: info.width as u16,
+            height: info.height as u16,
+            pixels,
+        })
+    }
+
+    fn decode_jpeg(resource: &Resource) -> Result<Image, Error> {
+        let mut decoder = jpeg_decoder::Decoder::new(resource.data.as_slice());
+        let buf = decoder
+            .decode()
+            .map_err(|e| image_error(resource.number, e))?;
+        let info = decoder
+            .info()
+            .ok_or_else(|| image_error(resource.number, "missing header"))?;
+
+        let pixels = match info.pixel_format {
+            jpeg_decoder::PixelFormat::RGB24 => rgba_from(&buf, 3, |p| [p[0], p[1], p[2], 255]),
+            jpeg_decoder::PixelFormat::L8 => rgba_from(&buf, 1, |p| [p[0], p[0], p[0], 255]),
+            jpeg_decoder::PixelFormat::L16 => rgba_from(&buf, 2, |p| [p[0], p[0], p[0], 255]),
+            jpeg_decoder::PixelFormat::CMYK32 => rgba_from(&buf, 4, |p| {
+                let k = 255 - p[3] as u16;
+                let c = |v: u8| ((255 - v as u16) * k / 255) as u8;
+                [c(p[0]), c(p[1]), c(p[2]), 255]
+            }),
+        };
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+fn rgba_from(buf: &[u8], channels: usize, f: impl Fn(&[u8]) -> [u8; 4]) -> Vec<u8> {
+    buf.chunks_exact(channels).flat_map(f).collect()
+}
+
+/// An RGBA pixel buffer the size of a window. Coordinates are 1-based, as in the game.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Framebuffer {
+    width: u16,
+    height: u16,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u16, height: u16, background: [u8; 4]) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: background.repeat(width as usize * height as usize),
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// The whole buffer, 8 bit RGBA, row major.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, y: u16, x: u16) -> Option<[u8; 4]> {
+        let i = self.index(y, x)?;
+        Some([
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ])
+    }
+
+    fn index(&self, y: u16, x: u16) -> Option<usize> {
+        if y == 0 || x == 0 || y > self.height || x > self.width {
+            None
+        } else {
+            Some(((y as usize - 1) * self.width as usize + x as usize - 1) * 4)
+        }
+    }
+
+    /// Composites `image` with its top left corner at (y, x), clipping to the buffer.
+    pub fn draw(&mut self, image: &Image, y: u16, x: u16) {
+        for row in 0..image.height {
+            for col in 0..image.width {
+                let dest = match self.index(y.saturating_add(row), x.saturating_add(col)) {
+                    Some(i) => i,
+                    None => continue,
+                };
+                let src = (row as usize * image.width as usize + col as usize) * 4;
+                let alpha = image.pixels[src + 3] as u16;
+                for c in 0..3 {
+                    let s = image.pixels[src + c] as u16;
+                    let d = self.pixels[dest + c] as u16;
+                    self.pixels[dest + c] = ((s * alpha + d * (255 - alpha)) / 255) as u8;
+                }
+                self.pixels[dest + 3] = 255;
+            }
+        }
+    }
+
+    /// Fills a rectangle with (y, x) as its top left corner, clipping to the buffer.
+    pub fn fill(&mut self, y: u16, x: u16, height: u16, width: u16, colour: [u8; 4]) {
+        for row in y..y.saturating_add(height) {
+            for col in x..x.saturating_add(width) {
+                if let Some(i) = self.index(row, col) {
+                    self.pixels[i..i + 4].copy_from_slice(&colour);
+                }
+            }
+        }
+    }
+}
+
+const WHITE: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+/// Decoded pictures and the framebuffers of each window.
+pub struct Graphics {
+    images: HashMap<u32, Image>,
+    framebuffers: Vec<Framebuffer>,
+}
+
+impl Default for Graphics {
+    fn default() -> Graphics {
+        Graphics::new()
+    }
+}
+
+impl Graphics {
+    pub fn new() -> Graphics {
+        Graphics {
+            images: HashMap::new(),
+            framebuffers: vec![Framebuffer::default(); WINDOW_COUNT],
+        }
+    }
+
+    //decoded picture `number`, decoding it on first use. None if there's no such picture
+    pub fn image(&mut self, blorb: Option<&Blorb>, number: u32) -> Result<Option<&Image>, Error> {
+        if let std::collections::hash_map::Entry::Vacant(entry) = self.images.entry(number) {
+            match blorb.and_then(|b| b.picture(number)) {
+                Some(resource) => {
+                    entry.insert(Image::decode(resource)?);
+                }
+                None => return Ok(None),
+            }
+        }
+        Ok(self.images.get(&number))
+    }
+
+    //picture `number` if it has been decoded already, without decoding it
+    pub fn decoded(&self, number: u32) -> Option<&Image> {
+        self.images.get(&number)
+    }
+
+    pub fn framebuffer(&self, num: usize) -> Option<&Framebuffer> {
+        self.framebuffers.get(num)
+    }
+
+    //the window's framebuffer, made on first use and remade if the window has changed size.
+    //windows can be sized beyond the screen, (height, width) in `screen`, but buffers can't
+    pub fn framebuffer_mut(&mut self, num: usize, window: &Window, screen: (u16, u16)) -> &mut Framebuffer {
+        let fb = &mut self.framebuffers[num];
+        fit(fb, window, screen);
+        fb
+    }
+
+    //draws decoded picture `number` with its top left corner at (y, x), giving the updated
+    //framebuffer. None if the picture hasn't been decoded
+    pub fn draw(
+        &mut self,
+        number: u32,
+        num: usize,
+        window: &Window,
+        screen: (u16, u16),
+        (y, x): (u16, u16),
+    ) -> Option<&Framebuffer> {
+        let image = self.images.get(&number)?;
+        let fb = &mut self.framebuffers[num];
+        fit(fb, window, screen);
+        fb.draw(image, y, x);
+        Some(fb)
+    }
+
+    //an erased window has no buffer until something is drawn in it again
+    pub fn erase(&mut self, num: usize) {
+        self.framebuffers[num] = Framebuffer::default();
+    }
+}
+
+fn fit(fb: &mut Framebuffer, window: &Window, screen: (u16, u16)) {
+    let (height, width) = (window.height.min(screen.0), window.width.min(screen.1));
+    if fb.width != width || fb.height != height {
+        *fb = Framebuffer::new(width, height, background(window));
+    }
+}
+
+//the window's background: the high byte of its colour data, white by default
+pub fn background(window: &Window) -> [u8; 4] {
+    standard_colour((window.colour >> 8) as u8).unwrap_or(WHITE)
+}
+
+/// RGBA for one of the standard colours of 8.3.1 (2 = black ... 12 = dark grey).
+pub fn standard_colour(colour: u8) -> Option<[u8; 4]> {
+    Some(match colour {
+        2 => [0, 0, 0, 255],
+        3 => [0xef, 0, 0, 255],
+        4 => [0, 0xd6, 0, 255],
+        5 => [0xef, 0xef, 0, 255],
+        6 => [0, 0x6b, 0xb5, 255],
+        7 => [0xff, 0, 0xff, 255],
+        8 => [0, 0xef, 0xef, 255],
+        9 => [0xff, 0xff, 0xff, 255],
+        10 => [0xce, 0xce, 0xce, 255],
+        11 => [0x88, 0x88, 0x88, 255],
+        12 => [0x44, 0x44, 0x44, 255],
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod graphics_tests;
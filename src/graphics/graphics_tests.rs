@@ -0,0 +1,137 @@
+use super::*;
+use crate::blorb::Usage;
+
+const RED: [u8; 4] = [255, 0, 0, 255];
+const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+fn encode_png(width: u32, height: u32, colour: png::ColorType, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(colour);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+    }
+    bytes
+}
+
+fn resource(chunk_type: &str, data: Vec<u8>) -> Resource {
+    Resource {
+        usage: Usage::Pict,
+        number: 1,
+        chunk_type: chunk_type.to_string(),
+        data,
+    }
+}
+
+fn solid(width: u16, height: u16, colour: [u8; 4]) -> Image {
+    Image {
+        width,
+        height,
+        pixels: colour.repeat(width as usize * height as usize),
+    }
+}
+
+#[test]
+fn test_decode_png_rgba() {
+    let data = [RED, BLUE].concat();
+    let image = Image::decode(&resource("PNG ", encode_png(2, 1, png::ColorType::Rgba, &data))).unwrap();
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(image.pixels, data);
+}
+
+#[test]
+fn test_decode_png_rgb_and_grey() {
+    let rgb = encode_png(1, 1, png::ColorType::Rgb, &[1, 2, 3]);
+    assert_eq!(Image::decode(&resource("PNG ", rgb)).unwrap().pixels, vec![1, 2, 3, 255]);
+
+    let grey = encode_png(1, 1, png::ColorType::Grayscale, &[9]);
+    assert_eq!(Image::decode(&resource("PNG ", grey)).unwrap().pixels, vec![9, 9, 9, 255]);
+}
+
+#[test]
+fn test_decode_errors() {
+    assert!(Image::decode(&resource("PNG ", b"garbage".to_vec())).is_err());
+    assert!(Image::decode(&resource("JPEG", b"garbage".to_vec())).is_err());
+    assert!(Image::decode(&resource("Rect", vec![])).is_err());
+}
+
+#[test]
+fn test_draw_and_clip() {
+    let mut fb = Framebuffer::new(4, 3, WHITE);
+    assert_eq!(fb.pixels().len(), 4 * 3 * 4);
+
+    // 2x2 picture hanging off the bottom right corner
+    fb.draw(&solid(2, 2, RED), 3, 4);
+    assert_eq!(fb.pixel(3, 4), Some(RED));
+    assert_eq!(fb.pixel(3, 3), Some(WHITE));
+    assert_eq!(fb.pixel(2, 4), Some(WHITE));
+    assert_eq!(fb.pixel(4, 4), None);
+    assert_eq!(fb.pixel(0, 1), None);
+}
+
+#[test]
+fn test_alpha_compositing() {
+    let mut fb = Framebuffer::new(2, 1, BLUE);
+    let mut image = solid(2, 1, RED);
+    // left pixel transparent, right half transparent
+    image.pixels[3] = 0;
+    image.pixels[7] = 128;
+    fb.draw(&image, 1, 1);
+    assert_eq!(fb.pixel(1, 1), Some(BLUE));
+    assert_eq!(fb.pixel(1, 2), Some([128, 0, 127, 255]));
+}
+
+#[test]
+fn test_fill() {
+    let mut fb = Framebuffer::new(3, 3, WHITE);
+    fb.fill(2, 2, 5, 5, RED);
+    assert_eq!(fb.pixel(1, 1), Some(WHITE));
+    assert_eq!(fb.pixel(2, 2), Some(RED));
+    assert_eq!(fb.pixel(3, 3), Some(RED));
+    assert_eq!(fb.pixel(1, 3), Some(WHITE));
+}
+
+#[test]
+fn test_framebuffer_follows_window_size() {
+    let mut graphics = Graphics::new();
+    let mut window = Window {
+        width: 2,
+        height: 2,
+        colour: 3 << 8, // red background
+        ..Window::default()
+    };
+    assert_eq!(graphics.framebuffer_mut(1, &window, (400, 640)).pixel(1, 1), standard_colour(3));
+    window.width = 5;
+    assert_eq!(graphics.framebuffer_mut(1, &window, (400, 640)).width(), 5);
+    assert_eq!(graphics.framebuffer(1).unwrap().height(), 2);
+
+    graphics.erase(1);
+    assert_eq!(graphics.framebuffer(1).unwrap().pixels().len(), 0);
+}
+
+#[test]
+fn test_framebuffer_is_no_bigger_than_the_screen() {
+    let mut graphics = Graphics::new();
+    let window = Window {
+        width: 0xFFFF,
+        height: 0xFFFF,
+        ..Window::default()
+    };
+    let fb = graphics.framebuffer_mut(2, &window, (400, 640));
+    assert_eq!((fb.height(), fb.width()), (400, 640));
+}
+
+#[test]
+fn test_missing_picture() {
+    let mut graphics = Graphics::new();
+    assert_eq!(graphics.image(None, 1).unwrap(), None);
+}
+
+#[test]
+fn test_standard_colours() {
+    assert_eq!(standard_colour(2), Some([0, 0, 0, 255]));
+    assert_eq!(standard_colour(9), Some(WHITE));
+    assert_eq!(standard_colour(1), None);
+}
@@ -0,0 +1,408 @@
+use crate::error::Error;
+use crate::memory::Memory;
+use crate::object::{ObjectTree, Properties};
+use crate::zscii::Zscii;
+use std::fmt::{self, Write};
+
+/*
+ * An infodump-style report on a story file: the header, the abbreviations, the object
+ * table and tree, the dictionary and a map of where each table lives in memory. The
+ * report is worked out once from the story, so every part of it can be looked at
+ * without a running machine, and printed whole or a section at a time.
+ */
+
+/// The parts of a report, for printing some of them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Section {
+    Header,
+    Abbreviations,
+    Objects,
+    Tree,
+    Dictionary,
+    MemoryMap,
+}
+
+impl Section {
+    pub const ALL: [Section; 6] = [
+        Section::Header,
+        Section::Abbreviations,
+        Section::Objects,
+        Section::Tree,
+        Section::Dictionary,
+        Section::MemoryMap,
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub number: u16,
+    pub name: String,
+    pub attributes: Vec<u8>, //the attributes which are set
+    pub parent: u16,
+    pub sibling: u16,
+    pub child: u16,
+    pub properties_addr: u16,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dictionary {
+    pub separators: Vec<char>,
+    pub entry_length: u8,
+    pub words: Vec<(u16, String)>, //address and text of each entry
+}
+
+/// A table or area of memory, from `start` up to but not including `end`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: u32,
+    pub end: u32,
+    pub name: &'static str,
+}
+
+pub struct Report {
+    pub header: Vec<(&'static str, String)>,
+    pub abbreviations: Vec<String>,
+    pub objects: Vec<ObjectInfo>,
+    pub dictionary: Dictionary,
+    pub memory_map: Vec<Region>,
+    mem: Memory, //for the object tree and properties, read through ObjectTree
+}
+
+impl Report {
+    /// Fails if the story is too short to have a header; tables the header points past
+    /// the end of the story are reported as best they can be.
+    pub fn new(story: &[u8]) -> Result<Report, Error> {
+        Memory::check_header(story)?;
+        let mem = Memory::new(story);
+        let objects = objects(&mem);
+        let dictionary = dictionary(&mem);
+        Ok(Report {
+            header: header(&mem),
+            abbreviations: abbreviations(&mem),
+            memory_map: memory_map(&mem, &objects, &dictionary),
+            objects,
+            dictionary,
+            mem,
+        })
+    }
+
+    /// The object numbered `number`, as in "what is object 123".
+    pub fn object(&self, number: u16) -> Option<&ObjectInfo> {
+        self.objects.get((number as usize).checked_sub(1)?)
+    }
+
+    /// The properties of object `number`, with their raw data. Empty if there's no such object.
+    pub fn properties(&self, number: u16) -> Properties<'_> {
+        ObjectTree::new(&self.mem).properties(number)
+    }
+
+    /// The report's sections, in the order given.
+    pub fn render(&self, sections: &[Section]) -> String {
+        let mut out = String::new();
+        for (i, section) in sections.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            match section {
+                Section::Header => self.write_header(&mut out),
+                Section::Abbreviations => self.write_abbreviations(&mut out),
+                Section::Objects => {
+                    out.push_str("    **** Objects ****\n\n");
+                    for obj in &self.objects {
+                        self.write_object(&mut out, obj);
+                    }
+                }
+                Section::Tree => self.write_tree(&mut out),
+                Section::Dictionary => self.write_dictionary(&mut out),
+                Section::MemoryMap => self.write_memory_map(&mut out),
+            }
+        }
+        out
+    }
+
+    fn write_header(&self, out: &mut String) {
+        out.push_str("    **** Story file header ****\n\n");
+        for (name, value) in &self.header {
+            let _ = writeln!(out, "{:<28}{}", format!("{}:", name), value);
+        }
+    }
+
+    fn write_abbreviations(&self, out: &mut String) {
+        out.push_str("    **** Abbreviations ****\n\n");
+        for (i, abbrev) in self.abbreviations.iter().enumerate() {
+            let _ = writeln!(out, "[{:2}] \"{}\"", i, abbrev);
+        }
+    }
+
+    /// One object as the objects section shows it.
+    pub fn write_object(&self, out: &mut String, obj: &ObjectInfo) {
+        let attributes: Vec<String> = obj.attributes.iter().map(|a| a.to_string()).collect();
+        let _ = writeln!(out, "{:3}. Attributes: {}", obj.number, attributes.join(", "));
+        let _ = writeln!(
+            out,
+            "     Parent object: {:3}  Sibling object: {:3}  Child object: {:3}",
+            obj.parent, obj.sibling, obj.child
+        );
+        let _ = writeln!(out, "     Property address: {:04X}", obj.properties_addr);
+        let _ = writeln!(out, "         Description: \"{}\"", obj.name);
+        out.push_str("          Properties:\n");
+        for prop in self.properties(obj.number) {
+            let bytes: Vec<String> = prop.data.iter().map(|b| format!("{:02X}", b)).collect();
+            let _ = writeln!(out, "              [{:2}] {}", prop.number, bytes.join(" "));
+        }
+        out.push('\n');
+    }
+
+    fn write_tree(&self, out: &mut String) {
+        out.push_str("    **** Object tree ****\n\n");
+        let tree = ObjectTree::new(&self.mem);
+        let mut visited = vec![false; self.objects.len() + 1];
+        for root in tree.roots() {
+            self.write_subtree(out, &tree, root, &mut visited);
+        }
+    }
+
+    //depth first with a stack of (object, depth) rather than recursion, as a broken tree
+    //can be deep; an object already printed isn't printed again, so loops end
+    fn write_subtree(&self, out: &mut String, tree: &ObjectTree, root: u16, visited: &mut [bool]) {
+        let mut stack = vec![(root, 0)];
+        while let Some((obj, depth)) = stack.pop() {
+            if std::mem::replace(&mut visited[obj as usize], true) {
+                continue;
+            }
+            let name = self.object(obj).map_or("", |o| o.name.as_str());
+            let _ = writeln!(out, "{}[{:3}] \"{}\"", " . ".repeat(depth), obj, name);
+            //pushed last child first, so the first child comes off the stack first
+            let children: Vec<u16> = tree.children(obj).collect();
+            stack.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+        }
+    }
+
+    fn write_dictionary(&self, out: &mut String) {
+        out.push_str("    **** Dictionary ****\n\n");
+        let separators: Vec<String> = self.dictionary.separators.iter().map(|c| format!("\"{}\"", c)).collect();
+        let _ = writeln!(out, "  Word separators = {}", separators.join(" "));
+        let _ = writeln!(
+            out,
+            "  Word count = {}, word size = {}\n",
+            self.dictionary.words.len(),
+            self.dictionary.entry_length
+        );
+        for (i, (addr, word)) in self.dictionary.words.iter().enumerate() {
+            let _ = writeln!(out, "[{:4}] @ ${:04X} {}", i + 1, addr, word);
+        }
+    }
+
+    fn write_memory_map(&self, out: &mut String) {
+        out.push_str("    **** Story file memory map ****\n\n");
+        out.push_str("  Base    End     Size\n");
+        for region in &self.memory_map {
+            let end = region.end.max(region.start + 1) - 1;
+            let _ = writeln!(
+                out,
+                "{:6X}  {:6X}  {:6X}  {}",
+                region.start,
+                end,
+                region.end - region.start,
+                region.name
+            );
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(&Section::ALL))
+    }
+}
+
+//the header is checked to be there, but tables it points at may run past the end of the
+//story: they read 0 there, so the report shows what there is rather than failing
+fn header(mem: &Memory) -> Vec<(&'static str, String)> {
+    let version = mem.zmachine_version();
+    let inform: String = (0x3C..0x40).map(|a| mem.read_u8(a) as char).collect();
+    let mut header = vec![
+        ("Z-code version", version.to_string()),
+        ("Interpreter flags", format!("{:02X}", mem.read_u8(0x01))),
+        ("Release number", mem.release().to_string()),
+        ("Size of resident memory", format!("{:04X}", mem.high_memory())),
+        ("Start PC", format!("{:04X}", mem.initial_pc())),
+        ("Dictionary address", format!("{:04X}", mem.dictionary())),
+        ("Object table address", format!("{:04X}", mem.object_table())),
+        ("Global variables address", format!("{:04X}", mem.global_variables())),
+        ("Size of dynamic memory", format!("{:04X}", mem.static_memory())),
+        ("Game flags", format!("{:04X}", mem.read_u16(0x10))),
+        ("Serial number", mem.serial()),
+        ("Abbreviations address", format!("{:04X}", mem.abbreviations_table())),
+        ("File size", format!("{:05X}", mem.file_length())),
+        ("Checksum", format!("{:04X}", mem.checksum())),
+    ];
+    if version >= 4 {
+        header.push(("Interpreter number", mem.read_u8(0x1E).to_string()));
+        header.push(("Interpreter version", mem.read_u8(0x1F).to_string()));
+        header.push(("Screen rows", mem.screen_height_lines().to_string()));
+        header.push(("Screen columns", mem.screen_width_chars().to_string()));
+    }
+    if version == 6 || version == 7 {
+        header.push(("Routines offset", format!("{:05X}", mem.routine_offset() as u32 * 8)));
+        header.push(("Strings offset", format!("{:05X}", mem.string_offset() as u32 * 8)));
+    }
+    if version >= 5 {
+        header.push(("Terminating keys address", format!("{:04X}", mem.character_table())));
+        header.push(("Alphabet address", format!("{:04X}", mem.alphabet_table())));
+        header.push(("Header extension address", format!("{:04X}", mem.header_extension_table())));
+    }
+    if inform.chars().all(|c| c.is_ascii_graphic()) {
+        header.push(("Inform version", inform));
+    }
+    header
+}
+
+//V1 has no abbreviations and V2 only has z-char 1's 32, see 3.3
+fn abbreviation_count(version: u8) -> u32 {
+    match version {
+        1 => 0,
+        2 => 32,
+        _ => 96,
+    }
+}
+
+//strings from word addresses in the table
+fn abbreviations(mem: &Memory) -> Vec<String> {
+    let table = mem.abbreviations_table() as u32;
+    if table == 0 {
+        return vec![];
+    }
+    let mut zscii = Zscii::new(mem);
+    (0..abbreviation_count(mem.zmachine_version()))
+        .map(|i| zscii.get_string(mem.fetch_u16(table + i * 2).unwrap_or(0) as u32 * 2).unwrap_or_default())
+        .collect()
+}
+
+fn objects(mem: &Memory) -> Vec<ObjectInfo> {
+    let tree = ObjectTree::new(mem);
+    (1..=tree.count())
+        .map(|obj| ObjectInfo {
+            number: obj,
+            name: tree.name(obj).unwrap_or_default(),
+            attributes: tree.attributes(obj),
+            parent: tree.parent(obj).unwrap_or(0),
+            sibling: tree.sibling(obj).unwrap_or(0),
+            child: tree.child(obj).unwrap_or(0),
+            properties_addr: tree.properties_addr(obj).unwrap_or(0),
+        })
+        .collect()
+}
+
+fn dictionary(mem: &Memory) -> Dictionary {
+    let addr = mem.dictionary() as u32;
+    if addr == 0 {
+        return Dictionary::default();
+    }
+    let count = mem.fetch_u8(addr).unwrap_or(0);
+    let separators = (0..count as u32)
+        .map(|i| mem.fetch_u8(addr + 1 + i).unwrap_or(0) as char)
+        .collect();
+    let entries = addr + 1 + count as u32;
+    let entry_length = mem.fetch_u8(entries).unwrap_or(0);
+    //negative for an unsorted dictionary
+    let words = (mem.fetch_u16(entries + 1).unwrap_or(0) as i16).unsigned_abs();
+
+    let mut zscii = Zscii::new(mem);
+    let first = entries + 3;
+    let words = (0..words)
+        .map(|i| first + i as u32 * entry_length as u32)
+        .take_while(|&word| (word as usize) < mem.len())
+        .map(|word| (word as u16, zscii.get_string(word).unwrap_or_default()))
+        .collect();
+    Dictionary {
+        separators,
+        entry_length,
+        words,
+    }
+}
+
+fn memory_map(mem: &Memory, objects: &[ObjectInfo], dictionary: &Dictionary) -> Vec<Region> {
+    let mut map = vec![Region {
+        start: 0,
+        end: 0x40,
+        name: "Story file header",
+    }];
+    let abbrevs = mem.abbreviations_table() as u32;
+    let abbrev_count = abbreviation_count(mem.zmachine_version());
+    if abbrevs != 0 && abbrev_count > 0 {
+        map.push(Region {
+            start: abbrevs,
+            end: abbrevs + abbrev_count * 2,
+            name: "Abbreviation pointer table",
+        });
+    }
+
+    let table = mem.object_table() as u32;
+    if table != 0 {
+        let tree = ObjectTree::new(mem);
+        map.push(Region {
+            start: table,
+            end: tree.entries_end(),
+            name: "Object table",
+        });
+        if let Some(first) = objects.iter().map(|o| o.properties_addr as u32).min() {
+            //each table ends with a 0 after its last property
+            let last = objects
+                .iter()
+                .map(|o| {
+                    let mut props = tree.properties(o.number);
+                    props.by_ref().for_each(drop);
+                    props.addr() as u32 + 1
+                })
+                .max()
+                .unwrap_or(first);
+            map.push(Region {
+                start: first,
+                end: last,
+                name: "Property tables",
+            });
+        }
+    }
+
+    let globals = mem.global_variables() as u32;
+    map.push(Region {
+        start: globals,
+        end: globals + 480,
+        name: "Global variables",
+    });
+
+    let dict = mem.dictionary() as u32;
+    if dict != 0 {
+        let end = match dictionary.words.last() {
+            Some((addr, _)) => *addr as u32 + dictionary.entry_length as u32,
+            None => dict + dictionary.separators.len() as u32 + 4,
+        };
+        map.push(Region {
+            start: dict,
+            end,
+            name: "Dictionary",
+        });
+    }
+
+    map.push(Region {
+        start: mem.static_memory() as u32,
+        end: mem.static_memory() as u32,
+        name: "Start of static memory",
+    });
+    map.push(Region {
+        start: mem.high_memory() as u32,
+        end: mem.high_memory() as u32,
+        name: "Start of high memory",
+    });
+    map.push(Region {
+        start: mem.len() as u32,
+        end: mem.len() as u32,
+        name: "End of file",
+    });
+    map.sort_by_key(|r| (r.start, r.end));
+    map
+}
+
+#[cfg(test)]
+mod inspect_tests;
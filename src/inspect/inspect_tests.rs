@@ -0,0 +1,168 @@
+use super::*;
+
+// V3 story with abbreviations at 0x40, two objects at 0x100, globals at 0x160 and a
+// dictionary of two words at 0x340
+fn create_story() -> Vec<u8> {
+    let mut data = vec![0u8; 0x500];
+    data[0x00] = 3;
+    data[0x04] = 0x04; // High memory at 0x400
+    data[0x06] = 0x04; // PC at 0x400
+    data[0x08] = 0x03;
+    data[0x09] = 0x40; // Dictionary at 0x340
+    data[0x0A] = 0x01; // Object table at 0x100
+    data[0x0C] = 0x01;
+    data[0x0D] = 0x60; // Globals at 0x160
+    data[0x0E] = 0x03;
+    data[0x0F] = 0x40; // Static memory at 0x340
+    data[0x18] = 0x00;
+    data[0x19] = 0x40; // Abbreviations at 0x40
+    // every abbreviation is "hi", at word address 0x1F8
+    for i in 0..96 {
+        data[0x40 + i * 2] = 0x01;
+        data[0x41 + i * 2] = 0xF8;
+    }
+    data[0x3F0..0x3F2].copy_from_slice(&[0xB5, 0xC5]);
+    // object 1 has attributes 3 and 31 and contains object 2
+    data[0x13E..0x147].copy_from_slice(&[0x10, 0x00, 0x00, 0x01, 0, 0, 2, 0x01, 0x50]);
+    data[0x147..0x150].copy_from_slice(&[0x00, 0x00, 0x00, 0x00, 1, 0, 0, 0x01, 0x59]);
+    // "hi", property 5 = 12 34, property 2 = AB; then no name and no properties
+    data[0x150..0x15B].copy_from_slice(&[0x01, 0xB5, 0xC5, 0x25, 0x12, 0x34, 0x02, 0xAB, 0x00, 0x00, 0x00]);
+    // separators '.' and ',', 7 byte entries, "hi" and "i"
+    data[0x340..0x346].copy_from_slice(&[0x02, b'.', b',', 0x07, 0x00, 0x02]);
+    data[0x346..0x34A].copy_from_slice(&[0x35, 0xC5, 0x94, 0xA5]);
+    data[0x34D..0x351].copy_from_slice(&[0x38, 0xA5, 0x94, 0xA5]);
+    data
+}
+
+#[test]
+fn test_header_and_abbreviations() {
+    let report = Report::new(&create_story()).unwrap();
+    assert_eq!(report.header[0], ("Z-code version", "3".to_string()));
+    assert!(report.header.contains(&("Dictionary address", "0340".to_string())));
+    assert!(!report.header.iter().any(|(name, _)| *name == "Screen rows"));
+    assert_eq!(report.abbreviations.len(), 96);
+    assert_eq!(report.abbreviations[95], "hi");
+}
+
+#[test]
+fn test_objects() {
+    let report = Report::new(&create_story()).unwrap();
+    assert_eq!(report.objects.len(), 2);
+    let first = report.object(1).unwrap();
+    assert_eq!(first.name, "hi");
+    assert_eq!(first.attributes, vec![3, 31]);
+    assert_eq!((first.parent, first.sibling, first.child), (0, 0, 2));
+    let props: Vec<(u8, &[u8])> = report.properties(1).map(|p| (p.number, p.data)).collect();
+    assert_eq!(props, vec![(5, &[0x12, 0x34][..]), (2, &[0xAB][..])]);
+    assert_eq!(report.object(2).unwrap().parent, 1);
+    assert!(report.object(0).is_none());
+    assert!(report.object(3).is_none());
+
+    let tree = report.render(&[Section::Tree]);
+    assert_eq!(tree, "    **** Object tree ****\n\n[  1] \"hi\"\n . [  2] \"\"\n");
+}
+
+#[test]
+fn test_dictionary_and_memory_map() {
+    let report = Report::new(&create_story()).unwrap();
+    assert_eq!(report.dictionary.separators, vec!['.', ',']);
+    assert_eq!(report.dictionary.entry_length, 7);
+    assert_eq!(report.dictionary.words, vec![(0x346, "hi".to_string()), (0x34D, "i".to_string())]);
+
+    let regions: Vec<(u32, u32, &str)> = report.memory_map.iter().map(|r| (r.start, r.end, r.name)).collect();
+    assert_eq!(
+        regions,
+        vec![
+            (0x000, 0x040, "Story file header"),
+            (0x040, 0x100, "Abbreviation pointer table"),
+            (0x100, 0x150, "Object table"),
+            (0x150, 0x15B, "Property tables"),
+            (0x160, 0x340, "Global variables"),
+            (0x340, 0x340, "Start of static memory"),
+            (0x340, 0x354, "Dictionary"),
+            (0x400, 0x400, "Start of high memory"),
+            (0x500, 0x500, "End of file"),
+        ]
+    );
+    let text = report.to_string();
+    assert!(text.contains("   150     15A       B  Property tables\n"));
+    assert!(text.contains("[   2] @ $034D i\n"));
+}
+
+#[test]
+fn test_v5_properties() {
+    let mut data = vec![0u8; 0x400];
+    data[0x00] = 5;
+    data[0x0A] = 0x01; // Object table at 0x100
+    data[0x0C] = 0x02; // Globals at 0x200
+    data[0x0E] = 0x02;
+    // one object with attribute 40, and properties at 0x18C
+    data[0x17E + 5] = 0x80;
+    data[0x17E + 12] = 0x01;
+    data[0x17E + 13] = 0x8C;
+    // no name, property 10 = 01 02 03 with a two byte header, property 4 = AA BB
+    data[0x18C..0x195].copy_from_slice(&[0x00, 0x8A, 0x03, 1, 2, 3, 0x44, 0xAA, 0xBB]);
+    let report = Report::new(&data).unwrap();
+    assert_eq!(report.objects.len(), 1);
+    let obj = report.object(1).unwrap();
+    assert_eq!(obj.attributes, vec![40]);
+    let props: Vec<(u8, &[u8])> = report.properties(1).map(|p| (p.number, p.data)).collect();
+    assert_eq!(props, vec![(10, &[1, 2, 3][..]), (4, &[0xAA, 0xBB][..])]);
+    assert!(report.abbreviations.is_empty());
+    assert!(report.dictionary.words.is_empty());
+}
+
+#[test]
+fn test_v2_abbreviations() {
+    let mut data = create_story();
+    data[0x00] = 2;
+    let report = Report::new(&data).unwrap();
+    assert_eq!(report.abbreviations.len(), 32);
+    assert!(report.memory_map.iter().any(|r| (r.start, r.end) == (0x40, 0x80)));
+}
+
+#[test]
+fn test_tables_past_end_of_story() {
+    assert!(Report::new(&[3; 0x3F]).is_err());
+
+    // the abbreviations and dictionary are past the end of the story
+    let mut data = create_story();
+    data.truncate(0x100);
+    data[0x08] = 0x0F;
+    data[0x18] = 0xFF;
+    data[0x19] = 0xFE;
+    let report = Report::new(&data).unwrap();
+    assert_eq!(report.abbreviations.len(), 96);
+    assert!(report.dictionary.separators.is_empty());
+    assert!(report.dictionary.words.is_empty());
+}
+
+#[test]
+fn test_tree_with_a_loop() {
+    // object 2 is its own sibling
+    let mut data = create_story();
+    data[0x14C] = 2;
+    let report = Report::new(&data).unwrap();
+    let tree = report.render(&[Section::Tree]);
+    assert_eq!(tree, "    **** Object tree ****\n\n[  1] \"hi\"\n . [  2] \"\"\n");
+}
+
+#[test]
+fn test_deep_tree() {
+    // a chain of 255 objects, each inside the one before
+    let mut data = vec![0u8; 0x2000];
+    data[0x00] = 3;
+    data[0x0A] = 0x01; // Object table at 0x100
+    data[0x0E] = 0x20;
+    for obj in 1..=255usize {
+        let at = 0x13E + (obj - 1) * 9;
+        data[at + 4] = (obj - 1) as u8;
+        data[at + 6] = if obj < 255 { obj as u8 + 1 } else { 0 };
+        data[at + 7..at + 9].copy_from_slice(&0x1000u16.to_be_bytes());
+    }
+    let report = Report::new(&data).unwrap();
+    assert_eq!(report.objects.len(), 255);
+    let tree = report.render(&[Section::Tree]);
+    assert_eq!(tree.lines().count(), 2 + 255);
+    assert!(tree.ends_with(&format!("{}[255] \"\"\n", " . ".repeat(254))));
+}
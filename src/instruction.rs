@@ -1,13 +1,21 @@
+use crate::blorb::Usage;
 use crate::object::Object;
 use crate::zmachine::State;
+use crate::decode::Decoded;
 use crate::error::Error;
-use crate::memory::{Memory, StackFrame};
-use std::ops::Deref;
+use crate::memory::Memory;
+use crate::stack::StackFrame;
+use crate::zscreen::{Input, SoundEffect as ZSoundEffect};
+use std::ops::{Deref, RangeInclusive};
 
 /// Trait representing a single Z-Machine instruction.
+///
+/// Besides running it, an instruction describes how it is encoded in each version, so
+/// the decoder can read every instruction the same way without executing it.
 pub trait Instruction {
-    /// Executes the instruction with the given operands.
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error>;
+    /// Executes the instruction with the given operands. `decoded` holds the store
+    /// variable, branch and inline text which followed them; the PC is already past it all.
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error>;
 
     /// Returns the mnemonic name of the instruction (e.g., "ADD", "JE").
     fn name(&self) -> &'static str;
@@ -16,34 +24,99 @@ pub trait Instruction {
     fn description(&self) -> &'static str {
         "No description available."
     }
+
+    /// How many operands the instruction takes in `version`.
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        0..=0
+    }
+
+    /// Whether a store variable byte follows the operands in `version`.
+    fn stores(&self, _version: u8) -> bool {
+        false
+    }
+
+    /// Whether branch bytes follow the operands (and store variable) in `version`.
+    fn branches(&self, _version: u8) -> bool {
+        false
+    }
+
+    /// Whether a literal string follows the instruction, as for print and print_ret.
+    fn inline_text(&self) -> bool {
+        false
+    }
 }
 
-// ============================================================================
-// Placeholder / Default Instructions
-// ============================================================================
+/// A statically dispatched instruction handler.
+pub type Handler = fn(&mut State, &[u16], &Decoded) -> Result<(), Error>;
 
-#[derive(Clone)]
-pub struct IllegalInstruction(pub u8);
+/// An entry in the dispatch tables: a plain function pointer to run the instruction,
+/// and the instruction itself for its name and description.
+#[derive(Copy, Clone)]
+pub struct Op {
+    pub execute: Handler,
+    pub instruction: &'static dyn Instruction,
+}
+
+/// Builds a dispatch table entry for one of the instructions in this module.
+macro_rules! op {
+    ($name:ident) => {
+        Some(crate::instruction::Op {
+            execute: |state, operands, decoded| {
+                crate::instruction::Instruction::execute(&crate::instruction::$name, state, operands, decoded)
+            },
+            instruction: &crate::instruction::$name,
+        })
+    };
+}
+pub(crate) use op;
+
+/// The dispatch tables: one indexed by opcode byte, and one for EXT (0xBE) instructions
+/// indexed by their second byte. Opcodes left as None are illegal.
+pub struct OpTable {
+    pub main: [Option<Op>; 256],
+    pub ext: [Option<Op>; 256],
+}
 
-impl Instruction for IllegalInstruction {
-    fn execute(&self, _state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        Err(Error::ZMachineError(format!("Illegal opcode: 0x{:02x}", self.0)))
+impl OpTable {
+    pub fn get(&self, opcode: u8, ext: Option<u8>) -> Option<Op> {
+        match ext {
+            Some(ext) => self.ext[ext as usize],
+            None => self.main[opcode as usize],
+        }
     }
+}
 
-    fn name(&self) -> &'static str {
-        "ILLEGAL"
+/// The operands of one instruction, without allocating: at most 8 (for call_vs2).
+#[derive(Copy, Clone, Default)]
+pub struct Operands {
+    values: [u16; 8],
+    len: usize,
+}
+
+impl Operands {
+    pub fn push(&mut self, val: u16) {
+        self.values[self.len] = val;
+        self.len += 1;
     }
+}
 
-    fn description(&self) -> &'static str {
-        "An illegal or unsupported opcode."
+impl Deref for Operands {
+    type Target = [u16];
+
+    fn deref(&self) -> &[u16] {
+        &self.values[..self.len]
     }
 }
 
+// ============================================================================
+// Placeholder / Default Instructions
+// ============================================================================
+
 #[derive(Clone)]
 pub struct NopInstruction;
 
 impl Instruction for NopInstruction {
-    fn execute(&self, _state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, _state: &mut State, _operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         Ok(())
     }
 
@@ -60,13 +133,12 @@ impl Instruction for NopInstruction {
 pub struct Add;
 
 impl Instruction for Add {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0] as i16;
         let b = operands[1] as i16;
         let result = a.wrapping_add(b);
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, result as u16)
+        state.store_result(decoded, result as u16)
     }
 
     fn name(&self) -> &'static str {
@@ -76,19 +148,26 @@ impl Instruction for Add {
     fn description(&self) -> &'static str {
         "Adds two signed 16-bit integers."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Sub;
 
 impl Instruction for Sub {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0] as i16;
         let b = operands[1] as i16;
         let result = a.wrapping_sub(b);
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, result as u16)
+        state.store_result(decoded, result as u16)
     }
 
     fn name(&self) -> &'static str {
@@ -98,19 +177,26 @@ impl Instruction for Sub {
     fn description(&self) -> &'static str {
         "Subtracts two signed 16-bit integers."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Mul;
 
 impl Instruction for Mul {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0] as i16;
         let b = operands[1] as i16;
         let result = a.wrapping_mul(b);
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, result as u16)
+        state.store_result(decoded, result as u16)
     }
 
     fn name(&self) -> &'static str {
@@ -120,22 +206,31 @@ impl Instruction for Mul {
     fn description(&self) -> &'static str {
         "Multiplies two signed 16-bit integers."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Div;
 
 impl Instruction for Div {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0] as i16;
         let b = operands[1] as i16;
         if b == 0 {
-            return Err(Error::ZMachineError("Division by zero".to_string()));
+            return Err(Error::DivisionByZero {
+                context: Default::default(),
+            });
         }
         let result = a.wrapping_div(b);
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, result as u16)
+        state.store_result(decoded, result as u16)
     }
 
     fn name(&self) -> &'static str {
@@ -145,22 +240,31 @@ impl Instruction for Div {
     fn description(&self) -> &'static str {
         "Divides two signed 16-bit integers."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Mod;
 
 impl Instruction for Mod {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0] as i16;
         let b = operands[1] as i16;
         if b == 0 {
-            return Err(Error::ZMachineError("Division by zero".to_string()));
+            return Err(Error::DivisionByZero {
+                context: Default::default(),
+            });
         }
         let result = a.wrapping_rem(b);
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, result as u16)
+        state.store_result(decoded, result as u16)
     }
 
     fn name(&self) -> &'static str {
@@ -170,19 +274,26 @@ impl Instruction for Mod {
     fn description(&self) -> &'static str {
         "Remainder of division of two signed 16-bit integers."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Or;
 
 impl Instruction for Or {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0];
         let b = operands[1];
         let result = a | b;
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, result)
+        state.store_result(decoded, result)
     }
 
     fn name(&self) -> &'static str {
@@ -192,19 +303,26 @@ impl Instruction for Or {
     fn description(&self) -> &'static str {
         "Bitwise OR."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct And;
 
 impl Instruction for And {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0];
         let b = operands[1];
         let result = a & b;
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, result)
+        state.store_result(decoded, result)
     }
 
     fn name(&self) -> &'static str {
@@ -214,18 +332,25 @@ impl Instruction for And {
     fn description(&self) -> &'static str {
         "Bitwise AND."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Not;
 
 impl Instruction for Not {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0];
         let result = !a;
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, result)
+        state.store_result(decoded, result)
     }
 
     fn name(&self) -> &'static str {
@@ -235,13 +360,21 @@ impl Instruction for Not {
     fn description(&self) -> &'static str {
         "Bitwise NOT."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Store;
 
 impl Instruction for Store {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let var_id = operands[0] as u8;
         let value = operands[1];
         state.store_variable(var_id, value)
@@ -254,17 +387,20 @@ impl Instruction for Store {
     fn description(&self) -> &'static str {
         "Stores the value in the given variable."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
 }
 
 #[derive(Clone)]
 pub struct Load;
 
 impl Instruction for Load {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let var_id = operands[0] as u8;
         let value = state.read_variable(var_id)?;
-        let result_var = state.next_u8();
-        state.store_variable(result_var, value)
+        state.store_result(decoded, value)
     }
 
     fn name(&self) -> &'static str {
@@ -274,17 +410,25 @@ impl Instruction for Load {
     fn description(&self) -> &'static str {
         "Loads the value from the given variable and stores it in the result variable."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct StoreW;
 
 impl Instruction for StoreW {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let array = operands[0];
         let index = operands[1];
         let value = operands[2];
-        state.mem.write_u16(array.wrapping_add(index.wrapping_mul(2)), value);
+        state.mem.checked_write_u16(array.wrapping_add(index.wrapping_mul(2)), value)?;
         Ok(())
     }
 
@@ -295,17 +439,21 @@ impl Instruction for StoreW {
     fn description(&self) -> &'static str {
         "Stores a 16-bit word at the given array address and index."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        3..=3
+    }
 }
 
 #[derive(Clone)]
 pub struct StoreB;
 
 impl Instruction for StoreB {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let array = operands[0];
         let index = operands[1];
         let value = operands[2] as u8;
-        state.mem.write_u8(array.wrapping_add(index), value);
+        state.mem.checked_write_u8(array.wrapping_add(index), value)?;
         Ok(())
     }
 
@@ -316,18 +464,21 @@ impl Instruction for StoreB {
     fn description(&self) -> &'static str {
         "Stores an 8-bit byte at the given array address and index."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        3..=3
+    }
 }
 
 #[derive(Clone)]
 pub struct LoadW;
 
 impl Instruction for LoadW {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let array = operands[0];
         let index = operands[1];
-        let value = state.mem.read_u16(array.wrapping_add(index.wrapping_mul(2)));
-        let result_var = state.next_u8();
-        state.store_variable(result_var, value)
+        let value = state.mem.checked_read_u16(array.wrapping_add(index.wrapping_mul(2)))?;
+        state.store_result(decoded, value)
     }
 
     fn name(&self) -> &'static str {
@@ -337,18 +488,25 @@ impl Instruction for LoadW {
     fn description(&self) -> &'static str {
         "Loads a 16-bit word from the given array address and index."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct LoadB;
 
 impl Instruction for LoadB {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let array = operands[0];
         let index = operands[1];
-        let value = state.mem.read_u8(array.wrapping_add(index)) as u16;
-        let result_var = state.next_u8();
-        state.store_variable(result_var, value)
+        let value = state.mem.checked_read_u8(array.wrapping_add(index))? as u16;
+        state.store_result(decoded, value)
     }
 
     fn name(&self) -> &'static str {
@@ -358,13 +516,21 @@ impl Instruction for LoadB {
     fn description(&self) -> &'static str {
         "Loads an 8-bit byte from the given array address and index."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Push;
 
 impl Instruction for Push {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let value = operands[0];
         state.store_variable(0, value)
     }
@@ -376,13 +542,17 @@ impl Instruction for Push {
     fn description(&self) -> &'static str {
         "Pushes a value onto the stack."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct Pull;
 
 impl Instruction for Pull {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let var_id = operands[0] as u8;
         let value = state.read_variable(0)?;
         state.store_variable(var_id, value)
@@ -395,13 +565,17 @@ impl Instruction for Pull {
     fn description(&self) -> &'static str {
         "Pulls a value from the stack and stores it in the given variable."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct Inc;
 
 impl Instruction for Inc {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let var_id = operands[0] as u8;
         let value = state.read_variable(var_id)? as i16;
         state.store_variable(var_id, value.wrapping_add(1) as u16)
@@ -414,13 +588,17 @@ impl Instruction for Inc {
     fn description(&self) -> &'static str {
         "Increments the value in the given variable."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct Dec;
 
 impl Instruction for Dec {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let var_id = operands[0] as u8;
         let value = state.read_variable(var_id)? as i16;
         state.store_variable(var_id, value.wrapping_sub(1) as u16)
@@ -433,19 +611,22 @@ impl Instruction for Dec {
     fn description(&self) -> &'static str {
         "Decrements the value in the given variable."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct IncChk;
 
 impl Instruction for IncChk {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let var_id = operands[0] as u8;
         let threshold = operands[1] as i16;
         let value = (state.read_variable(var_id)? as i16).wrapping_add(1);
         state.store_variable(var_id, value as u16)?;
-        state.branch(value > threshold);
-        Ok(())
+        state.branch(decoded, value > threshold)
     }
 
     fn name(&self) -> &'static str {
@@ -455,19 +636,26 @@ impl Instruction for IncChk {
     fn description(&self) -> &'static str {
         "Increments the variable and jumps if it is greater than the threshold."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct DecChk;
 
 impl Instruction for DecChk {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let var_id = operands[0] as u8;
         let threshold = operands[1] as i16;
         let value = (state.read_variable(var_id)? as i16).wrapping_sub(1);
         state.store_variable(var_id, value as u16)?;
-        state.branch(value < threshold);
-        Ok(())
+        state.branch(decoded, value < threshold)
     }
 
     fn name(&self) -> &'static str {
@@ -477,22 +665,67 @@ impl Instruction for DecChk {
     fn description(&self) -> &'static str {
         "Decrements the variable and jumps if it is less than the threshold."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
+}
+
+// Object 0 is "nothing", V1-3 have only 255 objects, and the object table is in dynamic memory.
+// A permissive machine carries on past a bad object: false tells the opcode to do nothing,
+// storing 0 and not branching, as it would for an object with no parent, child or attributes
+fn check_object(state: &State, obj: u16) -> Result<bool, Error> {
+    let (defaults, size) = if state.version <= 3 { (31, 9) } else { (63, 14) };
+    let end = state.mem.object_table() as u32 + defaults * 2 + obj as u32 * size;
+    if obj != 0 && (state.version > 3 || obj <= 255) && end <= state.mem.static_memory() as u32 {
+        Ok(true)
+    } else if state.mem.permissive {
+        Ok(false)
+    } else {
+        Err(Error::BadObject {
+            object: obj,
+            context: Default::default(),
+        })
+    }
+}
+
+// Attributes run to 31 in V1-3 and 47 after. Like objects, a bad one is an error unless
+// permissive, when Ok(false) tells the opcode to do nothing
+fn check_attribute(state: &State, attr: u16) -> Result<bool, Error> {
+    let max = if state.version <= 3 { Object::<u8>::ATTRMAX } else { Object::<u16>::ATTRMAX };
+    if attr <= max {
+        Ok(true)
+    } else if state.mem.permissive {
+        Ok(false)
+    } else {
+        Err(Error::BadAttribute {
+            attr,
+            context: Default::default(),
+        })
+    }
 }
 
 #[derive(Clone)]
 pub struct SetAttr;
 
 impl Instruction for SetAttr {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? || !check_attribute(state, operands[1])? {
+            return Ok(());
+        }
         let attr = operands[1] as u8;
         
         if state.version <= 3 {
              let mut obj = Object::<u8>::new(&mut state.mem);
-             obj.attr_set(obj_id as u8, attr);
+             obj.attr_set(obj_id as u8, attr)?;
         } else {
              let mut obj = Object::<u16>::new(&mut state.mem);
-             obj.attr_set(obj_id, attr);
+             obj.attr_set(obj_id, attr)?;
         }
         Ok(())
     }
@@ -504,22 +737,29 @@ impl Instruction for SetAttr {
     fn description(&self) -> &'static str {
         "Sets the attribute of the object."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
 }
 
 #[derive(Clone)]
 pub struct ClearAttr;
 
 impl Instruction for ClearAttr {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? || !check_attribute(state, operands[1])? {
+            return Ok(());
+        }
         let attr = operands[1] as u8;
         
         if state.version <= 3 {
              let mut obj = Object::<u8>::new(&mut state.mem);
-             obj.attr_clear(obj_id as u8, attr);
+             obj.attr_clear(obj_id as u8, attr)?;
         } else {
              let mut obj = Object::<u16>::new(&mut state.mem);
-             obj.attr_clear(obj_id, attr);
+             obj.attr_clear(obj_id, attr)?;
         }
         Ok(())
     }
@@ -531,26 +771,32 @@ impl Instruction for ClearAttr {
     fn description(&self) -> &'static str {
         "Clears the attribute of the object."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
 }
 
 #[derive(Clone)]
 pub struct TestAttr;
 
 impl Instruction for TestAttr {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? || !check_attribute(state, operands[1])? {
+            return state.branch(decoded, false);
+        }
         let attr = operands[1] as u8;
         
         let result = if state.version <= 3 {
              let obj = Object::<u8>::new(&mut state.mem);
-             obj.attr_test(obj_id as u8, attr)
+             obj.attr_test(obj_id as u8, attr)?
         } else {
              let obj = Object::<u16>::new(&mut state.mem);
-             obj.attr_test(obj_id as u8, attr)
+             obj.attr_test(obj_id, attr)?
         };
         
-        state.branch(result);
-        Ok(())
+        state.branch(decoded, result)
     }
 
     fn name(&self) -> &'static str {
@@ -560,22 +806,33 @@ impl Instruction for TestAttr {
     fn description(&self) -> &'static str {
         "Jumps if the object has the attribute set."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct InsertObj;
 
 impl Instruction for InsertObj {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
         let dest_id = operands[1];
+        if !check_object(state, obj_id)? || !check_object(state, dest_id)? {
+            return Ok(());
+        }
         
         if state.version <= 3 {
              let mut obj = Object::<u8>::new(&mut state.mem);
-             obj.insert(obj_id as u8, dest_id as u8);
+             obj.insert(obj_id as u8, dest_id as u8)?;
         } else {
              let mut obj = Object::<u16>::new(&mut state.mem);
-             obj.insert(obj_id, dest_id);
+             obj.insert(obj_id, dest_id)?;
         }
         Ok(())
     }
@@ -587,21 +844,28 @@ impl Instruction for InsertObj {
     fn description(&self) -> &'static str {
         "Moves object to be the first child of the destination object."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
 }
 
 #[derive(Clone)]
 pub struct RemoveObj;
 
 impl Instruction for RemoveObj {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? {
+            return Ok(());
+        }
         
         if state.version <= 3 {
              let mut obj = Object::<u8>::new(&mut state.mem);
-             obj.remove(obj_id as u8);
+             obj.remove(obj_id as u8)?;
         } else {
              let mut obj = Object::<u16>::new(&mut state.mem);
-             obj.remove(obj_id);
+             obj.remove(obj_id)?;
         }
         Ok(())
     }
@@ -613,25 +877,31 @@ impl Instruction for RemoveObj {
     fn description(&self) -> &'static str {
         "Detaches the object from its parent."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct GetParent;
 
 impl Instruction for GetParent {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? {
+            return state.store_result(decoded, 0);
+        }
         
         let parent = if state.version <= 3 {
              let obj = Object::<u8>::new(&mut state.mem);
-             obj.parent(obj_id as u8) as u16
+             obj.parent(obj_id as u8)? as u16
         } else {
              let obj = Object::<u16>::new(&mut state.mem);
-             obj.parent(obj_id)
+             obj.parent(obj_id)?
         };
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, parent)
+        state.store_result(decoded, parent)
     }
 
     fn name(&self) -> &'static str {
@@ -641,27 +911,37 @@ impl Instruction for GetParent {
     fn description(&self) -> &'static str {
         "Stores the parent of the object."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct GetChild;
 
 impl Instruction for GetChild {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? {
+            state.store_result(decoded, 0)?;
+            return state.branch(decoded, false);
+        }
         
         let child = if state.version <= 3 {
              let obj = Object::<u8>::new(&mut state.mem);
-             obj.child(obj_id as u8) as u16
+             obj.child(obj_id as u8)? as u16
         } else {
              let obj = Object::<u16>::new(&mut state.mem);
-             obj.child(obj_id)
+             obj.child(obj_id)?
         };
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, child)?;
-        state.branch(child != 0);
-        Ok(())
+        state.store_result(decoded, child)?;
+        state.branch(decoded, child != 0)
     }
 
     fn name(&self) -> &'static str {
@@ -671,27 +951,41 @@ impl Instruction for GetChild {
     fn description(&self) -> &'static str {
         "Stores the first child of the object and jumps if it exists."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct GetSibling;
 
 impl Instruction for GetSibling {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? {
+            state.store_result(decoded, 0)?;
+            return state.branch(decoded, false);
+        }
         
         let sibling = if state.version <= 3 {
              let obj = Object::<u8>::new(&mut state.mem);
-             obj.sibling(obj_id as u8) as u16
+             obj.sibling(obj_id as u8)? as u16
         } else {
              let obj = Object::<u16>::new(&mut state.mem);
-             obj.sibling(obj_id)
+             obj.sibling(obj_id)?
         };
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, sibling)?;
-        state.branch(sibling != 0);
-        Ok(())
+        state.store_result(decoded, sibling)?;
+        state.branch(decoded, sibling != 0)
     }
 
     fn name(&self) -> &'static str {
@@ -701,26 +995,40 @@ impl Instruction for GetSibling {
     fn description(&self) -> &'static str {
         "Stores the next sibling of the object and jumps if it exists."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct GetProp;
 
 impl Instruction for GetProp {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? {
+            return state.store_result(decoded, 0);
+        }
         let prop_id = operands[1] as u8;
         
         let value = if state.version <= 3 {
              let obj = Object::<u8>::new(&mut state.mem);
-             obj.get_prop(obj_id as u8, prop_id)
+             obj.get_prop(obj_id as u8, prop_id)?
         } else {
              let obj = Object::<u16>::new(&mut state.mem);
-             obj.get_prop(obj_id, prop_id)
+             obj.get_prop(obj_id, prop_id)?
         };
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, value)
+        state.store_result(decoded, value)
     }
 
     fn name(&self) -> &'static str {
@@ -730,26 +1038,36 @@ impl Instruction for GetProp {
     fn description(&self) -> &'static str {
         "Stores the value of the property for the object."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct GetPropAddr;
 
 impl Instruction for GetPropAddr {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? {
+            return state.store_result(decoded, 0);
+        }
         let prop_id = operands[1] as u8;
         
         let addr = if state.version <= 3 {
              let obj = Object::<u8>::new(&mut state.mem);
-             obj.get_prop_addr(obj_id as u8, prop_id).addr
+             obj.get_prop_addr(obj_id as u8, prop_id)?
         } else {
              let obj = Object::<u16>::new(&mut state.mem);
-             obj.get_prop_addr(obj_id, prop_id).addr
+             obj.get_prop_addr(obj_id, prop_id)?
         };
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, addr)
+        state.store_result(decoded, addr)
     }
 
     fn name(&self) -> &'static str {
@@ -759,25 +1077,32 @@ impl Instruction for GetPropAddr {
     fn description(&self) -> &'static str {
         "Stores the address of the property data for the object."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct GetPropLen;
 
 impl Instruction for GetPropLen {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let prop_addr = operands[0];
         
         let len = if state.version <= 3 {
              let obj = Object::<u8>::new(&mut state.mem);
-             obj.get_prop_len(prop_addr)
+             obj.get_prop_len(prop_addr)?
         } else {
              let obj = Object::<u16>::new(&mut state.mem);
-             obj.get_prop_len(prop_addr)
+             obj.get_prop_len(prop_addr)?
         };
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, len as u16)
+        state.store_result(decoded, len as u16)
     }
 
     fn name(&self) -> &'static str {
@@ -787,26 +1112,36 @@ impl Instruction for GetPropLen {
     fn description(&self) -> &'static str {
         "Stores the length of the property data at the given address."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct GetNextProp;
 
 impl Instruction for GetNextProp {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? {
+            return state.store_result(decoded, 0);
+        }
         let prop_id = operands[1] as u8;
         
         let next_prop = if state.version <= 3 {
              let obj = Object::<u8>::new(&mut state.mem);
-             obj.get_prop_next(obj_id as u8, prop_id)
+             obj.get_prop_next(obj_id as u8, prop_id)?
         } else {
              let obj = Object::<u16>::new(&mut state.mem);
-             obj.get_prop_next(obj_id, prop_id)
+             obj.get_prop_next(obj_id, prop_id)?
         };
         
-        let store_var = state.next_u8();
-        state.store_variable(store_var, next_prop as u16)
+        state.store_result(decoded, next_prop as u16)
     }
 
     fn name(&self) -> &'static str {
@@ -816,23 +1151,34 @@ impl Instruction for GetNextProp {
     fn description(&self) -> &'static str {
         "Stores the next property ID of the object."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct PutProp;
 
 impl Instruction for PutProp {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? {
+            return Ok(());
+        }
         let prop_id = operands[1] as u8;
         let value = operands[2];
         
         if state.version <= 3 {
-             let obj = Object::<u8>::new(&mut state.mem);
-             obj.put_prop(obj_id as u8, prop_id, value);
+             let mut obj = Object::<u8>::new(&mut state.mem);
+             obj.put_prop(obj_id as u8, prop_id, value)?;
         } else {
-             let obj = Object::<u16>::new(&mut state.mem);
-             obj.put_prop(obj_id, prop_id, value);
+             let mut obj = Object::<u16>::new(&mut state.mem);
+             obj.put_prop(obj_id, prop_id, value)?;
         }
         Ok(())
     }
@@ -844,89 +1190,27 @@ impl Instruction for PutProp {
     fn description(&self) -> &'static str {
         "Sets the value of the property for the object."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        3..=3
+    }
 }
 
 #[derive(Clone)]
 pub struct Call;
 
 impl Instruction for Call {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let routine_addr = operands[0];
         let args = &operands[1..];
         
         if routine_addr == 0 {
             // Calling 0 returns false immediately
-            let result_store = state.next_u8();
-            state.store_variable(result_store, 0)?;
-            return Ok(());
-        }
-        
-        let packed_addr = routine_addr as u32 * 2; // TODO: V4/V5 packed address logic
-        // But for now, assuming unpacked or V3 logic (addr * 2) matches ZMachine.java for V3?
-        // ZMachine.java: pc *= PACK; (PACK=2 for V3)
-        // Let's assume V3 for now or generic packed handling later.
-        // Actually, operands are u16. Packed address * 2 might overflow u16, so use u32.
-        
-        // Read routine metadata
-        let num_locals = state.mem.read_u8(packed_addr as u16);
-        
-        // Create new stack frame
-        // PC starts after local count byte
-        let mut new_pc = packed_addr + 1;
-        
-        // We read `store_var` now.
-        let store_var = state.next_u8();
-        
-        // Push new frame with store var
-        let new_frame = state.frame.clone().push(&mut state.mem.stack, new_pc, Some(store_var));
-        
-        // Initialize locals
-        // V3: Locals are initialized with default values from the routine header
-        // V4: Locals are initialized to 0
-        if state.version <= 4 { // Actually V1-V4 use defaults? ZMachine.java says V5 zeroes, V1-V4 load defaults.
-             for i in 0..num_locals {
-                 let default_val = state.mem.read_u16(new_pc as u16);
-                 new_pc += 2;
-                 new_frame.write_local(&mut state.mem.stack, i as u16 + 1, default_val);
-             }
-        } else {
-             // V5+: Locals zeroed
-             for i in 0..num_locals {
-                 new_frame.write_local(&mut state.mem.stack, i as u16 + 1, 0);
-             }
-        }
-        
-        // Overwrite locals with arguments
-        for (i, arg) in args.iter().enumerate() {
-            if i < num_locals as usize {
-                new_frame.write_local(&mut state.mem.stack, i as u16 + 1, *arg);
-            }
+            return state.store_result(decoded, 0);
         }
-        
-        // Update state
-        state.frame = new_frame;
-        state.frame.pc = new_pc; // Set PC to start of instructions (after locals)
-        
-        // Read store variable for result (this is part of the CALL instruction in the OLD frame context)
-        // BUT, the Z-Machine spec says the store variable is part of the call instruction.
-        // And the result is stored AFTER returning.
-        // So we need to save where to store the result in the stack frame?
-        // StackFrame has `prev`... but doesn't explicitly store the "return result storage variable".
-        // ZMachine.java `op_call`: "f.res = res;" where res is the store variable.
-        // Rust StackFrame struct logic seems to be missing this 'res' field to store the return variable index.
-        // We need to update StackFrame to hold this.
-        
-        // Let's hold off on modifying StackFrame in this atomic step.
-        // Instead, let's assume `next_u8()` was called before this execute?
-        // No, `state.next_u8()` reads from `state.frame.pc`.
-        // We effectively just switched frames.
-        // The `store_var` byte is at the PC of the *calling* frame (old frame).
-        
-        // We read `store_var` now.
-        // Now we need to save `store_var` in the new frame so we know where to put the result when we return.
-        // TODO: Update StackFrame to support `return_store_var`.
-        
-        Ok(())
+
+        // The result is stored in the caller's variable when the routine returns
+        state.call_routine(routine_addr, args, decoded.store)
     }
 
     fn name(&self) -> &'static str {
@@ -936,27 +1220,46 @@ impl Instruction for Call {
     fn description(&self) -> &'static str {
         "Calls a routine."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=8
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct CallN;
+
+impl Instruction for CallN {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        if operands[0] == 0 {
+            return Ok(());
+        }
+        state.call_routine(operands[0], &operands[1..], None)
+    }
+
+    fn name(&self) -> &'static str {
+        "CALL_N"
+    }
+
+    fn description(&self) -> &'static str {
+        "Calls a routine and throws away the result (V5+)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=8
+    }
 }
 
 #[derive(Clone)]
 pub struct Ret;
 
 impl Instruction for Ret {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
-        let value = operands[0];
-        
-        // Pop frame
-        let old_frame = std::mem::replace(&mut state.frame, StackFrame::main(&state.mem)); // Temp dummy
-        let store_var = old_frame.return_store_var;
-        let prev_frame = old_frame.pop(&mut state.mem.stack)?;
-        state.frame = prev_frame;
-        
-        // Store result
-        if let Some(var_id) = store_var {
-            state.store_variable(var_id, value)?;
-        }
-        
-        Ok(())
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        state.return_routine(operands[0])
     }
 
     fn name(&self) -> &'static str {
@@ -966,15 +1269,18 @@ impl Instruction for Ret {
     fn description(&self) -> &'static str {
         "Returns from a routine with the given value."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct RTrue;
 
 impl Instruction for RTrue {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        let instr = Ret;
-        instr.execute(state, vec![1])
+    fn execute(&self, state: &mut State, _operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        state.return_routine(1)
     }
 
     fn name(&self) -> &'static str {
@@ -990,9 +1296,8 @@ impl Instruction for RTrue {
 pub struct RFalse;
 
 impl Instruction for RFalse {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        let instr = Ret;
-        instr.execute(state, vec![0])
+    fn execute(&self, state: &mut State, _operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        state.return_routine(0)
     }
 
     fn name(&self) -> &'static str {
@@ -1008,10 +1313,9 @@ impl Instruction for RFalse {
 pub struct RetPopped;
 
 impl Instruction for RetPopped {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        let value = state.mem.load(0, &mut state.frame)?; // Pop from stack
-        let instr = Ret;
-        instr.execute(state, vec![value])
+    fn execute(&self, state: &mut State, _operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let value = state.read_variable(0)?; // Pop from stack
+        state.return_routine(value)
     }
 
     fn name(&self) -> &'static str {
@@ -1023,18 +1327,60 @@ impl Instruction for RetPopped {
     }
 }
 
+#[derive(Clone)]
+pub struct Pop;
+
+impl Instruction for Pop {
+    fn execute(&self, state: &mut State, _operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        state.read_variable(0)?;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "POP"
+    }
+
+    fn description(&self) -> &'static str {
+        "Throws away the top of the stack (V1-4)."
+    }
+}
+
+#[derive(Clone)]
+pub struct Catch;
+
+impl Instruction for Catch {
+    fn execute(&self, state: &mut State, _operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        // The number of calls under the current routine identifies its frame for throw
+        let frame = state.mem.stack.depth() as u16;
+        state.store_result(decoded, frame)
+    }
+
+    fn name(&self) -> &'static str {
+        "CATCH"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stores a value identifying the current routine's stack frame (V5+)."
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
+}
+
 #[derive(Clone)]
 pub struct Restart;
 
 impl Instruction for Restart {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, _operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         // TODO: Implement actual restart (reload memory, reset stack)
         // For now, just reset PC?
         // ZMachine.java reloads memory from backup.
         // We don't have backup memory implemented in State yet.
         println!("RESTART (Not fully implemented)");
-        let _mem = Memory::new(&state.mem.deref().clone()); // Re-init from current mem? No, need original.
-        state.frame = StackFrame::main(&state.mem);
+        let _mem = Memory::new(state.mem.deref()); // Re-init from current mem? No, need original.
+        state.mem.stack.clear();
+        state.frame = StackFrame::main(state.mem.initial_pc() as u32);
         Ok(())
     }
 
@@ -1051,7 +1397,8 @@ impl Instruction for Restart {
 pub struct Quit;
 
 impl Instruction for Quit {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, _operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        state.flush_output();
         state.running = false;
         Ok(())
     }
@@ -1065,17 +1412,17 @@ impl Instruction for Quit {
     }
 }
 
-use crate::zscii::Zscii;
+use crate::zscii::{unicode_to_zscii, zscii_to_unicode, Zscii};
 
 #[derive(Clone)]
 pub struct Print;
 
 impl Instruction for Print {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        let mut zscii = Zscii::new(&state.mem);
-        let s = zscii.get_string(state.frame.pc as u16);
-        state.frame.pc = zscii.get_ptr() as u32;
-        state.zscreen.print(s);
+    fn execute(&self, state: &mut State, _operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        if let Some(addr) = decoded.text {
+            let s = Zscii::new(&state.mem).get_string(addr)?;
+            state.print(&s);
+        }
         Ok(())
     }
 
@@ -1086,18 +1433,20 @@ impl Instruction for Print {
     fn description(&self) -> &'static str {
         "Prints a literal string from the instruction stream."
     }
+
+    fn inline_text(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct PrintRet;
 
 impl Instruction for PrintRet {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
-        let instr_print = Print;
-        instr_print.execute(state, vec![])?;
-        state.zscreen.newline();
-        let instr_ret = Ret;
-        instr_ret.execute(state, vec![1])
+    fn execute(&self, state: &mut State, _operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        Print.execute(state, &[], decoded)?;
+        state.newline();
+        state.return_routine(1)
     }
 
     fn name(&self) -> &'static str {
@@ -1107,17 +1456,21 @@ impl Instruction for PrintRet {
     fn description(&self) -> &'static str {
         "Prints a literal string, a newline, and returns true."
     }
+
+    fn inline_text(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct PrintAddr;
 
 impl Instruction for PrintAddr {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let addr = operands[0];
         let mut zscii = Zscii::new(&state.mem);
-        let s = zscii.get_string(addr);
-        state.zscreen.print(s);
+        let s = zscii.get_string(addr as u32)?;
+        state.print(&s);
         Ok(())
     }
 
@@ -1128,19 +1481,21 @@ impl Instruction for PrintAddr {
     fn description(&self) -> &'static str {
         "Prints a string from the given byte address."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct PrintPAddr;
 
 impl Instruction for PrintPAddr {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
-        let paddr = operands[0];
-        // TODO: Proper packed address logic for V4/V5
-        let addr = paddr * 2; 
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let addr = state.mem.unpack_string(operands[0]);
         let mut zscii = Zscii::new(&state.mem);
-        let s = zscii.get_string(addr);
-        state.zscreen.print(s);
+        let s = zscii.get_string(addr)?;
+        state.print(&s);
         Ok(())
     }
 
@@ -1151,24 +1506,31 @@ impl Instruction for PrintPAddr {
     fn description(&self) -> &'static str {
         "Prints a string from the given packed address."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct PrintObj;
 
 impl Instruction for PrintObj {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let obj_id = operands[0];
+        if !check_object(state, obj_id)? {
+            return Ok(());
+        }
         let name = if state.version <= 3 {
             let mut obj = Object::<u8>::new(&mut state.mem);
-            obj.name(obj_id as u8)
+            obj.name(obj_id as u8)?
         } else {
             let mut obj = Object::<u16>::new(&mut state.mem);
-            obj.name(obj_id)
+            obj.name(obj_id)?
         };
         
         if let Some(s) = name {
-            state.zscreen.print(s);
+            state.print(&s);
         }
         Ok(())
     }
@@ -1180,16 +1542,21 @@ impl Instruction for PrintObj {
     fn description(&self) -> &'static str {
         "Prints the name of the given object."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct PrintChar;
 
 impl Instruction for PrintChar {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let zscii_code = operands[0];
-        // TODO: ZSCII to char conversion
-        state.zscreen.print_char(zscii_code as u8 as char);
+        if let Some(ch) = zscii_to_unicode(&state.mem, zscii_code) {
+            state.print_char(ch);
+        }
         Ok(())
     }
 
@@ -1200,15 +1567,101 @@ impl Instruction for PrintChar {
     fn description(&self) -> &'static str {
         "Prints a single ZSCII character."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+}
+
+#[derive(Clone)]
+pub struct PrintUnicode;
+
+impl Instruction for PrintUnicode {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        if let Some(ch) = char::from_u32(operands[0] as u32) {
+            state.print_char(ch);
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "PRINT_UNICODE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Prints a Unicode character."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+}
+
+#[derive(Clone)]
+pub struct CheckUnicode;
+
+impl Instruction for CheckUnicode {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        // Bit 0: can be printed, bit 1: can be received from the keyboard
+        let result = match char::from_u32(operands[0] as u32) {
+            Some(ch) => {
+                state.zscreen.can_print_char(ch) as u16
+                    | (state.zscreen.can_input_char(ch) as u16) << 1
+            }
+            None => 0,
+        };
+
+        state.store_result(decoded, result)
+    }
+
+    fn name(&self) -> &'static str {
+        "CHECK_UNICODE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stores whether a Unicode character can be printed and input."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct BufferMode;
+
+impl Instruction for BufferMode {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        // Turning buffering off sends out what is already buffered
+        state.flush_output();
+        state.output.buffered = operands[0] != 0;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "BUFFER_MODE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Turns word-wrap buffering of lower window text on or off."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct PrintNum;
 
 impl Instruction for PrintNum {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let num = operands[0];
-        state.zscreen.print_number(num);
+        state.print_number(num);
         Ok(())
     }
 
@@ -1219,14 +1672,18 @@ impl Instruction for PrintNum {
     fn description(&self) -> &'static str {
         "Prints a signed 16-bit integer."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct NewLine;
 
 impl Instruction for NewLine {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        state.zscreen.newline();
+    fn execute(&self, state: &mut State, _operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        state.newline();
         Ok(())
     }
 
@@ -1243,21 +1700,18 @@ impl Instruction for NewLine {
 pub struct Sread;
 
 impl Instruction for Sread {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let text_buffer = operands[0];
-        let parse_buffer = operands[1];
         
+        state.flush_output();
+        state.output.reset_pager();
         let input = state.zscreen.readline();
         // TODO: Full Sread implementation (writing to buffer, tokenizing)
         // For now, just a placeholder
         println!("SREAD: {} into 0x{:X}", input, text_buffer);
         
-        if state.version >= 4 {
-            let result_var = state.next_u8();
-            state.store_variable(result_var, 10)?; // 10 is typically the terminating character (newline)
-        }
-        
-        Ok(())
+        // 10 is typically the terminating character (newline)
+        state.store_result(decoded, 10)
     }
 
     fn name(&self) -> &'static str {
@@ -1267,16 +1721,41 @@ impl Instruction for Sread {
     fn description(&self) -> &'static str {
         "Reads a line of input."
     }
+
+    // V4 added the optional time and routine for timed input
+    fn operand_count(&self, version: u8) -> RangeInclusive<usize> {
+        match version {
+            1..=3 => 2..=2,
+            4 => 2..=4,
+            _ => 1..=4,
+        }
+    }
+
+    fn stores(&self, version: u8) -> bool {
+        version >= 5
+    }
 }
 
 #[derive(Clone)]
 pub struct ReadChar;
 
 impl Instruction for ReadChar {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        let ch = state.zscreen.read();
-        let result_var = state.next_u8();
-        state.store_variable(result_var, ch as u16)
+    fn execute(&self, state: &mut State, _operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        state.flush_output();
+        state.output.reset_pager();
+        let zscii_code = loop {
+            match state.zscreen.read_input() {
+                Input::Key(ch) => break unicode_to_zscii(&state.mem, ch).unwrap_or(b'?' as u16),
+                Input::Click { y, x, double } => {
+                    if !state.in_mouse_window(y, x) {
+                        continue;
+                    }
+                    state.mem.set_click_position(y, x);
+                    break if double { 253 } else { 254 };
+                }
+            }
+        };
+        state.store_result(decoded, zscii_code)
     }
 
     fn name(&self) -> &'static str {
@@ -1286,14 +1765,77 @@ impl Instruction for ReadChar {
     fn description(&self) -> &'static str {
         "Reads a single character from the input."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=3
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct ReadMouse;
+
+impl Instruction for ReadMouse {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let array = operands[0];
+        let mouse = state.zscreen.mouse_state();
+        state.mem.checked_write_u16(array, mouse.y)?;
+        state.mem.checked_write_u16(array + 2, mouse.x)?;
+        state.mem.checked_write_u16(array + 4, mouse.buttons)?;
+        state.mem.checked_write_u16(array + 6, mouse.menu)?;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "READ_MOUSE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Reads the mouse position, buttons and menu selection into a table (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+}
+
+#[derive(Clone)]
+pub struct MouseWindow;
+
+impl Instruction for MouseWindow {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        // -1 removes the constraint, so the mouse can be used anywhere
+        state.mouse_window = match operands[0] {
+            0xFFFF => 0xFFFF,
+            window => state.windows.resolve(window)? as u16,
+        };
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "MOUSE_WINDOW"
+    }
+
+    fn description(&self) -> &'static str {
+        "Constrains mouse clicks to a window (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct SplitWindow;
 
 impl Instruction for SplitWindow {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let lines = operands[0];
+        state.windows.split(lines);
+        state.flush_output();
         state.zscreen.split_window(lines);
         Ok(())
     }
@@ -1305,15 +1847,21 @@ impl Instruction for SplitWindow {
     fn description(&self) -> &'static str {
         "Splits the screen into two windows."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct SetWindow;
 
 impl Instruction for SetWindow {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
-        let window = operands[0];
-        state.zscreen.set_window(window);
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let window = state.windows.resolve(operands[0])?;
+        state.windows.current = window;
+        state.flush_output();
+        state.zscreen.set_window(window as u16);
         Ok(())
     }
 
@@ -1324,14 +1872,32 @@ impl Instruction for SetWindow {
     fn description(&self) -> &'static str {
         "Sets the current window for output."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct EraseWindow;
 
 impl Instruction for EraseWindow {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let window = operands[0];
+        state.windows.erase(window)?;
+        if state.version == 6 {
+            let erased = match window as i16 {
+                -1 | -2 => 0..crate::window::WINDOW_COUNT,
+                _ => {
+                    let num = state.windows.resolve(window)?;
+                    num..num + 1
+                }
+            };
+            for num in erased {
+                state.graphics.erase(num);
+            }
+        }
+        state.flush_output();
         state.zscreen.erase_window(window);
         Ok(())
     }
@@ -1343,15 +1909,26 @@ impl Instruction for EraseWindow {
     fn description(&self) -> &'static str {
         "Erases the specified window."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct MoveCursor;
 
 impl Instruction for MoveCursor {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let line = operands[0];
         let column = operands[1];
+        // V6 takes an optional window, and a negative line turns the cursor off/on
+        let window = state.windows.resolve(operands.get(2).copied().unwrap_or(0xfffd))?;
+        if (line as i16) > 0 {
+            let w = state.windows.get_mut(window);
+            w.cursor_y = line;
+            w.cursor_x = column;
+        }
         state.zscreen.move_cursor(column as u8, line as u8);
         Ok(())
     }
@@ -1363,15 +1940,496 @@ impl Instruction for MoveCursor {
     fn description(&self) -> &'static str {
         "Moves the cursor to the specified position."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=3
+    }
+}
+
+#[derive(Clone)]
+pub struct SoundEffect;
+
+impl Instruction for SoundEffect {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let number = operands.first().copied().unwrap_or(1);
+        if number < 3 {
+            // Built-in bleeps, the other operands are ignored
+            state.zscreen.beep(number);
+            return Ok(());
+        }
+
+        let effect = match operands.get(1).copied().unwrap_or(2) {
+            1 => ZSoundEffect::Prepare,
+            2 => ZSoundEffect::Start,
+            3 => ZSoundEffect::Stop,
+            4 => ZSoundEffect::Finish,
+            _ => return Ok(()),
+        };
+        // Low byte is the volume, high byte the number of repeats (V5+)
+        let volume_repeats = operands.get(2).copied().unwrap_or(0x00ff);
+        let volume = volume_repeats as u8;
+        let repeats = ((volume_repeats >> 8) as u8).max(1);
+
+        match effect {
+            ZSoundEffect::Start => {
+                // Starting a sound replaces any pending end-of-sound routine
+                state.sound_routine = match operands.get(3) {
+                    Some(&routine) if state.version >= 5 && routine != 0 => Some((number, routine)),
+                    _ => None,
+                };
+            }
+            ZSoundEffect::Stop | ZSoundEffect::Finish => {
+                // The routine is not called if the sound is stopped early
+                if matches!(state.sound_routine, Some((n, _)) if n == number) {
+                    state.sound_routine = None;
+                }
+            }
+            ZSoundEffect::Prepare => {}
+        }
+
+        state.zscreen.sound_effect(number, effect, volume, repeats);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "SOUND_EFFECT"
+    }
+
+    fn description(&self) -> &'static str {
+        "Plays, prepares or stops a sound effect."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        0..=4
+    }
+}
+
+// Updates a window's model and tells the frontend about it
+fn update_window(state: &mut State, num: u16, f: impl FnOnce(&mut crate::window::Window)) -> Result<(), Error> {
+    let num = state.windows.resolve(num)?;
+    let window = state.windows.get_mut(num);
+    f(window);
+    state.zscreen.window_changed(num as u16, window);
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct SetFont;
+
+impl Instruction for SetFont {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        let font = operands[0];
+        // V6 can name the window, otherwise it's the current one
+        let num = match operands.get(1) {
+            Some(&window) if state.version == 6 => state.windows.resolve(window)?,
+            _ => state.windows.current,
+        };
+        let previous = state.windows.get(num).font;
+
+        // Font 0 just asks for the current font
+        let result = if font == 0 {
+            previous
+        } else if state.zscreen.font_available(font) {
+            state.windows.get_mut(num).font = font;
+            state.zscreen.set_font(num as u16, font);
+            previous
+        } else {
+            0
+        };
+
+        state.store_result(decoded, result)
+    }
+
+    fn name(&self) -> &'static str {
+        "SET_FONT"
+    }
+
+    fn description(&self) -> &'static str {
+        "Sets the font, storing the previous one, or 0 if the font is unavailable."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct MoveWindow;
+
+impl Instruction for MoveWindow {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let (y, x) = (operands[1], operands[2]);
+        update_window(state, operands[0], |w| {
+            w.y = y;
+            w.x = x;
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "MOVE_WINDOW"
+    }
+
+    fn description(&self) -> &'static str {
+        "Moves a window to the given position (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        3..=3
+    }
+}
+
+#[derive(Clone)]
+pub struct WindowSize;
+
+impl Instruction for WindowSize {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let (height, width) = (operands[1], operands[2]);
+        update_window(state, operands[0], |w| {
+            w.height = height;
+            w.width = width;
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "WINDOW_SIZE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Changes the size of a window (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        3..=3
+    }
+}
+
+#[derive(Clone)]
+pub struct WindowStyle;
+
+impl Instruction for WindowStyle {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let flags = operands[1];
+        let operation = operands.get(2).copied().unwrap_or(0);
+        update_window(state, operands[0], |w| {
+            w.attributes = match operation {
+                0 => flags,
+                1 => w.attributes | flags,
+                2 => w.attributes & !flags,
+                _ => w.attributes ^ flags,
+            };
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "WINDOW_STYLE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Sets, clears or toggles a window's attributes (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=3
+    }
+}
+
+#[derive(Clone)]
+pub struct SetMargins;
+
+impl Instruction for SetMargins {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let (left, right) = (operands[0], operands[1]);
+        update_window(state, operands.get(2).copied().unwrap_or(0xfffd), |w| {
+            w.left_margin = left;
+            w.right_margin = right;
+            // the cursor is moved inside the new margins
+            if w.cursor_x <= left || w.cursor_x > w.width.saturating_sub(right) {
+                w.cursor_x = left + 1;
+            }
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "SET_MARGINS"
+    }
+
+    fn description(&self) -> &'static str {
+        "Sets the left and right margins of a window (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=3
+    }
+}
+
+#[derive(Clone)]
+pub struct GetWindProp;
+
+impl Instruction for GetWindProp {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        let window = state.windows.resolve(operands[0])?;
+        let prop = operands[1];
+        let value = state.windows.get(window).property(prop).ok_or_else(|| {
+            Error::illegal(format!("Illegal window property {}", prop))
+        })?;
+
+        state.store_result(decoded, value)
+    }
+
+    fn name(&self) -> &'static str {
+        "GET_WIND_PROP"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stores the value of a window property (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct PutWindProp;
+
+impl Instruction for PutWindProp {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let (prop, value) = (operands[1], operands[2]);
+        let mut legal = true;
+        update_window(state, operands[0], |w| legal = w.set_property(prop, value))?;
+        if legal {
+            Ok(())
+        } else {
+            Err(Error::illegal(format!("Illegal window property {}", prop)))
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "PUT_WIND_PROP"
+    }
+
+    fn description(&self) -> &'static str {
+        "Sets the value of a window property (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        3..=3
+    }
+}
+
+#[derive(Clone)]
+pub struct ScrollWindow;
+
+impl Instruction for ScrollWindow {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let window = state.windows.resolve(operands[0])?;
+        state.zscreen.scroll_window(window as u16, operands[1] as i16);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "SCROLL_WINDOW"
+    }
+
+    fn description(&self) -> &'static str {
+        "Scrolls a window up or down by a number of pixels (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+}
+
+//(y, x) operands for the picture opcodes, defaulting to the cursor position
+fn picture_position(state: &State, operands: &[u16]) -> (u16, u16) {
+    let window = state.windows.current();
+    match (operands.get(1).copied().unwrap_or(0), operands.get(2).copied().unwrap_or(0)) {
+        (0, 0) => (window.cursor_y, window.cursor_x),
+        (0, x) => (window.cursor_y, x),
+        (y, 0) => (y, window.cursor_x),
+        (y, x) => (y, x),
+    }
+}
+
+#[derive(Clone)]
+pub struct DrawPicture;
+
+impl Instruction for DrawPicture {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let (y, x) = picture_position(state, operands);
+        let num = state.windows.current;
+        let window = *state.windows.current();
+        let screen = (state.mem.screen_height_units(), state.mem.screen_width_units());
+        let picture = operands[0] as u32;
+        // Unavailable pictures are ignored
+        if state.graphics.image(state.blorb.as_ref(), picture)?.is_some() {
+            if let Some(fb) = state.graphics.draw(picture, num, &window, screen, (y, x)) {
+                state.zscreen.framebuffer_updated(num as u16, fb);
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "DRAW_PICTURE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Draws a picture in the current window (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=3
+    }
+}
+
+#[derive(Clone)]
+pub struct PictureData;
+
+impl Instruction for PictureData {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        let picture = operands[0];
+        let array = operands[1];
+        let second = array
+            .checked_add(2)
+            .ok_or_else(|| Error::out_of_bounds(array as u32 + 2, "table runs past the end of memory"))?;
+
+        let available = if picture == 0 {
+            // number of pictures and the release number of the picture file
+            let (count, release) = match &state.blorb {
+                Some(blorb) => (
+                    blorb.resources().filter(|r| r.usage == Usage::Pict).count() as u16,
+                    blorb.release.unwrap_or(0),
+                ),
+                None => (0, 0),
+            };
+            state.mem.checked_write_u16(array, count)?;
+            state.mem.checked_write_u16(second, release)?;
+            count > 0
+        } else {
+            match state.graphics.image(state.blorb.as_ref(), picture as u32)? {
+                Some(image) => {
+                    let (height, width) = (image.height, image.width);
+                    state.mem.checked_write_u16(array, height)?;
+                    state.mem.checked_write_u16(second, width)?;
+                    true
+                }
+                None => false,
+            }
+        };
+
+        state.branch(decoded, available)
+    }
+
+    fn name(&self) -> &'static str {
+        "PICTURE_DATA"
+    }
+
+    fn description(&self) -> &'static str {
+        "Stores the size of a picture and branches if it is available (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct ErasePicture;
+
+impl Instruction for ErasePicture {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        let (y, x) = picture_position(state, operands);
+        let num = state.windows.current;
+        let window = *state.windows.current();
+        let screen = (state.mem.screen_height_units(), state.mem.screen_width_units());
+        let graphics = &mut state.graphics;
+        let (height, width) = match graphics.image(state.blorb.as_ref(), operands[0] as u32)? {
+            Some(image) => (image.height, image.width),
+            None => return Ok(()),
+        };
+        let fb = graphics.framebuffer_mut(num, &window, screen);
+        fb.fill(y, x, height, width, crate::graphics::background(&window));
+        state.zscreen.framebuffer_updated(num as u16, fb);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "ERASE_PICTURE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Erases the area a picture would cover to the background colour (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=3
+    }
+}
+
+#[derive(Clone)]
+pub struct PictureTable;
+
+impl Instruction for PictureTable {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        // A hint to preload the zero-terminated list of pictures, so decode them now. Being
+        // only a hint, a picture which won't decode is left for draw_picture to report
+        let mut addr = operands[0] as u32;
+        loop {
+            let picture = state.mem.fetch_u16(addr)?;
+            if picture == 0 {
+                break;
+            }
+            let _ = state.graphics.image(state.blorb.as_ref(), picture as u32);
+            addr += 2;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "PICTURE_TABLE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Gives the interpreter a list of pictures which will soon be drawn (V6)."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct SetColor;
 
 impl Instruction for SetColor {
-    fn execute(&self, _state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        // TODO: Implement color support in ZScreen
-        Ok(())
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        // Colour 0 keeps the current one; V6 can name the window, otherwise it's the current one
+        let (foreground, background) = (operands[0], operands[1]);
+        let window = match operands.get(2) {
+            Some(&window) if state.version == 6 => window,
+            _ => 0xfffd,
+        };
+        // Text already output is in the old colours
+        state.flush_output();
+        update_window(state, window, |w| {
+            let (old_foreground, old_background) = (w.colour & 0xff, w.colour >> 8);
+            let foreground = if foreground == 0 { old_foreground } else { foreground & 0xff };
+            let background = if background == 0 { old_background } else { background & 0xff };
+            w.colour = background << 8 | foreground;
+        })
     }
 
     fn name(&self) -> &'static str {
@@ -1381,23 +2439,56 @@ impl Instruction for SetColor {
     fn description(&self) -> &'static str {
         "Sets the foreground and background colors."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=3
+    }
+}
+
+#[derive(Clone)]
+pub struct SetTextStyle;
+
+impl Instruction for SetTextStyle {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
+        // Style 0 is roman, anything else is combined with the current style (8.7.1.1)
+        let style = operands[0];
+        state.flush_output();
+        update_window(state, 0xfffd, |w| {
+            w.text_style = if style == 0 { 0 } else { w.text_style | style };
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "SET_TEXT_STYLE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Sets the text style of the current window: roman, reverse video, bold, italic or fixed pitch."
+    }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct Random;
 
 impl Instruction for Random {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let range = operands[0] as i16;
-        let result = if range <= 0 {
-            state.zscreen.random(0); // Seed
-            0
+        let result = if range > 0 {
+            state.rng.random(range as u16)
         } else {
-            state.zscreen.random(range as u16)
+            if range == 0 {
+                state.rng.reseed();
+            } else {
+                state.rng.seed(range.unsigned_abs());
+            }
+            0
         };
         
-        let result_var = state.next_u8();
-        state.store_variable(result_var, result)
+        state.store_result(decoded, result)
     }
 
     fn name(&self) -> &'static str {
@@ -1407,72 +2498,87 @@ impl Instruction for Random {
     fn description(&self) -> &'static str {
         "Generates a random number."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn stores(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Save;
 
 impl Instruction for Save {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        // TODO: Implement full serialization
-        // For now, just a placeholder return false (failed)
-        // V3 branch logic?
-        // ZMachine.java: if(V4) BADOP... Branch(scr.Save(save()));
-        // If V3, SAVE is a branching instruction on success.
-        // If V4, it stores a result.
-        
-        // Let's assume V3 for now as we did for other branching
-        // TODO: Proper version check and branch vs store logic
-        
-        if state.version <= 3 {
-             state.branch(false); // Fail for now
-        } else {
-             let result_var = state.next_u8();
-             state.store_variable(result_var, 0)?; // 0 = failure
-        }
-        Ok(())
+    fn execute(&self, state: &mut State, _operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        // Fail for now: V1-3 branch on success, V4 stores 0 for failure
+        state.store_result(decoded, 0)?;
+        state.branch(decoded, false)
     }
 
     fn name(&self) -> &'static str {
         "SAVE"
     }
 
+    fn operand_count(&self, version: u8) -> RangeInclusive<usize> {
+        // the EXT forms in V5+ can name a table to save or restore
+        if version >= 5 { 0..=3 } else { 0..=0 }
+    }
+
     fn description(&self) -> &'static str {
         "Saves the game state."
     }
+
+    fn stores(&self, version: u8) -> bool {
+        version >= 4
+    }
+
+    fn branches(&self, version: u8) -> bool {
+        version <= 3
+    }
 }
 
 #[derive(Clone)]
 pub struct Restore;
 
 impl Instruction for Restore {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
-        if state.version <= 3 {
-             state.branch(false); // Fail for now
-        } else {
-             let result_var = state.next_u8();
-             state.store_variable(result_var, 0)?; // 0 = failure
-        }
-        Ok(())
+    fn execute(&self, state: &mut State, _operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+        // Fail for now: V1-3 branch on success, V4 stores 0 for failure
+        state.store_result(decoded, 0)?;
+        state.branch(decoded, false)
     }
 
     fn name(&self) -> &'static str {
         "RESTORE"
     }
 
+    fn operand_count(&self, version: u8) -> RangeInclusive<usize> {
+        // the EXT forms in V5+ can name a table to save or restore
+        if version >= 5 { 0..=3 } else { 0..=0 }
+    }
+
     fn description(&self) -> &'static str {
         "Restores the game state."
     }
+
+    fn stores(&self, version: u8) -> bool {
+        version >= 4
+    }
+
+    fn branches(&self, version: u8) -> bool {
+        version <= 3
+    }
 }
 
 #[derive(Clone)]
 pub struct Verify;
 
 impl Instruction for Verify {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, _operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         // Always verify true for now
-        state.branch(true);
-        Ok(())
+        state.branch(decoded, true)
     }
 
     fn name(&self) -> &'static str {
@@ -1482,13 +2588,17 @@ impl Instruction for Verify {
     fn description(&self) -> &'static str {
         "Verifies the story file integrity."
     }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct ShowStatus;
 
 impl Instruction for ShowStatus {
-    fn execute(&self, state: &mut State, _operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, _operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         // Only valid in V3
         if state.version <= 3 {
             // Logic to render status line
@@ -1506,7 +2616,7 @@ impl Instruction for ShowStatus {
     }
 
     fn description(&self) -> &'static str {
-        "Updates the status line (V3 only)."
+        "Updates the status line (V3 only, a no-op otherwise)."
     }
 }
 
@@ -1514,7 +2624,7 @@ impl Instruction for ShowStatus {
 pub struct Je;
 
 impl Instruction for Je {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let first = operands[0];
         let mut match_found = false;
         
@@ -1525,8 +2635,7 @@ impl Instruction for Je {
             }
         }
         
-        state.branch(match_found);
-        Ok(())
+        state.branch(decoded, match_found)
     }
 
     fn name(&self) -> &'static str {
@@ -1536,16 +2645,23 @@ impl Instruction for Je {
     fn description(&self) -> &'static str {
         "Jumps if the first operand is equal to any of the subsequent operands."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=4
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Jz;
 
 impl Instruction for Jz {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0];
-        state.branch(a == 0);
-        Ok(())
+        state.branch(decoded, a == 0)
     }
 
     fn name(&self) -> &'static str {
@@ -1555,17 +2671,24 @@ impl Instruction for Jz {
     fn description(&self) -> &'static str {
         "Jumps if the operand is zero."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Jl;
 
 impl Instruction for Jl {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0] as i16;
         let b = operands[1] as i16;
-        state.branch(a < b);
-        Ok(())
+        state.branch(decoded, a < b)
     }
 
     fn name(&self) -> &'static str {
@@ -1575,17 +2698,24 @@ impl Instruction for Jl {
     fn description(&self) -> &'static str {
         "Jumps if the first operand is less than the second (signed)."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Jg;
 
 impl Instruction for Jg {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0] as i16;
         let b = operands[1] as i16;
-        state.branch(a > b);
-        Ok(())
+        state.branch(decoded, a > b)
     }
 
     fn name(&self) -> &'static str {
@@ -1595,21 +2725,23 @@ impl Instruction for Jg {
     fn description(&self) -> &'static str {
         "Jumps if the first operand is greater than the second (signed)."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Jump;
 
 impl Instruction for Jump {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], _decoded: &Decoded) -> Result<(), Error> {
         let offset = operands[0] as i16;
-        // PC is currently pointing to the next instruction (after fetch/decode).
-        // JUMP offset is relative to the *operand* start?
-        // ZMachine.java: f.pc = f.pc - 2 + ((short)a);
-        // My next_u16 increments PC by 2.
-        // So state.frame.pc is (Instr + 1 + 2).
-        // If we subtract 2, we get address of operand.
-        // So state.frame.pc = (state.frame.pc - 2) + offset.
+        // The PC is already past the instruction, and the offset counts from there, less 2
         let pc = state.frame.pc as i32;
         state.frame.pc = (pc - 2 + offset as i32) as u32;
         Ok(())
@@ -1622,17 +2754,20 @@ impl Instruction for Jump {
     fn description(&self) -> &'static str {
         "Unconditional jump to the given label (offset)."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        1..=1
+    }
 }
 
 #[derive(Clone)]
 pub struct Test;
 
 impl Instruction for Test {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let a = operands[0];
         let b = operands[1];
-        state.branch((a & b) == b);
-        Ok(())
+        state.branch(decoded, (a & b) == b)
     }
 
     fn name(&self) -> &'static str {
@@ -1642,52 +2777,40 @@ impl Instruction for Test {
     fn description(&self) -> &'static str {
         "Jumps if all flags in the second operand are set in the first operand."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[derive(Clone)]
 pub struct Jin;
 
 impl Instruction for Jin {
-    fn execute(&self, state: &mut State, operands: Vec<u16>) -> Result<(), Error> {
+    fn execute(&self, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
         let obj_a = operands[0];
         let obj_b = operands[1];
         
-        // TODO: Move this logic to a proper Object handling module
-        // Constants depend on version
-        let v3 = state.version <= 3;
-        let obj_size = if v3 { 9 } else { 14 };
-        let parent_offset = if v3 { 4 } else { 6 };
-        
-        // Calculate Object Table locations
-        // ZMachine.java: object_ptr = default_prop_ptr + obj.PropMax * 2 - obj.Size;
-        // But simpler: Mem has object_table() address.
-        // The object table header is 31 words (defaults).
-        // Objects start after that.
-        // But object numbering starts at 1.
-        
-        let object_table_base = state.mem.object_table();
-        // Skip 31*2 bytes of default properties (62 bytes) for V3
-        // Or 63*2 bytes (126 bytes) for V4+
-        let prop_max = if v3 { 31 } else { 63 };
-        let objects_start = object_table_base + (prop_max * 2);
-        
-        // Address of Obj A
-        // Objects are 1-indexed
-        if obj_a == 0 {
-             state.branch(false);
-             return Ok(());
+        // Object 0 is inside nothing
+        if obj_a == 0 || !check_object(state, obj_a)? {
+            return state.branch(decoded, false);
         }
-        
-        let addr = objects_start + ((obj_a - 1) * obj_size);
-        
-        let parent = if v3 {
-            state.mem.read_u8(addr + parent_offset) as u16
+
+        let inside = if state.version <= 3 {
+            let obj = Object::<u8>::new(&mut state.mem);
+            match u8::try_from(obj_b) {
+                Ok(b) => obj.inside(obj_a as u8, b)?,
+                Err(_) => false,
+            }
         } else {
-            state.mem.read_u16(addr + parent_offset)
+            let obj = Object::<u16>::new(&mut state.mem);
+            obj.inside(obj_a, obj_b)?
         };
-        
-        state.branch(parent == obj_b);
-        Ok(())
+        state.branch(decoded, inside)
     }
 
     fn name(&self) -> &'static str {
@@ -1697,6 +2820,14 @@ impl Instruction for Jin {
     fn description(&self) -> &'static str {
         "Jumps if object a is inside object b (i.e. parent(a) == b)."
     }
+
+    fn operand_count(&self, _version: u8) -> RangeInclusive<usize> {
+        2..=2
+    }
+
+    fn branches(&self, _version: u8) -> bool {
+        true
+    }
 }
 
 #[cfg(test)]
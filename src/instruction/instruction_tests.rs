@@ -1,17 +1,20 @@
 use super::*;
-use crate::zscreen::ZScreen;
+use crate::decode::decode_tail;
+use crate::zscreen::{Input, MouseState, SoundEffect as ZSoundEffect, ZScreen};
 
 use std::sync::{Arc, Mutex};
 
 // Mock Screen
 struct MockScreen {
     output: Arc<Mutex<String>>,
+    inputs: Arc<Mutex<Vec<Input>>>,
 }
 
 impl MockScreen {
     fn new(output: Arc<Mutex<String>>) -> Self {
         MockScreen {
             output,
+            inputs: Arc::new(Mutex::new(vec![])),
         }
     }
 }
@@ -26,7 +29,6 @@ impl ZScreen for MockScreen {
     fn read(&self) -> char { ' ' }
     fn readline(&self) -> String { String::new() }
     fn exit(&self) {}
-    fn random(&self, _limit: u16) -> u16 { 0 }
     fn set_status(&self, _status: String) {}
     fn get_width(&self) {}
     fn get_height(&self) {}
@@ -43,12 +45,35 @@ impl ZScreen for MockScreen {
     fn print_char(&self, _char: char) {
         self.output.lock().unwrap().push(_char);
     }
+    fn beep(&self, number: u16) {
+        self.output.lock().unwrap().push_str(&format!("[beep {}]", number));
+    }
+    fn sound_effect(&self, number: u16, effect: ZSoundEffect, volume: u8, repeats: u8) {
+        self.output
+            .lock()
+            .unwrap()
+            .push_str(&format!("[sound {} {:?} {} {}]", number, effect, volume, repeats));
+    }
+    fn read_input(&self) -> Input {
+        let mut inputs = self.inputs.lock().unwrap();
+        if inputs.is_empty() {
+            Input::Key(self.read())
+        } else {
+            inputs.remove(0)
+        }
+    }
+    fn mouse_state(&self) -> MouseState {
+        MouseState { y: 10, x: 20, buttons: 1, menu: 0x0102 }
+    }
 }
 
 fn create_test_state_with_output() -> (State<'static>, Arc<Mutex<String>>) {
     let mut data = vec![0u8; 4096];
     
     // Setup Header
+    // 0x00: Version 3
+    data[0x00] = 3;
+
     // 0x06: Initial PC = 0x100 (256)
     data[0x06] = 0x01;
     data[0x07] = 0x00;
@@ -57,14 +82,31 @@ fn create_test_state_with_output() -> (State<'static>, Arc<Mutex<String>>) {
     data[0x0C] = 0x02;
     data[0x0D] = 0x00;
 
+    // 0x0E: Static memory from 0x800, so everything below is writable
+    data[0x0E] = 0x08;
+
     let output = Arc::new(Mutex::new(String::new()));
-    (State::new(&data, Box::new(MockScreen::new(output.clone()))), output)
+    (State::new(&data, Box::new(MockScreen::new(output.clone()))).unwrap(), output)
 }
 
 fn create_test_state() -> State<'static> {
     create_test_state_with_output().0
 }
 
+// Runs an instruction as the interpreter would: its store variable, branch and inline
+// text are decoded from memory at the PC, which is moved past them first
+trait Run {
+    fn run(&self, state: &mut State, operands: &[u16]) -> Result<(), Error>;
+}
+
+impl<T: Instruction> Run for T {
+    fn run(&self, state: &mut State, operands: &[u16]) -> Result<(), Error> {
+        let decoded = decode_tail(&state.mem, state.frame.pc, self, state.version);
+        state.frame.pc = decoded.next_pc;
+        self.execute(state, operands, &decoded)
+    }
+}
+
 #[test]
 fn test_print() {
     let (mut state, output) = create_test_state_with_output();
@@ -76,17 +118,31 @@ fn test_print() {
     state.mem.write_u8(0x103, 0x85);
     
     let instr = Print;
-    instr.execute(&mut state, vec![]).unwrap();
+    instr.run(&mut state, &[]).unwrap();
     
     assert_eq!(state.frame.pc, 0x104);
     assert_eq!(*output.lock().unwrap(), "hello");
 }
 
+#[test]
+fn test_show_status_is_a_no_op_after_v3() {
+    let mut state = create_screen_state(5, vec![]);
+    ShowStatus.run(&mut state, &[]).unwrap();
+    assert_eq!(state.frame.pc, 0x100);
+}
+
+#[test]
+fn test_print_paddr_past_end_of_story() {
+    let mut state = create_screen_state(5, vec![]);
+    let err = PrintPAddr.run(&mut state, &[0x1000]).unwrap_err();
+    assert!(matches!(err, Error::OutOfBounds { addr: 0x4000, .. }));
+}
+
 #[test]
 fn test_split_window() {
-    let (mut state, output) = create_test_state_with_output();
+    let (mut state, _output) = create_test_state_with_output();
     let instr = SplitWindow;
-    instr.execute(&mut state, vec![10]).unwrap();
+    instr.run(&mut state, &[10]).unwrap();
     // Verification would require checking side effects on MockScreen.
     // For now, just ensure it runs without error.
     // We can add logging to MockScreen if we really want to verify.
@@ -99,7 +155,7 @@ fn test_verify() {
     state.mem[pc as u16] = 0xCA; // Branch true +10
 
     let instr = Verify;
-    instr.execute(&mut state, vec![]).unwrap();
+    instr.run(&mut state, &[]).unwrap();
     // Verify always branches true currently
     assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
 }
@@ -114,18 +170,40 @@ fn test_random() {
     state.mem[pc as u16] = 0x10; 
 
     let instr = Random;
-    instr.execute(&mut state, vec![10]).unwrap();
+    instr.run(&mut state, &[10]).unwrap();
     
-    // Result should be 0 (from MockScreen default)
     let result = state.mem.read_u16(0x220);
-    assert_eq!(result, 0);
+    assert!((1..=10).contains(&result));
+}
+
+#[test]
+fn test_random_seed() {
+    let mut state = create_test_state();
+    let instr = Random;
+
+    // RANDOM -4 seeds the sequence 1, 2, 3, 4 and stores 0
+    let pc = state.frame.pc as u16;
+    for i in 0..4 {
+        state.mem[pc + i] = 0x10;
+    }
+    instr.run(&mut state, &[(-4i16) as u16]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 0);
+
+    instr.run(&mut state, &[10]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 1);
+    instr.run(&mut state, &[10]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 2);
+
+    // RANDOM 0 goes back to random mode
+    instr.run(&mut state, &[0]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 0);
 }
 
 #[test]
 fn test_print_num() {
     let (mut state, output) = create_test_state_with_output();
     let instr = PrintNum;
-    instr.execute(&mut state, vec![1234]).unwrap();
+    instr.run(&mut state, &[1234]).unwrap();
     assert_eq!(*output.lock().unwrap(), "1234");
 }
 
@@ -133,7 +211,7 @@ fn test_print_num() {
 fn test_new_line() {
     let (mut state, output) = create_test_state_with_output();
     let instr = NewLine;
-    instr.execute(&mut state, vec![]).unwrap();
+    instr.run(&mut state, &[]).unwrap();
     assert_eq!(*output.lock().unwrap(), "\n");
 }
 
@@ -157,7 +235,7 @@ fn test_add() {
     state.mem[pc as u16] = 0x10; // Store to global 0x10
 
     let instr = Add;
-    instr.execute(&mut state, vec![op1, op2]).unwrap();
+    instr.run(&mut state, &[op1, op2]).unwrap();
 
     // Check result in global 0x10
     // Address = Global Table (0x200) + 0x10 * 2 = 0x220
@@ -179,7 +257,7 @@ fn test_add_overflow() {
     state.mem[pc as u16] = 0x10; 
 
     let instr = Add;
-    instr.execute(&mut state, vec![op1, op2]).unwrap();
+    instr.run(&mut state, &[op1, op2]).unwrap();
 
     let result = state.mem.read_u16(0x220);
     // Result is stored as u16 bit pattern of i16 result
@@ -196,7 +274,7 @@ fn test_sub() {
     state.mem[pc as u16] = 0x10; 
 
     let instr = Sub;
-    instr.execute(&mut state, vec![op1, op2]).unwrap();
+    instr.run(&mut state, &[op1, op2]).unwrap();
 
     let result = state.mem.read_u16(0x220);
     assert_eq!(result, 15);
@@ -212,7 +290,7 @@ fn test_mul() {
     state.mem[pc as u16] = 0x10; 
 
     let instr = Mul;
-    instr.execute(&mut state, vec![op1, op2]).unwrap();
+    instr.run(&mut state, &[op1, op2]).unwrap();
 
     let result = state.mem.read_u16(0x220);
     assert_eq!(result as i16, -20);
@@ -228,7 +306,7 @@ fn test_div() {
     state.mem[pc as u16] = 0x10; 
 
     let instr = Div;
-    instr.execute(&mut state, vec![op1, op2]).unwrap();
+    instr.run(&mut state, &[op1, op2]).unwrap();
 
     let result = state.mem.read_u16(0x220);
     assert_eq!(result as i16, -4);
@@ -244,7 +322,7 @@ fn test_mod() {
     state.mem[pc as u16] = 0x10; 
 
     let instr = Mod;
-    instr.execute(&mut state, vec![op1, op2]).unwrap();
+    instr.run(&mut state, &[op1, op2]).unwrap();
 
     let result = state.mem.read_u16(0x220);
     assert_eq!(result, 3);
@@ -260,7 +338,7 @@ fn test_or() {
     state.mem[pc as u16] = 0x10; 
 
     let instr = Or;
-    instr.execute(&mut state, vec![op1, op2]).unwrap();
+    instr.run(&mut state, &[op1, op2]).unwrap();
 
     let result = state.mem.read_u16(0x220);
     assert_eq!(result, 0b1111);
@@ -276,7 +354,7 @@ fn test_and() {
     state.mem[pc as u16] = 0x10; 
 
     let instr = And;
-    instr.execute(&mut state, vec![op1, op2]).unwrap();
+    instr.run(&mut state, &[op1, op2]).unwrap();
 
     let result = state.mem.read_u16(0x220);
     assert_eq!(result, 0b0100);
@@ -291,7 +369,7 @@ fn test_not() {
     state.mem[pc as u16] = 0x10; 
 
     let instr = Not;
-    instr.execute(&mut state, vec![op1]).unwrap();
+    instr.run(&mut state, &[op1]).unwrap();
 
     let result = state.mem.read_u16(0x220);
     assert_eq!(result, 0xFF00);
@@ -301,7 +379,7 @@ fn test_not() {
 fn test_div_zero() {
     let mut state = create_test_state();
     let instr = Div;
-    let result = instr.execute(&mut state, vec![10, 0]);
+    let result = instr.run(&mut state, &[10, 0]);
     assert!(result.is_err());
 }
 
@@ -321,13 +399,9 @@ fn test_je_match() {
     state.mem[pc as u16] = 0xCA; 
     
     let instr = Je;
-    instr.execute(&mut state, vec![5, 10, 5]).unwrap();
+    instr.run(&mut state, &[5, 10, 5]).unwrap();
     
-    // PC should be: Old PC + 1 (read branch byte) + Offset (10) - 2
-    // Wait, logic says: self.frame.pc = (self.frame.pc as i32 + offset as i32 - 2) as u32;
-    // self.frame.pc was incremented by next_u8() *before* this calculation.
-    // Initial PC (0x100) -> next_u8 (read 0xDA) -> PC is 0x101.
-    // New PC = 0x101 + 10 - 2 = 0x101 + 8 = 0x109.
+    // The offset counts from after the branch byte, less 2: 0x101 + 10 - 2 = 0x109
     assert_eq!(state.frame.pc, 0x109);
 }
 
@@ -340,7 +414,7 @@ fn test_jz() {
     state.mem[pc as u16] = 0xCA; // Branch on true, offset 10
 
     let instr = Jz;
-    instr.execute(&mut state, vec![0]).unwrap();
+    instr.run(&mut state, &[0]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
 
     // JZ 1 -> No Branch
@@ -348,7 +422,7 @@ fn test_jz() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = Jz;
-    instr.execute(&mut state, vec![1]).unwrap();
+    instr.run(&mut state, &[1]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1);
 }
 
@@ -361,7 +435,7 @@ fn test_jl() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = Jl;
-    instr.execute(&mut state, vec![5, 10]).unwrap();
+    instr.run(&mut state, &[5, 10]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
 
     // JL 10, 5 -> No Branch
@@ -369,7 +443,7 @@ fn test_jl() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = Jl;
-    instr.execute(&mut state, vec![10, 5]).unwrap();
+    instr.run(&mut state, &[10, 5]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1);
 }
 
@@ -382,7 +456,7 @@ fn test_jg() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = Jg;
-    instr.execute(&mut state, vec![10, 5]).unwrap();
+    instr.run(&mut state, &[10, 5]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
     
     // JG 5, 10 -> No Branch
@@ -390,7 +464,7 @@ fn test_jg() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = Jg;
-    instr.execute(&mut state, vec![5, 10]).unwrap();
+    instr.run(&mut state, &[5, 10]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1);
 }
 
@@ -409,7 +483,7 @@ fn test_jump() {
     // The instruction impl says: frame.pc = frame.pc - 2 + offset.
     
     let instr = Jump;
-    instr.execute(&mut state, vec![10]).unwrap();
+    instr.run(&mut state, &[10]).unwrap();
     
     // Expected: PC - 2 + 10.
     // But wait, the "PC" in the test context (state.frame.pc) is whatever it was *after* fetch/decode.
@@ -428,7 +502,7 @@ fn test_test() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = Test;
-    instr.execute(&mut state, vec![0b1111, 0b0101]).unwrap();
+    instr.run(&mut state, &[0b1111, 0b0101]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
     
     // TEST 0b0000, 0b0101 -> No Branch
@@ -436,7 +510,7 @@ fn test_test() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = Test;
-    instr.execute(&mut state, vec![0b0000, 0b0101]).unwrap();
+    instr.run(&mut state, &[0b0000, 0b0101]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1);
 }
 
@@ -479,7 +553,7 @@ fn test_get_parent() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = Jin;
-    instr.execute(&mut state, vec![1, 2]).unwrap();
+    instr.run(&mut state, &[1, 2]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
     
     // JIN 2, 1 -> No Branch
@@ -487,7 +561,7 @@ fn test_get_parent() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = Jin;
-    instr.execute(&mut state, vec![2, 1]).unwrap();
+    instr.run(&mut state, &[2, 1]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1);
 }
 
@@ -510,19 +584,19 @@ fn test_call_ret() {
     // If V3, addr = packed * 2. So if we want 0x400, packed is 0x200.
     
     let instr_call = Call;
-    instr_call.execute(&mut state, vec![0x200]).unwrap();
+    instr_call.run(&mut state, &[0x200]).unwrap();
     
     // Check we are in new frame
     // PC should be after header: 0x400 + 1 (count) + 2 (local) = 0x403
     assert_eq!(state.frame.pc, 0x403);
     
     // Check local 1 initialized
-    let local1 = state.frame.read_local(&state.mem.stack, 1);
+    let local1 = state.read_variable(1).unwrap();
     assert_eq!(local1, 0x1234);
     
     // RET 0x5678
     let instr_ret = Ret;
-    instr_ret.execute(&mut state, vec![0x5678]).unwrap();
+    instr_ret.run(&mut state, &[0x5678]).unwrap();
     
     // Check we returned to previous frame
     assert_eq!(state.frame.pc as usize, pc + 1);
@@ -532,12 +606,92 @@ fn test_call_ret() {
     assert_eq!(result, 0x5678);
 }
 
+#[test]
+fn test_call_v4_routine_above_64k() {
+    let mut data = vec![0u8; 0x10800];
+    data[0x00] = 4;
+    data[0x06] = 0x01; // PC at 0x100
+    data[0x0C] = 0x02; // Globals at 0x200
+    data[0x0E] = 0x08; // Static memory at 0x800
+    // Routine at 0x10400 with one local of 0x1234; the same offset below 64K holds 0xDEAD
+    data[0x10400..0x10403].copy_from_slice(&[1, 0x12, 0x34]);
+    data[0x401..0x403].copy_from_slice(&[0xDE, 0xAD]);
+    let mut state = State::new(&data, Box::new(MockScreen::new(Arc::new(Mutex::new(String::new()))))).unwrap();
+
+    Call.run(&mut state, &[0x4100]).unwrap();
+    assert_eq!(state.frame.pc, 0x10403);
+    assert_eq!(state.read_variable(1).unwrap(), 0x1234);
+}
+
+#[test]
+fn test_call_past_end_of_story() {
+    let mut state = create_screen_state(5, vec![]);
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0x10;
+    let err = Call.run(&mut state, &[0x1000]).unwrap_err();
+    assert!(matches!(err, Error::OutOfBounds { addr: 0x4000, .. }));
+}
+
+#[test]
+fn test_branch_returns() {
+    let mut state = create_test_state();
+    state.mem.write_u8(0x400, 0);
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0x10;
+    Call.run(&mut state, &[0x200]).unwrap();
+
+    // JZ 0 ?rtrue returns from the routine instead of jumping
+    state.mem.write_u8(0x401, 0xC1);
+    Jz.run(&mut state, &[0]).unwrap();
+    assert_eq!(state.frame.pc as usize, pc + 1);
+    assert_eq!(state.mem.read_u16(0x220), 1);
+}
+
+#[test]
+fn test_call_zero_stores_false() {
+    let mut state = create_test_state();
+    state.mem.write_u16(0x220, 0xFFFF);
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0x10;
+    Call.run(&mut state, &[0]).unwrap();
+    assert_eq!(state.frame.pc as usize, pc + 1);
+    assert_eq!(state.mem.read_u16(0x220), 0);
+}
+
+#[test]
+fn test_call_n_discards_result() {
+    let mut state = create_test_state();
+    state.mem.write_u8(0x400, 0);
+    let pc = state.frame.pc as usize;
+    CallN.run(&mut state, &[0x200, 7]).unwrap();
+    assert_eq!(state.frame.pc, 0x401);
+    RTrue.run(&mut state, &[]).unwrap();
+    assert_eq!(state.frame.pc as usize, pc);
+    assert!(state.read_variable(0).is_err());
+}
+
+#[test]
+fn test_pop_and_catch() {
+    let mut state = create_test_state();
+    state.store_variable(0, 1).unwrap();
+    state.store_variable(0, 2).unwrap();
+    Pop.run(&mut state, &[]).unwrap();
+    assert_eq!(state.read_variable(0).unwrap(), 1);
+
+    // catch -> G00 inside a routine called from main
+    state.mem.write_u8(0x400, 0);
+    CallN.run(&mut state, &[0x200]).unwrap();
+    state.mem.write_u8(0x401, 0x10);
+    Catch.run(&mut state, &[]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 1);
+}
+
 #[test]
 fn test_quit() {
     let mut state = create_test_state();
     assert!(state.running);
     let instr = Quit;
-    instr.execute(&mut state, vec![]).unwrap();
+    instr.run(&mut state, &[]).unwrap();
     assert!(!state.running);
 }
 
@@ -545,7 +699,7 @@ fn test_quit() {
 fn test_store() {
     let mut state = create_test_state();
     let instr = Store;
-    instr.execute(&mut state, vec![0x10, 42]).unwrap();
+    instr.run(&mut state, &[0x10, 42]).unwrap();
     let result = state.mem.read_u16(0x220); // Global 0x10
     assert_eq!(result, 42);
 }
@@ -559,17 +713,27 @@ fn test_load() {
     state.mem[pc as u16] = 0x11; // Result to global 0x11
 
     let instr = Load;
-    instr.execute(&mut state, vec![0x10]).unwrap();
+    instr.run(&mut state, &[0x10]).unwrap();
     
     let result = state.mem.read_u16(0x222); // Global 0x11
     assert_eq!(result, 42);
 }
 
+#[test]
+fn test_load_high_global() {
+    let mut state = create_screen_state(5, vec![]);
+    state.mem.write_u16(0x320, 42); // Global 0x90, past 0xFF bytes into the table
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0x91;
+    Load.run(&mut state, &[0x90]).unwrap();
+    assert_eq!(state.mem.read_u16(0x322), 42);
+}
+
 #[test]
 fn test_storew() {
     let mut state = create_test_state();
     let instr = StoreW;
-    instr.execute(&mut state, vec![0x300, 2, 1234]).unwrap();
+    instr.run(&mut state, &[0x300, 2, 1234]).unwrap();
     let result = state.mem.read_u16(0x300 + 4);
     assert_eq!(result, 1234);
 }
@@ -578,7 +742,7 @@ fn test_storew() {
 fn test_storeb() {
     let mut state = create_test_state();
     let instr = StoreB;
-    instr.execute(&mut state, vec![0x300, 2, 123]).unwrap();
+    instr.run(&mut state, &[0x300, 2, 123]).unwrap();
     let result = state.mem.read_u8(0x302);
     assert_eq!(result, 123);
 }
@@ -592,7 +756,7 @@ fn test_loadw() {
     state.mem[pc as u16] = 0x10; // Result to global 0x10
 
     let instr = LoadW;
-    instr.execute(&mut state, vec![0x300, 2]).unwrap();
+    instr.run(&mut state, &[0x300, 2]).unwrap();
     
     let result = state.mem.read_u16(0x220);
     assert_eq!(result, 1234);
@@ -607,7 +771,7 @@ fn test_loadb() {
     state.mem[pc as u16] = 0x10; // Result to global 0x10
 
     let instr = LoadB;
-    instr.execute(&mut state, vec![0x300, 2]).unwrap();
+    instr.run(&mut state, &[0x300, 2]).unwrap();
     
     let result = state.mem.read_u16(0x220);
     assert_eq!(result, 123);
@@ -618,10 +782,10 @@ fn test_push_pull() {
     let mut state = create_test_state();
     
     let instr_push = Push;
-    instr_push.execute(&mut state, vec![42]).unwrap();
+    instr_push.run(&mut state, &[42]).unwrap();
     
     let instr_pull = Pull;
-    instr_pull.execute(&mut state, vec![0x10]).unwrap();
+    instr_pull.run(&mut state, &[0x10]).unwrap();
     
     let result = state.mem.read_u16(0x220);
     assert_eq!(result, 42);
@@ -633,11 +797,11 @@ fn test_inc_dec() {
     state.mem.write_u16(0x220, 42); // Global 0x10
     
     let instr_inc = Inc;
-    instr_inc.execute(&mut state, vec![0x10]).unwrap();
+    instr_inc.run(&mut state, &[0x10]).unwrap();
     assert_eq!(state.mem.read_u16(0x220), 43);
     
     let instr_dec = Dec;
-    instr_dec.execute(&mut state, vec![0x10]).unwrap();
+    instr_dec.run(&mut state, &[0x10]).unwrap();
     assert_eq!(state.mem.read_u16(0x220), 42);
 }
 
@@ -651,7 +815,7 @@ fn test_inc_chk() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = IncChk;
-    instr.execute(&mut state, vec![0x10, 42]).unwrap();
+    instr.run(&mut state, &[0x10, 42]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
 }
 
@@ -665,7 +829,7 @@ fn test_dec_chk() {
     state.mem[pc as u16] = 0xCA; 
 
     let instr = DecChk;
-    instr.execute(&mut state, vec![0x10, 42]).unwrap();
+    instr.run(&mut state, &[0x10, 42]).unwrap();
     assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
 }
 
@@ -682,7 +846,7 @@ fn test_set_attr() {
     // Attr 0 is bit 7 of byte 0.
     
     let instr = SetAttr;
-    instr.execute(&mut state, vec![1, 0]).unwrap();
+    instr.run(&mut state, &[1, 0]).unwrap();
     
     let attr_byte = state.mem.read_u8(0x300 + 62);
     assert_eq!(attr_byte, 0x80); // Bit 7 set
@@ -697,7 +861,7 @@ fn test_clear_attr() {
     state.mem.write_u8(0x300 + 62, 0x80);
     
     let instr = ClearAttr;
-    instr.execute(&mut state, vec![1, 0]).unwrap();
+    instr.run(&mut state, &[1, 0]).unwrap();
     
     let attr_byte = state.mem.read_u8(0x300 + 62);
     assert_eq!(attr_byte, 0x00);
@@ -714,16 +878,81 @@ fn test_test_attr() {
     state.mem[pc as u16] = 0xCA; // Branch true +10
 
     let instr = TestAttr;
-    instr.execute(&mut state, vec![1, 0]).unwrap(); // Test attr 0
+    instr.run(&mut state, &[1, 0]).unwrap(); // Test attr 0
     assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2); // Branch taken
     
     let pc = state.frame.pc as usize;
     state.mem[pc as u16] = 0xCA; 
     
-    instr.execute(&mut state, vec![1, 1]).unwrap(); // Test attr 1 (not set)
+    instr.run(&mut state, &[1, 1]).unwrap(); // Test attr 1 (not set)
     assert_eq!(state.frame.pc as usize, pc + 1); // No branch
 }
 
+#[test]
+fn test_bad_attribute() {
+    let mut state = create_test_state();
+    state.version = 5;
+    state.mem.write_u16(0x0A, 0x300);
+    let obj2 = 0x300 + 126 + 14;
+
+    // 288 would be attribute 32 if it were cut to a byte
+    assert!(matches!(
+        SetAttr.run(&mut state, &[2, 288]),
+        Err(Error::BadAttribute { attr: 288, .. })
+    ));
+    assert_eq!(state.mem.read_u8(obj2 + 4), 0);
+    state.version = 3;
+    assert!(matches!(
+        ClearAttr.run(&mut state, &[1, 32]),
+        Err(Error::BadAttribute { attr: 32, .. })
+    ));
+
+    // Permissive, the opcodes do nothing and the test fails
+    state.mem.permissive = true;
+    SetAttr.run(&mut state, &[2, 288]).unwrap();
+    assert_eq!(state.mem.read_u8(obj2 + 4), 0);
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0xCA;
+    TestAttr.run(&mut state, &[1, 0xFFFF]).unwrap();
+    assert_eq!(state.frame.pc as usize, pc + 1);
+}
+
+#[test]
+fn test_v5_objects() {
+    let mut state = create_test_state();
+    state.version = 5;
+    state.mem.write_u16(0x0A, 0x300);
+    // Objects are 14 bytes after 63 default properties: 6 attribute bytes, then word links
+    let obj1 = 0x300 + 126;
+    let obj2 = obj1 + 14;
+
+    SetAttr.run(&mut state, &[2, 40]).unwrap();
+    assert_eq!(state.mem.read_u8(obj2 + 5), 0x80);
+
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0xCA; // Branch true +10
+    TestAttr.run(&mut state, &[2, 40]).unwrap();
+    assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
+
+    ClearAttr.run(&mut state, &[2, 40]).unwrap();
+    assert_eq!(state.mem.read_u8(obj2 + 5), 0);
+
+    InsertObj.run(&mut state, &[2, 1]).unwrap();
+    assert_eq!(state.mem.read_u16(obj2 + 6), 1);
+    assert_eq!(state.mem.read_u16(obj1 + 10), 2);
+
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0xCA;
+    Jin.run(&mut state, &[2, 1]).unwrap();
+    assert_eq!(state.frame.pc as usize, pc + 1 + 10 - 2);
+
+    // Objects past the end of dynamic memory don't exist
+    assert!(matches!(
+        GetParent.run(&mut state, &[300]),
+        Err(Error::BadObject { object: 300, .. })
+    ));
+}
+
 #[test]
 fn test_insert_obj() {
     let mut state = create_test_state();
@@ -733,7 +962,7 @@ fn test_insert_obj() {
     // Insert Obj 2 into Obj 1
     
     let instr = InsertObj;
-    instr.execute(&mut state, vec![2, 1]).unwrap();
+    instr.run(&mut state, &[2, 1]).unwrap();
     
     // Obj 2 Parent (offset 4) should be 1
     assert_eq!(state.mem.read_u8(0x300 + 62 + 9 + 4), 1);
@@ -751,7 +980,7 @@ fn test_remove_obj() {
     state.mem.write_u8(0x300 + 62 + 6, 2);     // Obj 1 child = 2
     
     let instr = RemoveObj;
-    instr.execute(&mut state, vec![2]).unwrap();
+    instr.run(&mut state, &[2]).unwrap();
     
     // Obj 2 Parent should be 0
     assert_eq!(state.mem.read_u8(0x300 + 62 + 9 + 4), 0);
@@ -759,6 +988,37 @@ fn test_remove_obj() {
     assert_eq!(state.mem.read_u8(0x300 + 62 + 6), 0);
 }
 
+#[test]
+fn test_props() {
+    let mut state = create_test_state();
+    state.version = 1;
+    state.mem.write_u16(0x0A, 0x300);
+    state.mem.write_u16(0x300 + 4, 0x0707); // Default property 3
+    state.mem.write_u16(0x300 + 62 + 7, 0x400); // Obj 1 properties
+    // No name; property 5 = 1234; property 2 = AB
+    for (addr, byte) in (0x400..).zip([0x00, 0x25, 0x12, 0x34, 0x02, 0xAB, 0x00]) {
+        state.mem.write_u8(addr, byte);
+    }
+
+    let pc = state.frame.pc;
+    state.mem[pc as u16] = 0x10; // Store to global 0x10
+    GetProp.run(&mut state, &[1, 5]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 0x1234);
+
+    state.frame.pc = pc;
+    GetProp.run(&mut state, &[1, 3]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 0x0707);
+
+    state.frame.pc = pc;
+    GetNextProp.run(&mut state, &[1, 5]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 2);
+
+    PutProp.run(&mut state, &[1, 2, 0x1CD]).unwrap();
+    assert_eq!(state.mem.read_u8(0x405), 0xCD);
+    assert!(matches!(PutProp.run(&mut state, &[1, 3, 0]), Err(Error::Illegal { .. })));
+    assert!(GetNextProp.run(&mut state, &[1, 4]).is_err());
+}
+
 #[test]
 fn test_get_child() {
     let mut state = create_test_state();
@@ -771,7 +1031,7 @@ fn test_get_child() {
     state.mem[pc as u16 + 1] = 0xCA; // Branch true +10
 
     let instr = GetChild;
-    instr.execute(&mut state, vec![1]).unwrap();
+    instr.run(&mut state, &[1]).unwrap();
     
     assert_eq!(state.mem.read_u16(0x220), 2);
     assert_eq!(state.frame.pc as usize, pc + 2 + 10 - 2);
@@ -789,8 +1049,500 @@ fn test_get_sibling() {
     state.mem[pc as u16 + 1] = 0xCA; // Branch true +10
 
     let instr = GetSibling;
-    instr.execute(&mut state, vec![1]).unwrap();
+    instr.run(&mut state, &[1]).unwrap();
     
     assert_eq!(state.mem.read_u16(0x220), 2);
     assert_eq!(state.frame.pc as usize, pc + 2 + 10 - 2);
 }
+
+#[test]
+fn test_print_char() {
+    let (mut state, output) = create_test_state_with_output();
+    let instr = PrintChar;
+    instr.run(&mut state, &[65]).unwrap();
+    instr.run(&mut state, &[170]).unwrap(); // é in the default table
+    instr.run(&mut state, &[13]).unwrap();
+    instr.run(&mut state, &[0]).unwrap(); // null prints nothing
+    assert_eq!(*output.lock().unwrap(), "Aé\n");
+}
+
+#[test]
+fn test_print_unicode() {
+    let (mut state, output) = create_test_state_with_output();
+    let instr = PrintUnicode;
+    instr.run(&mut state, &[0x20AC]).unwrap();
+    assert_eq!(*output.lock().unwrap(), "€");
+}
+
+#[test]
+fn test_check_unicode() {
+    let mut state = create_test_state();
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0x10; // Store to global 0x10
+
+    let instr = CheckUnicode;
+    instr.run(&mut state, &[0x20AC]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 3);
+}
+
+#[test]
+fn test_sound_effect_beep() {
+    let (mut state, output) = create_test_state_with_output();
+    let instr = SoundEffect;
+    instr.run(&mut state, &[]).unwrap();
+    instr.run(&mut state, &[2]).unwrap();
+    assert_eq!(*output.lock().unwrap(), "[beep 1][beep 2]");
+}
+
+#[test]
+fn test_sound_effect_resource() {
+    let (mut state, output) = create_test_state_with_output();
+    let instr = SoundEffect;
+    // Start sound 3 at volume 5, repeated twice; no routine before V5
+    instr.run(&mut state, &[3, 2, 0x0205, 0x200]).unwrap();
+    assert!(state.sound_routine.is_none());
+    instr.run(&mut state, &[3, 3]).unwrap();
+    assert_eq!(*output.lock().unwrap(), "[sound 3 Start 5 2][sound 3 Stop 255 1]");
+}
+
+#[test]
+fn test_sound_effect_routine() {
+    let mut state = create_test_state();
+    state.mem.write_u8(0x00, 5);
+    state.version = 5;
+    // Routine at 0x400 (packed 0x100 in V5) with no locals
+    state.mem.write_u8(0x400, 0);
+
+    let instr = SoundEffect;
+    instr.run(&mut state, &[4, 2, 0x0108, 0x100]).unwrap();
+    assert_eq!(state.sound_routine, Some((4, 0x100)));
+
+    // Stopping the sound cancels the routine
+    instr.run(&mut state, &[4, 3]).unwrap();
+    assert!(state.sound_routine.is_none());
+
+    // Once finished, the routine is called as an interrupt and returns to the same PC
+    instr.run(&mut state, &[4, 2, 0x0108, 0x100]).unwrap();
+    let pc = state.frame.pc;
+    state.poll_sound().unwrap();
+    assert!(state.sound_routine.is_none());
+    assert_eq!(state.frame.pc, 0x401);
+
+    let instr_ret = Ret;
+    instr_ret.run(&mut state, &[1]).unwrap();
+    assert_eq!(state.frame.pc, pc);
+}
+
+// A state with a 640x400 screen, a header extension table at 0x300 and the given inputs
+// queued for the screen to return
+fn create_screen_state(version: u8, inputs: Vec<Input>) -> State<'static> {
+    let mut data = vec![0u8; 4096];
+    data[0x00] = version;
+    data[0x06] = 0x01; // PC at 0x100
+    data[0x0C] = 0x02; // Globals at 0x200
+    data[0x0E] = 0x08; // Static memory at 0x800
+    data[0x36..0x38].copy_from_slice(&0x300u16.to_be_bytes()); // Header extension table
+    data[0x301] = 3;
+    data[0x22..0x24].copy_from_slice(&640u16.to_be_bytes());
+    data[0x24..0x26].copy_from_slice(&400u16.to_be_bytes());
+    let mut screen = MockScreen::new(Arc::new(Mutex::new(String::new())));
+    screen.inputs = Arc::new(Mutex::new(inputs));
+    State::new(&data, Box::new(screen)).unwrap()
+}
+
+fn create_v6_state() -> State<'static> {
+    create_screen_state(6, vec![])
+}
+
+#[test]
+fn test_window_size_and_move() {
+    let mut state = create_v6_state();
+    WindowSize.run(&mut state, &[3, 100, 200]).unwrap();
+    MoveWindow.run(&mut state, &[3, 50, 60]).unwrap();
+    let w = state.windows.get(3);
+    assert_eq!((w.height, w.width, w.y, w.x), (100, 200, 50, 60));
+}
+
+#[test]
+fn test_window_style() {
+    let mut state = create_v6_state();
+    SetWindow.run(&mut state, &[2]).unwrap();
+    // -3 is the current window
+    WindowStyle.run(&mut state, &[0xfffd, 0x03]).unwrap();
+    WindowStyle.run(&mut state, &[2, 0x08, 1]).unwrap();
+    WindowStyle.run(&mut state, &[2, 0x01, 2]).unwrap();
+    WindowStyle.run(&mut state, &[2, 0x04, 3]).unwrap();
+    assert_eq!(state.windows.get(2).attributes, 0x0e);
+}
+
+#[test]
+fn test_get_put_wind_prop() {
+    let mut state = create_v6_state();
+    PutWindProp.run(&mut state, &[4, 11, 0x0203]).unwrap();
+
+    let pc = state.frame.pc as u16;
+    state.mem[pc] = 0x10; // Store to global 0x10
+    GetWindProp.run(&mut state, &[4, 11]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 0x0203);
+
+    // Window 0 fills the screen
+    state.mem[pc + 1] = 0x10;
+    GetWindProp.run(&mut state, &[0, 3]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 640);
+
+    assert!(PutWindProp.run(&mut state, &[4, 18, 0]).is_err());
+    assert!(PutWindProp.run(&mut state, &[8, 0, 0]).is_err());
+}
+
+#[test]
+fn test_set_colour_and_text_style() {
+    let mut state = create_v6_state();
+    let pc = state.frame.pc as u16;
+    let get = |state: &mut State, window: u16, prop: u16| {
+        state.frame.pc = pc as u32;
+        state.mem[pc] = 0x10; // Store to global 0x10
+        GetWindProp.run(state, &[window, prop]).unwrap();
+        state.mem.read_u16(0x220)
+    };
+
+    // Black on white in the current window, then a new foreground only
+    SetColor.run(&mut state, &[2, 9]).unwrap();
+    assert_eq!(get(&mut state, 0, 11), 0x0902);
+    SetColor.run(&mut state, &[3, 0]).unwrap();
+    assert_eq!(get(&mut state, 0, 11), 0x0903);
+    // V6 can name the window
+    SetColor.run(&mut state, &[4, 6, 2]).unwrap();
+    assert_eq!(get(&mut state, 2, 11), 0x0604);
+    assert_eq!(get(&mut state, 0, 11), 0x0903);
+    assert_eq!(crate::graphics::background(state.windows.get(2)), [0, 0x6b, 0xb5, 255]);
+
+    // Bold and italic combine, roman clears them
+    SetTextStyle.run(&mut state, &[2]).unwrap();
+    SetTextStyle.run(&mut state, &[4]).unwrap();
+    assert_eq!(get(&mut state, 0, 10), 6);
+    SetTextStyle.run(&mut state, &[0]).unwrap();
+    assert_eq!(get(&mut state, 0, 10), 0);
+}
+
+#[test]
+fn test_set_margins() {
+    let mut state = create_v6_state();
+    SetMargins.run(&mut state, &[10, 20, 0]).unwrap();
+    let w = state.windows.get(0);
+    assert_eq!((w.left_margin, w.right_margin, w.cursor_x), (10, 20, 11));
+}
+
+#[test]
+fn test_split_window_model() {
+    let mut state = create_test_state();
+    // V3 screens are measured in lines
+    state.mem.write_u8(0x20, 25);
+    state.mem.write_u8(0x21, 80);
+    state.windows = crate::window::Windows::new(&state.mem);
+    SplitWindow.run(&mut state, &[3]).unwrap();
+    assert_eq!(state.windows.get(1).height, 3);
+    assert_eq!(state.windows.get(0).height, 22);
+}
+
+// A V6 state with a Blorb holding picture 1: a 2x2 red PNG
+fn create_picture_state() -> State<'static> {
+    use crate::blorb::blorb_tests::chunk;
+
+    let mut png_data = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, 2, 2);
+        encoder.set_color(png::ColorType::Rgb);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[255, 0, 0].repeat(4)).unwrap();
+    }
+
+    // Picture 1 is red, 2x2; picture 2 won't decode
+    let png = chunk(b"PNG ", &png_data);
+    let mut index = 2u32.to_be_bytes().to_vec();
+    for (number, offset) in [(1u32, 12 + 8 + 28), (2, 12 + 8 + 28 + png.len() as u32)] {
+        index.extend_from_slice(b"Pict");
+        index.extend_from_slice(&number.to_be_bytes());
+        index.extend_from_slice(&offset.to_be_bytes());
+    }
+    let mut form = b"IFRS".to_vec();
+    form.extend_from_slice(&chunk(b"RIdx", &index));
+    form.extend_from_slice(&png);
+    form.extend_from_slice(&chunk(b"PNG ", b"broken"));
+    form.extend_from_slice(&chunk(b"RelN", &3u16.to_be_bytes()));
+    let mut data = b"FORM".to_vec();
+    data.extend_from_slice(&(form.len() as u32).to_be_bytes());
+    data.extend_from_slice(&form);
+
+    let mut state = create_v6_state();
+    state.blorb = Some(crate::blorb::Blorb::parse(&data).unwrap());
+    state
+}
+
+#[test]
+fn test_picture_data() {
+    let mut state = create_picture_state();
+    // Branch byte: branch on true, offset 10
+    state.mem.write_u8(0x100, 0xC0 | 10);
+    PictureData.run(&mut state, &[1, 0x400]).unwrap();
+    assert_eq!((state.mem.read_u16(0x400), state.mem.read_u16(0x402)), (2, 2));
+    assert_eq!(state.frame.pc, 0x101 + 10 - 2);
+
+    state.frame.pc = 0x100;
+    PictureData.run(&mut state, &[0, 0x400]).unwrap();
+    assert_eq!((state.mem.read_u16(0x400), state.mem.read_u16(0x402)), (2, 3));
+
+    // Missing pictures don't branch
+    state.frame.pc = 0x100;
+    PictureData.run(&mut state, &[7, 0x400]).unwrap();
+    assert_eq!(state.frame.pc, 0x101);
+}
+
+#[test]
+fn test_draw_and_erase_picture() {
+    let mut state = create_picture_state();
+    WindowSize.run(&mut state, &[0, 4, 4]).unwrap();
+    DrawPicture.run(&mut state, &[1, 2, 3]).unwrap();
+
+    let fb = state.graphics.framebuffer(0).unwrap();
+    assert_eq!((fb.width(), fb.height()), (4, 4));
+    assert_eq!(fb.pixel(2, 3), Some([255, 0, 0, 255]));
+    assert_eq!(fb.pixel(3, 4), Some([255, 0, 0, 255]));
+    assert_eq!(fb.pixel(1, 1), Some([255, 255, 255, 255]));
+
+    ErasePicture.run(&mut state, &[1, 2, 3]).unwrap();
+    let fb = state.graphics.framebuffer(0).unwrap();
+    assert_eq!(fb.pixel(2, 3), Some([255, 255, 255, 255]));
+
+    // Unknown pictures are ignored
+    DrawPicture.run(&mut state, &[9, 1, 1]).unwrap();
+}
+
+#[test]
+fn test_picture_table() {
+    let mut state = create_picture_state();
+    state.mem.write_u16(0x400, 1);
+    state.mem.write_u16(0x402, 0);
+    assert!(state.graphics.decoded(1).is_none());
+    PictureTable.run(&mut state, &[0x400]).unwrap();
+    let image = state.graphics.decoded(1).unwrap();
+    assert_eq!((image.width, image.height), (2, 2));
+
+    // Missing pictures and ones which won't decode are skipped
+    state.mem.write_u16(0x400, 7);
+    state.mem.write_u16(0x402, 2);
+    state.mem.write_u16(0x404, 0);
+    PictureTable.run(&mut state, &[0x400]).unwrap();
+    assert!(state.graphics.decoded(7).is_none());
+    assert!(state.graphics.decoded(2).is_none());
+    assert!(DrawPicture.run(&mut state, &[2]).is_err());
+
+    // A table running off the end of memory is an error
+    assert!(PictureTable.run(&mut state, &[0xFFFF]).is_err());
+    assert!(PictureData.run(&mut state, &[1, 0xFFFE]).is_err());
+}
+
+#[test]
+fn test_read_char_click() {
+    let mut state = create_screen_state(5, vec![
+        Input::Click { y: 3, x: 7, double: false },
+        Input::Click { y: 4, x: 8, double: true },
+    ]);
+    state.mem.write_u8(0x100, 0x10);
+    state.mem.write_u8(0x101, 0x11);
+    ReadChar.run(&mut state, &[1]).unwrap();
+    assert_eq!(state.read_variable(0x10).unwrap(), 254);
+    assert_eq!((state.mem.read_u16(0x302), state.mem.read_u16(0x304)), (7, 3));
+
+    ReadChar.run(&mut state, &[1]).unwrap();
+    assert_eq!(state.read_variable(0x11).unwrap(), 253);
+    assert_eq!((state.mem.read_u16(0x302), state.mem.read_u16(0x304)), (8, 4));
+}
+
+#[test]
+fn test_mouse_window_ignores_outside_clicks() {
+    let mut state = create_screen_state(6, vec![
+        Input::Click { y: 300, x: 7, double: false },
+        Input::Key('a'),
+    ]);
+    WindowSize.run(&mut state, &[1, 100, 640]).unwrap();
+    state.mem.write_u8(0x100, 0x10);
+    ReadChar.run(&mut state, &[1]).unwrap();
+    assert_eq!(state.read_variable(0x10).unwrap(), b'a' as u16);
+
+    // Without a mouse window the click counts
+    let mut state = create_screen_state(6, vec![Input::Click { y: 300, x: 7, double: false }]);
+    MouseWindow.run(&mut state, &[0xFFFF]).unwrap();
+    state.mem.write_u8(0x100, 0x10);
+    ReadChar.run(&mut state, &[1]).unwrap();
+    assert_eq!(state.read_variable(0x10).unwrap(), 254);
+
+    assert!(MouseWindow.run(&mut state, &[9]).is_err());
+}
+
+#[test]
+fn test_read_mouse() {
+    let mut state = create_screen_state(6, vec![]);
+    ReadMouse.run(&mut state, &[0x400]).unwrap();
+    let words: Vec<u16> = (0..4).map(|i| state.mem.read_u16(0x400 + i * 2)).collect();
+    assert_eq!(words, vec![10, 20, 1, 0x0102]);
+}
+
+#[test]
+fn test_set_font() {
+    let mut state = create_test_state();
+    state.mem.write_u8(0x100, 0x10);
+    state.mem.write_u8(0x101, 0x11);
+    state.mem.write_u8(0x102, 0x12);
+    state.mem.write_u8(0x103, 0x13);
+
+    SetFont.run(&mut state, &[3]).unwrap();
+    assert_eq!(state.read_variable(0x10).unwrap(), 1);
+    assert_eq!(state.windows.current().font, 3);
+
+    // Font 2 (pictures) isn't available and nothing changes
+    SetFont.run(&mut state, &[2]).unwrap();
+    assert_eq!(state.read_variable(0x11).unwrap(), 0);
+
+    // Font 0 queries
+    SetFont.run(&mut state, &[0]).unwrap();
+    assert_eq!(state.read_variable(0x12).unwrap(), 3);
+
+    SetFont.run(&mut state, &[4]).unwrap();
+    assert_eq!(state.read_variable(0x13).unwrap(), 3);
+    assert_eq!(state.windows.current().font, 4);
+}
+
+#[test]
+fn test_set_font_v6_window() {
+    let mut state = create_v6_state();
+    state.mem.write_u8(0x100, 0x10);
+    SetFont.run(&mut state, &[4, 2]).unwrap();
+    assert_eq!(state.read_variable(0x10).unwrap(), 1);
+    assert_eq!(state.windows.get(2).font, 4);
+    assert_eq!(state.windows.current().font, 1);
+}
+
+#[test]
+fn test_buffer_mode_and_word_wrap() {
+    let (mut state, output) = create_test_state_with_output();
+    state.mem.write_u8(0x21, 10); // 10 characters wide
+    state.output.wrap = true;
+
+    state.print("hello there world");
+    assert_eq!(*output.lock().unwrap(), "hello\nthere\n");
+
+    // Unbuffered text goes straight out, after what was pending
+    BufferMode.run(&mut state, &[0]).unwrap();
+    state.print("a long unbuffered line");
+    assert_eq!(*output.lock().unwrap(), "hello\nthere\nworlda long unbuffered line");
+
+    // The upper window is never wrapped
+    BufferMode.run(&mut state, &[1]).unwrap();
+    SplitWindow.run(&mut state, &[1]).unwrap();
+    SetWindow.run(&mut state, &[1]).unwrap();
+    output.lock().unwrap().clear();
+    state.print("status line text");
+    assert_eq!(*output.lock().unwrap(), "status line text");
+}
+
+#[test]
+fn test_fixed_pitch_is_not_wrapped() {
+    let (mut state, output) = create_test_state_with_output();
+    state.mem.write_u8(0x21, 10); // 10 characters wide
+    state.output.wrap = true;
+
+    SetTextStyle.run(&mut state, &[8]).unwrap();
+    state.print("+--------------+\n");
+    SetTextStyle.run(&mut state, &[0]).unwrap();
+    state.mem.write_u16(0x10, 0x02); // Flags 2 forces fixed pitch
+    state.print("| a wide map |\n");
+    assert_eq!(*output.lock().unwrap(), "+--------------+\n| a wide map |\n");
+
+    state.mem.write_u16(0x10, 0);
+    output.lock().unwrap().clear();
+    state.print("hello there world");
+    assert_eq!(*output.lock().unwrap(), "hello\nthere\n");
+}
+
+#[test]
+fn test_print_num_is_signed_either_way() {
+    let (mut state, output) = create_test_state_with_output();
+    PrintNum.run(&mut state, &[0xFFFF]).unwrap();
+    state.mem.write_u8(0x21, 10);
+    state.output.wrap = true;
+    PrintNum.run(&mut state, &[0xFFFE]).unwrap();
+    state.flush_output();
+    assert_eq!(*output.lock().unwrap(), "-1-2");
+}
+
+#[test]
+fn test_more_prompt_resets_on_input() {
+    let (mut state, output) = create_test_state_with_output();
+    state.mem.write_u8(0x20, 3); // 3 lines high
+    state.windows = crate::window::Windows::new(&state.mem);
+    state.output.more = Some("[MORE]".to_string());
+
+    state.print("one\n");
+    ReadChar.run(&mut state, &[1]).unwrap();
+    state.print("two\nthree\n");
+    assert_eq!(*output.lock().unwrap(), "one\ntwo\nthree\n[MORE]\n");
+}
+
+#[test]
+fn test_storew_outside_dynamic_memory() {
+    let mut state = create_test_state();
+    assert!(StoreW.run(&mut state, &[0x800, 0, 1]).is_err());
+    assert!(StoreB.run(&mut state, &[0x00, 0x0E, 1]).is_err());
+    assert!(LoadW.run(&mut state, &[0x1000, 0]).is_err());
+    assert_eq!(state.mem.static_memory(), 0x800);
+}
+
+#[test]
+fn test_bad_object() {
+    let mut state = create_test_state();
+    assert!(matches!(
+        SetAttr.run(&mut state, &[0, 1]),
+        Err(Error::BadObject { object: 0, .. })
+    ));
+    assert!(InsertObj.run(&mut state, &[1, 0]).is_err());
+    assert!(GetParent.run(&mut state, &[256]).is_err());
+    assert!(GetChild.run(&mut state, &[0]).is_err());
+    assert!(TestAttr.run(&mut state, &[0, 1]).is_err());
+}
+
+#[test]
+fn test_bad_object_permissive() {
+    let mut state = create_test_state();
+    state.mem.permissive = true;
+    state.mem.write_u16(0x220, 0xFFFF);
+
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0x10;
+    GetParent.run(&mut state, &[0]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 0);
+
+    // Store 0 and don't branch
+    state.mem.write_u16(0x220, 0xFFFF);
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0x10;
+    state.mem[pc as u16 + 1] = 0xCA;
+    GetSibling.run(&mut state, &[0]).unwrap();
+    assert_eq!(state.mem.read_u16(0x220), 0);
+    assert_eq!(state.frame.pc as usize, pc + 2);
+
+    let pc = state.frame.pc as usize;
+    state.mem[pc as u16] = 0xCA;
+    TestAttr.run(&mut state, &[0, 1]).unwrap();
+    assert_eq!(state.frame.pc as usize, pc + 1);
+
+    SetAttr.run(&mut state, &[0, 1]).unwrap();
+    InsertObj.run(&mut state, &[1, 0]).unwrap();
+    RemoveObj.run(&mut state, &[256]).unwrap();
+}
+
+#[test]
+fn test_sread_operand_count() {
+    assert_eq!(Sread.operand_count(3), 2..=2);
+    assert_eq!(Sread.operand_count(4), 2..=4);
+    assert_eq!(Sread.operand_count(5), 1..=4);
+}
@@ -1,17 +1,35 @@
 #![allow(dead_code)]
 
+pub mod babel;
+pub mod blorb;
+pub mod disasm;
 pub mod error;
+pub mod graphics;
+pub mod inspect;
+pub mod window;
 pub mod zmachine;
 pub mod zscreen;
 
+mod decode;
 mod instruction;
 mod memory;
 mod object;
+mod output;
+mod rng;
+mod stack;
 mod zscii;
 
 pub use zmachine::ZMachine;
-pub use zscreen::ZScreen;
+pub use zscii::font3_to_unicode;
+pub use zscreen::{Input, MouseState, SoundEffect, ZScreen};
 pub use error::Error;
+pub use graphics::Framebuffer;
+pub use window::Window;
+pub use babel::StoryInfo;
+pub use blorb::Blorb;
+pub use disasm::Disassembler;
+pub use inspect::Report;
+pub use object::{ObjectTree, Properties, Property};
 
 #[cfg(test)]
 mod tests {
@@ -1,101 +1,37 @@
 use crate::error::Error;
-use std::ops::{Deref, DerefMut, Index, IndexMut};
+use crate::stack::{Stack, StackFrame};
+use std::ops::{Deref, Index, IndexMut};
+
+//header bytes the game may write itself: flags 2 (transcripting, fixed pitch, redraw)
+const WRITABLE_HEADER: std::ops::Range<u16> = 0x10..0x12;
+const HEADER_LEN: u16 = 0x40;
 
 pub struct Memory {
     mem: Vec<u8>,
     pub(crate) stack: Stack,
-}
-
-pub struct Stack {
-    stack: [u16; 4096],
-    sp: u16, //stack pointer
-}
-
-#[derive(Clone)]
-pub struct StackFrame {
-    pub prev: Box<Option<StackFrame>>, //ie the call stack
-    pub pc: u32,                       //program counter
-    pub bp: u16, //base pointer of this stack frame - illegal for sp to drop below this
-    pub return_store_var: Option<u8>,
-                 //locals: Vec<u16>,
-}
-
-impl StackFrame {
-    pub fn main(mem: &Memory) -> StackFrame {
-        StackFrame {
-            prev: Box::new(None),
-            pc: mem.initial_pc() as u32,
-            bp: 17,
-            return_store_var: None,
-        }
-    }
-
-    //advance stack pointer for new locals & create new stackframe
-    pub fn push(self, stack: &mut Stack, pc: u32, return_store_var: Option<u8>) -> StackFrame {
-        stack.sp += 16; //16 locals
-        StackFrame {
-            prev: Box::new(Some(self)),
-            pc,
-            bp: stack.sp,
-            return_store_var,
-        }
-    }
-
-    //return to previous stack frame, zeroing out space used by this stack
-    pub fn pop(self, stack: &mut Stack) -> Result<StackFrame, Error> {
-        for p in stack.sp..=(self.bp - 16) {
-            //erase current stack + 16 locals
-            stack[p] = 0;
-        }
-        stack.sp = self.bp - 16;
-        match *self.prev {
-            Some(prev) => Ok(prev),
-            None => Err(Error::ZMachineError(
-                "Attempted to return from main routine".to_string(),
-            )),
-        }
-    }
-
-    pub fn read_local(&self, stack: &Stack, i: u16) -> u16 {
-        stack[self.bp - i]
-    }
-
-    pub fn write_local(&self, stack: &mut Stack, i: u16, val: u16) {
-        stack[self.bp - i] = val;
-    }
-}
-
-impl Stack {
-    fn pop(&mut self, frame: &StackFrame) -> Result<u16, Error> {
-        if self.sp <= frame.bp {
-            Err(Error::ZMachineError("Stack underflow".to_string()))
-        } else {
-            self.sp -= 1;
-            Ok(self[self.sp])
-        }
-    }
-
-    fn push(&mut self, _frame: &mut StackFrame, val: u16) -> Result<(), Error> {
-        if self.sp >= self.stack.len() as u16 {
-            Err(Error::ZMachineError("Stack Overflow".to_string()))
-        } else {
-            let sp = self.sp;
-            self[sp] = val;
-            self.sp += 1;
-            Ok(())
-        }
-    }
+    pub permissive: bool, //let illegal game accesses through rather than failing
+    generation: u64,      //bumped by every write, so cached decodes of dynamic memory can be checked
 }
 
 impl Memory {
     pub fn new(story: &[u8]) -> Self {
         Memory {
             mem: story.into(),
-            stack: Stack {
-                stack: [0u16; 4096],
-                sp: 17,
-            },
+            stack: Stack::new(),
+            permissive: false,
+            generation: 0,
+        }
+    }
+
+    //for tools which read the header before anything else: it must all be there
+    pub fn check_header(story: &[u8]) -> Result<(), Error> {
+        if story.len() < HEADER_LEN as usize {
+            return Err(Error::out_of_bounds(
+                story.len() as u32,
+                format!("story file of {} bytes is too short for a header", story.len()),
+            ));
         }
+        Ok(())
     }
 
     //First define various read_* and write_* fns
@@ -122,7 +58,84 @@ impl Memory {
         self[addr + 3] = bytes[3];
     }
 
-    //TODO various legality checks as some areas of memory have write restrictions
+    //reads and writes made by the game (loadw, storeb etc.) are checked against the memory
+    //map. The unchecked versions are for the interpreter's own use, eg. filling in the header
+    pub fn checked_read_u8(&self, addr: u16) -> Result<u8, Error> {
+        if (addr as u32) < self.readable_len() {
+            Ok(self[addr])
+        } else if self.permissive {
+            Ok(0)
+        } else {
+            Err(Error::out_of_bounds(
+                addr as u32,
+                format!("read beyond the end of the story file (0x{:X} bytes)", self.readable_len()),
+            ))
+        }
+    }
+
+    pub fn checked_read_u16(&self, addr: u16) -> Result<u16, Error> {
+        Ok((self.checked_read_u8(addr)? as u16) << 8
+            | self.checked_read_u8(addr.wrapping_add(1))? as u16)
+    }
+
+    pub fn checked_write_u8(&mut self, addr: u16, val: u8) -> Result<(), Error> {
+        let illegal = if addr < HEADER_LEN && !WRITABLE_HEADER.contains(&addr) {
+            Some("write to a header byte the game may not change".to_string())
+        } else if addr >= self.static_memory() {
+            Some(format!(
+                "write outside dynamic memory, which ends at 0x{:04X}",
+                self.static_memory()
+            ))
+        } else {
+            None
+        };
+
+        match illegal {
+            Some(reason) if !self.permissive => Err(Error::out_of_bounds(addr as u32, reason)),
+            _ => {
+                //even a permissive machine can't write past the end of memory
+                if (addr as usize) < self.mem.len() {
+                    self[addr] = val;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn checked_write_u16(&mut self, addr: u16, val: u16) -> Result<(), Error> {
+        let vals = val.to_be_bytes();
+        self.checked_write_u8(addr, vals[0])?;
+        self.checked_write_u8(addr.wrapping_add(1), vals[1])
+    }
+
+    //the interpreter's own reads of routine headers and strings, which can be anywhere in the
+    //story file, above 64K too. Reading past its end is an error even when permissive, as
+    //there's no sensible routine or string to carry on with
+    pub fn fetch_u8(&self, addr: u32) -> Result<u8, Error> {
+        self.mem.get(addr as usize).copied().ok_or_else(|| {
+            Error::out_of_bounds(
+                addr,
+                format!("read beyond the end of the story file (0x{:X} bytes)", self.mem.len()),
+            )
+        })
+    }
+
+    pub fn fetch_u16(&self, addr: u32) -> Result<u16, Error> {
+        Ok((self.fetch_u8(addr)? as u16) << 8 | self.fetch_u8(addr.wrapping_add(1))? as u16)
+    }
+
+    //the file length from the header bounds reads, if it is given and no longer than the data
+    fn readable_len(&self) -> u32 {
+        match self.file_length() {
+            0 => self.mem.len() as u32,
+            len => len.min(self.mem.len() as u32),
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     pub fn write_u8(&mut self, addr: u16, val: u8) {
         self[addr] = val;
     }
@@ -133,7 +146,7 @@ impl Memory {
         self[addr + 1] = vals[1];
     }
 
-    pub fn load(&mut self, id: u8, frame: &mut StackFrame) -> Result<u16, Error> {
+    pub fn load(&mut self, id: u8, frame: &StackFrame) -> Result<u16, Error> {
         match id {
             0x00 => {
                 //pop from stack
@@ -141,36 +154,40 @@ impl Memory {
             }
             0x01..=0x0f => {
                 //read from locals
-                Ok(frame.read_local(&self.stack, id as u16))
+                self.stack.read_local(frame, id)
             }
             _ => {
                 //read from globals
-                Ok(self.read_global(id))
+                self.read_global(id)
             }
         }
     }
 
-    pub fn store(&mut self, id: u8, val: u16, frame: &mut StackFrame) -> Result<(), Error> {
+    pub fn store(&mut self, id: u8, val: u16, frame: &StackFrame) -> Result<(), Error> {
         match id {
             0x00 => {
                 //push to stack
-                self.stack.push(frame, val)
+                self.stack.push(val)
             }
             0x01..=0x0f => {
                 //write to locals
-                frame.write_local(&mut self.stack, id as u16, val);
-                Ok(())
+                self.stack.write_local(frame, id, val)
             }
             _ => {
                 //write to globals
-                self.write_u16(self.global_variables() + (id as u16 * 2), val);
-                Ok(())
+                self.checked_write_u16(self.global_addr(id), val)
             }
         }
     }
 
-    pub fn read_global(&self, id: u8) -> u16 {
-        self.read_u16(self.global_variables() + (id * 2) as u16)
+    pub fn read_global(&self, id: u8) -> Result<u16, Error> {
+        self.checked_read_u16(self.global_addr(id))
+    }
+
+    //where loads and stores of variable `id` go; a table near the top of memory wraps
+    //rather than overflowing, and the checked read or write then rejects it
+    fn global_addr(&self, id: u8) -> u16 {
+        self.global_variables().wrapping_add(id as u16 * 2)
     }
 
     pub fn high_memory(&self) -> u16 {
@@ -205,10 +222,50 @@ impl Memory {
         self.read_u16(0x18)
     }
 
+    //Flags 2 bit 1: the game wants fixed-pitch text for now, eg. while it draws a map
+    pub fn forced_fixed_pitch(&self) -> bool {
+        self.read_u16(0x10) & 0x02 != 0
+    }
+
     pub fn header_extension_table(&self) -> u16 {
         self.read_u16(0x36)
     }
 
+    //word 3 of the header extension table, if the table is long enough - "or zero for default".
+    //a table past the end of the story, or running off it, gives 0 too
+    pub fn unicode_table(&self) -> u16 {
+        let ext = self.header_extension_table() as u32;
+        let table = match self.fetch_u16(ext) {
+            Ok(len) if ext != 0 && len >= 3 => self.fetch_u16(ext + 6).unwrap_or(0),
+            _ => 0,
+        };
+        //a count byte, then that many words
+        let end = self.fetch_u8(table as u32).map(|count| table as usize + 1 + count as usize * 2);
+        if end.is_ok_and(|end| end <= self.len()) {
+            table
+        } else {
+            0
+        }
+    }
+
+    //click coordinates go in words 1 (x) and 2 (y) of the header extension table, if present.
+    //Words which would be past the end of the story are left out
+    pub fn set_click_position(&mut self, y: u16, x: u16) {
+        let ext = self.header_extension_table() as u32;
+        if ext == 0 {
+            return;
+        }
+        let len = self.fetch_u16(ext).unwrap_or(0);
+        for (word, val) in [(1, x), (2, y)] {
+            let addr = (ext + word * 2) as usize;
+            if len as u32 >= word {
+                if let Some(bytes) = self.mem.get_mut(addr..addr + 2) {
+                    bytes.copy_from_slice(&val.to_be_bytes());
+                }
+            }
+        }
+    }
+
     pub fn routine_offset(&self) -> u16 {
         self.read_u16(0x28)
     }
@@ -221,6 +278,74 @@ impl Memory {
         self.read_u8(0x00)
     }
 
+    //screen dimensions, set by the interpreter (see ZMachine::set_screen_size)
+    pub fn screen_height_lines(&self) -> u8 {
+        self.read_u8(0x20)
+    }
+
+    pub fn screen_width_chars(&self) -> u8 {
+        self.read_u8(0x21)
+    }
+
+    pub fn screen_width_units(&self) -> u16 {
+        self.read_u16(0x22)
+    }
+
+    pub fn screen_height_units(&self) -> u16 {
+        self.read_u16(0x24)
+    }
+
+    //the two font size bytes swap places in V6
+    pub fn font_width(&self) -> u8 {
+        self.read_u8(if self.zmachine_version() == 6 { 0x27 } else { 0x26 })
+    }
+
+    pub fn font_height(&self) -> u8 {
+        self.read_u8(if self.zmachine_version() == 6 { 0x26 } else { 0x27 })
+    }
+
+    pub fn release(&self) -> u16 {
+        self.read_u16(0x02)
+    }
+
+    //six ASCII characters, conventionally the compilation date as YYMMDD
+    pub fn serial(&self) -> String {
+        (0x12..0x18u16).map(|addr| self.read_u8(addr) as char).collect()
+    }
+
+    pub fn checksum(&self) -> u16 {
+        self.read_u16(0x1C)
+    }
+
+    //stored divided by 2 (V1-3), 4 (V4-5) or 8 (V6+). Some early files leave this as 0
+    pub fn file_length(&self) -> u32 {
+        let scale = match self.zmachine_version() {
+            1..=3 => 2,
+            4 | 5 => 4,
+            _ => 8,
+        };
+        self.read_u16(0x1A) as u32 * scale
+    }
+
+    //See 1.2.3 - packed addresses scale with version, V6 & V7 add the header offsets
+    pub fn unpack_routine(&self, packed: u16) -> u32 {
+        self.unpack(packed, self.routine_offset())
+    }
+
+    pub fn unpack_string(&self, packed: u16) -> u32 {
+        self.unpack(packed, self.string_offset())
+    }
+
+    fn unpack(&self, packed: u16, offset: u16) -> u32 {
+        let packed = packed as u32;
+        match self.zmachine_version() {
+            1..=3 => packed * 2,
+            4 | 5 => packed * 4,
+            6 | 7 => packed * 4 + offset as u32 * 8,
+            _ => packed * 8,
+        }
+    }
+
     //in v6 and above this is a packed address but that handling is left to processor
     pub fn initial_pc(&self) -> u16 {
         self.read_u16(0x06)
@@ -229,20 +354,15 @@ impl Memory {
 
 //VARIOUS BOILERPLATE
 
+//read-only: the story can't be resized, and writes go through write_* or IndexMut
 impl Deref for Memory {
-    type Target = Vec<u8>;
+    type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
         &self.mem
     }
 }
 
-impl DerefMut for Memory {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.mem
-    }
-}
-
 impl Index<u16> for Memory {
     type Output = u8;
 
@@ -253,6 +373,7 @@ impl Index<u16> for Memory {
 
 impl IndexMut<u16> for Memory {
     fn index_mut(&mut self, i: u16) -> &mut u8 {
+        self.generation += 1;
         &mut self.mem[i as usize]
     }
 }
@@ -267,48 +388,10 @@ impl Index<u32> for Memory {
 
 impl IndexMut<u32> for Memory {
     fn index_mut(&mut self, i: u32) -> &mut u8 {
+        self.generation += 1;
         &mut self.mem[i as usize]
     }
 }
 
-impl Deref for Stack {
-    type Target = [u16; 4096];
-
-    fn deref(&self) -> &Self::Target {
-        &self.stack
-    }
-}
-
-impl DerefMut for Stack {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.stack
-    }
-}
-
-impl Index<u16> for Stack {
-    type Output = u16;
-
-    fn index(&self, i: u16) -> &Self::Output {
-        &self.stack[i as usize]
-    }
-}
-
-impl IndexMut<u16> for Stack {
-    fn index_mut(&mut self, i: u16) -> &mut u16 {
-        &mut self.stack[i as usize]
-    }
-}
-
-impl Index<u32> for Stack {
-    type Output = u16;
-
-    fn index(&self, i: u32) -> &Self::Output {
-        &self.stack[i as usize]
-    }
-}
-
-impl IndexMut<u32> for Stack {
-    fn index_mut(&mut self, i: u32) -> &mut u16 {
-        &mut self.stack[i as usize]
-    }
-}
+#[cfg(test)]
+mod memory_tests;
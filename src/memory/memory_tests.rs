@@ -0,0 +1,104 @@
+use super::*;
+
+fn create_memory(version: u8) -> Memory {
+    let mut data = vec![0u8; 1024];
+    data[0x00] = version;
+    Memory::new(&data)
+}
+
+#[test]
+fn test_unpack_addresses() {
+    let mut mem = create_memory(1);
+    assert_eq!(mem.unpack_routine(0x1234), 0x2468);
+    mem.write_u8(0x00, 3);
+    assert_eq!(mem.unpack_string(0x8000), 0x10000);
+    mem.write_u8(0x00, 5);
+    assert_eq!(mem.unpack_routine(0x1234), 0x48D0);
+
+    // V6 adds 8 * the routine/string offsets
+    mem.write_u8(0x00, 6);
+    mem.write_u16(0x28, 0x10);
+    mem.write_u16(0x2A, 0x20);
+    assert_eq!(mem.unpack_routine(0x100), 0x480);
+    assert_eq!(mem.unpack_string(0x100), 0x500);
+
+    mem.write_u8(0x00, 8);
+    assert_eq!(mem.unpack_routine(0x100), 0x800);
+}
+
+#[test]
+fn test_file_length() {
+    let mut mem = create_memory(2);
+    mem.write_u16(0x1A, 0x100);
+    assert_eq!(mem.file_length(), 0x200);
+    mem.write_u8(0x00, 5);
+    assert_eq!(mem.file_length(), 0x400);
+}
+
+// 1K story with static memory from 0x200
+fn create_protected_memory() -> Memory {
+    let mut data = vec![0u8; 1024];
+    data[0x00] = 5;
+    data[0x0E] = 0x02;
+    Memory::new(&data)
+}
+
+#[test]
+fn test_writes_limited_to_dynamic_memory() {
+    let mut mem = create_protected_memory();
+    assert!(mem.checked_write_u16(0x100, 0xBEEF).is_ok());
+    assert_eq!(mem.read_u16(0x100), 0xBEEF);
+    assert!(mem.checked_write_u8(0x1FF, 1).is_ok());
+    assert!(mem.checked_write_u8(0x200, 1).is_err());
+    assert!(mem.checked_write_u16(0x1FF, 0x0102).is_err());
+    assert!(mem.checked_write_u8(0xFFFF, 1).is_err());
+    assert_eq!(mem.read_u8(0x200), 0);
+}
+
+#[test]
+fn test_header_writes() {
+    let mut mem = create_protected_memory();
+    // Flags 2 is the game's to change, the rest of the header isn't
+    assert!(mem.checked_write_u16(0x10, 0x0003).is_ok());
+    assert!(mem.checked_write_u8(0x0E, 0xFF).is_err());
+    assert!(mem.checked_write_u8(0x01, 0xFF).is_err());
+    assert!(mem.checked_write_u8(0x3F, 0xFF).is_err());
+    assert_eq!(mem.static_memory(), 0x200);
+    assert!(mem.checked_write_u8(0x40, 1).is_ok());
+}
+
+#[test]
+fn test_reads_bounded_by_file_length() {
+    let mut mem = create_protected_memory();
+    assert!(mem.checked_read_u8(0x3FF).is_ok());
+    assert!(mem.checked_read_u8(0x400).is_err());
+    assert!(mem.checked_read_u16(0x3FF).is_err());
+
+    // The header's file length is used when it is shorter than the data
+    mem.write_u16(0x1A, 0x80); // 0x80 * 4 bytes
+    assert!(mem.checked_read_u8(0x1FF).is_ok());
+    assert!(mem.checked_read_u8(0x200).is_err());
+}
+
+#[test]
+fn test_permissive_memory() {
+    let mut mem = create_protected_memory();
+    mem.permissive = true;
+    assert!(mem.checked_write_u8(0x300, 7).is_ok());
+    assert_eq!(mem.read_u8(0x300), 7);
+    assert!(mem.checked_write_u8(0x8000, 7).is_ok());
+    assert_eq!(mem.checked_read_u8(0x8000).unwrap(), 0);
+    assert_eq!(mem.len(), 1024);
+}
+
+#[test]
+fn test_click_position_past_end_of_story() {
+    let mut mem = create_memory(5);
+    mem.write_u16(0x36, 0x3FC);
+    mem.write_u16(0x3FC, 2);
+    mem.set_click_position(20, 10);
+    assert_eq!(mem.read_u16(0x3FE), 10); // y is left out
+
+    mem.write_u16(0x36, 0xFFFF);
+    mem.set_click_position(20, 10);
+}
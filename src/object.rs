@@ -1,4 +1,4 @@
-use crate::{memory::Memory, zscii};
+use crate::{error::Error, memory::Memory, zscii};
 //use core::num::traits::Num;
 //use core::num::Num;
 use num::{Integer, NumCast};
@@ -17,28 +17,34 @@ where
     phantom: PhantomData<&'a T>,
 }
 
+//object numbers are a byte in V1-3 and a word from V4, wherever they're stored. The links
+//come from the game, so they're read and written with the same checks as its own accesses
 pub trait ReadObject {
-    fn read_obj(&self, addr: u16, mem: &Memory) -> Self
+    fn read_obj(&self, addr: u16, mem: &Memory) -> Result<Self, Error>
     where
         Self: Sized;
+
+    fn write_obj(&self, addr: u16, mem: &mut Memory) -> Result<(), Error>;
 }
 
 impl ReadObject for u8 {
-    fn read_obj(&self, addr: u16, mem: &Memory) -> u8 {
-        mem.read_u8(addr)
+    fn read_obj(&self, addr: u16, mem: &Memory) -> Result<u8, Error> {
+        mem.checked_read_u8(addr)
+    }
+
+    fn write_obj(&self, addr: u16, mem: &mut Memory) -> Result<(), Error> {
+        mem.checked_write_u8(addr, *self)
     }
 }
 
 impl ReadObject for u16 {
-    fn read_obj(&self, addr: u16, mem: &Memory) -> u16 {
-        mem.read_u16(addr)
+    fn read_obj(&self, addr: u16, mem: &Memory) -> Result<u16, Error> {
+        mem.checked_read_u16(addr)
     }
-}
 
-pub struct PropAddr {
-    pub addr: u16,
-    size_bytes: u8,
-    data_length: u8,
+    fn write_obj(&self, addr: u16, mem: &mut Memory) -> Result<(), Error> {
+        mem.checked_write_u16(addr, *self)
+    }
 }
 
 impl<'a, T> Object<'a, T>
@@ -59,6 +65,7 @@ where
     const SIZE: u16 = if Object::<T>::WIDE { 14 } else { 9 };
 
     pub const PROPMAX: u16 = if Object::<T>::WIDE { 63 } else { 31 };
+    pub const ATTRMAX: u16 = if Object::<T>::WIDE { 47 } else { 31 };
 
     pub fn new(mem: &'a mut Memory) -> Object<'a, T> {
         Object {
@@ -68,104 +75,96 @@ where
     }
 
     pub fn object_table_ptr(&self) -> u16 {
-        self.mem.object_table() + Object::<T>::PROPMAX * 2 - Object::<T>::SIZE
+        (self.mem.object_table() + Object::<T>::PROPMAX * 2).wrapping_sub(Object::<T>::SIZE)
     }
 
+    //wraps rather than overflowing for object numbers past the end of memory, which
+    //callers check for first
     pub fn object_ptr(&self, obj: T) -> u16 {
-        self.object_table_ptr() + (Into::<u16>::into(obj) * Object::<T>::SIZE)
-    }
-
-    pub fn get_attr_bytes(&self, obj: u8) -> u32 {
-        self.mem
-            .read_u32(self.object_table_ptr() + (obj as u16 * Object::<T>::SIZE))
+        self.object_table_ptr()
+            .wrapping_add(Into::<u16>::into(obj).wrapping_mul(Object::<T>::SIZE))
     }
 
-    pub fn write_attr_bytes(&mut self, obj: u8, attrs: u32) {
-        self.mem.write_u32(
-            self.object_table_ptr() + (obj as u16 * Object::<T>::SIZE),
-            attrs,
-        );
+    //attributes are numbered from the top bit of the first byte: 32 over 4 bytes in V1-3,
+    //48 over 6 bytes from V4. Attributes past those don't exist, so are never set
+    fn attr_bit(&self, obj: T, attr: u8) -> Option<(u16, u8)> {
+        (attr as u16 <= Object::<T>::ATTRMAX)
+            .then(|| (self.object_ptr(obj).wrapping_add(attr as u16 / 8), 0x80 >> (attr % 8)))
     }
 
-    //There are 32 attrs bits across 4 bytes
-    //we need to find which byte has the attr, and then test the appropriate bit in that byte
-    pub fn attr_test(&self, obj: u8, attr: u8) -> bool {
-        self.get_attr_bytes(obj) & (1 << (31 - attr)) != 0
+    pub fn attr_test(&self, obj: T, attr: u8) -> Result<bool, Error> {
+        match self.attr_bit(obj, attr) {
+            Some((addr, bit)) => Ok(self.mem.checked_read_u8(addr)? & bit != 0),
+            None => Ok(false),
+        }
     }
 
-    pub fn attr_set(&mut self, obj: T, attr: u8) {
-        let obj_u8: u8 = num::cast(obj.into()).unwrap_or(0);
-        self.write_attr_bytes(
-            obj_u8,
-            self.get_attr_bytes(obj_u8) | 1 << (31 - attr),
-        );
+    pub fn attr_set(&mut self, obj: T, attr: u8) -> Result<(), Error> {
+        match self.attr_bit(obj, attr) {
+            Some((addr, bit)) => self.mem.checked_write_u8(addr, self.mem.checked_read_u8(addr)? | bit),
+            None => Ok(()),
+        }
     }
 
-    pub fn attr_clear(&mut self, obj: T, attr: u8) {
-        let obj_u8: u8 = num::cast(obj.into()).unwrap_or(0);
-        self.write_attr_bytes(
-            obj_u8,
-            self.get_attr_bytes(obj_u8) & !(1 << (31 - attr)),
-        );
+    pub fn attr_clear(&mut self, obj: T, attr: u8) -> Result<(), Error> {
+        match self.attr_bit(obj, attr) {
+            Some((addr, bit)) => self.mem.checked_write_u8(addr, self.mem.checked_read_u8(addr)? & !bit),
+            None => Ok(()),
+        }
     }
 
-    pub fn inside(&self, obj_a: T, obj_b: T) -> bool {
-        self.mem
-            .read_u8(self.object_ptr(obj_a) + Object::<T>::PARENT) as u16
-            == obj_b.into()
+    pub fn inside(&self, obj_a: T, obj_b: T) -> Result<bool, Error> {
+        Ok(self.parent(obj_a)? == obj_b)
     }
 
-    pub fn sibling(&self, obj: T) -> T {
-        obj.read_obj(self.object_ptr(obj) + Object::<T>::SIBLING, self.mem)
+    pub fn sibling(&self, obj: T) -> Result<T, Error> {
+        obj.read_obj(self.object_ptr(obj).wrapping_add(Object::<T>::SIBLING), self.mem)
     }
 
-    pub fn parent(&self, obj: T) -> T {
-        obj.read_obj(self.object_ptr(obj) + Object::<T>::PARENT, self.mem)
+    pub fn parent(&self, obj: T) -> Result<T, Error> {
+        obj.read_obj(self.object_ptr(obj).wrapping_add(Object::<T>::PARENT), self.mem)
     }
 
-    pub fn child(&self, obj: T) -> T {
-        obj.read_obj(self.object_ptr(obj) + Object::<T>::CHILD, self.mem)
+    pub fn child(&self, obj: T) -> Result<T, Error> {
+        obj.read_obj(self.object_ptr(obj).wrapping_add(Object::<T>::CHILD), self.mem)
     }
 
     //address of the props table for given object
-    pub fn props(&self, obj: T) -> u16 {
-        self.mem.read_u16(self.object_ptr(obj) + Object::<T>::PROPS)
+    pub fn props(&self, obj: T) -> Result<u16, Error> {
+        self.mem.checked_read_u16(self.object_ptr(obj).wrapping_add(Object::<T>::PROPS))
     }
 
-    pub fn write_sibling(&mut self, obj: T, sibling: T) {
-        self.mem
-            .write_u8(self.object_ptr(obj) + Object::<T>::SIBLING, num::cast(sibling.into()).unwrap_or(0))
+    pub fn write_sibling(&mut self, obj: T, sibling: T) -> Result<(), Error> {
+        sibling.write_obj(self.object_ptr(obj).wrapping_add(Object::<T>::SIBLING), self.mem)
     }
 
-    pub fn write_parent(&mut self, obj: T, parent: T) {
-        self.mem
-            .write_u8(self.object_ptr(obj) + Object::<T>::PARENT, num::cast(parent.into()).unwrap_or(0))
+    pub fn write_parent(&mut self, obj: T, parent: T) -> Result<(), Error> {
+        parent.write_obj(self.object_ptr(obj).wrapping_add(Object::<T>::PARENT), self.mem)
     }
 
-    pub fn write_child(&mut self, obj: T, child: T) {
-        self.mem
-            .write_u8(self.object_ptr(obj) + Object::<T>::CHILD, num::cast(child.into()).unwrap_or(0))
+    pub fn write_child(&mut self, obj: T, child: T) -> Result<(), Error> {
+        child.write_obj(self.object_ptr(obj).wrapping_add(Object::<T>::CHILD), self.mem)
     }
 
-    pub fn remove(&mut self, obj: T) {
-        let parent = self.parent(obj);
+    pub fn remove(&mut self, obj: T) -> Result<(), Error> {
+        let parent = self.parent(obj)?;
 
         if num::Zero::is_zero(&parent) {
-            return;
+            return Ok(());
         }
 
-        let obj_sibling = self.sibling(obj);
-        let mut child = self.child(parent);
+        let obj_sibling = self.sibling(obj)?;
+        let mut child = self.child(parent)?;
 
         if child == obj {
             //immediate child
-            self.write_child(parent, obj_sibling);
+            self.write_child(parent, obj_sibling)?;
         } else {
             while !num::Zero::is_zero(&child) {
-                let sibling = self.sibling(child);
+                let sibling = self.sibling(child)?;
 
                 if sibling == obj {
-                    self.write_sibling(child, obj_sibling);
+                    self.write_sibling(child, obj_sibling)?;
                     break;
                 } else {
                     child = sibling;
@@ -173,33 +172,34 @@ where
             }
         }
 
-        self.write_sibling(obj, num::Zero::zero());
-        self.write_parent(obj, num::Zero::zero());
+        self.write_sibling(obj, num::Zero::zero())?;
+        self.write_parent(obj, num::Zero::zero())
     }
 
-    pub fn insert(&mut self, obj: T, dest_obj: T) {
-        if !num::Zero::is_zero(&self.parent(obj)) {
-            self.remove(obj);
+    pub fn insert(&mut self, obj: T, dest_obj: T) -> Result<(), Error> {
+        if !num::Zero::is_zero(&self.parent(obj)?) {
+            self.remove(obj)?;
         }
 
-        self.write_sibling(obj, self.child(dest_obj));
-        self.write_child(dest_obj, obj);
-        self.write_parent(obj, dest_obj);
+        self.write_sibling(obj, self.child(dest_obj)?)?;
+        self.write_child(dest_obj, obj)?;
+        self.write_parent(obj, dest_obj)
     }
 
     //print
 
-    pub fn name(&mut self, obj: T) -> Option<String> {
-        if self.mem.read_u8(self.props(obj)) != 0 {
+    pub fn name(&mut self, obj: T) -> Result<Option<String>, Error> {
+        let props = self.props(obj)? as u32;
+        if self.mem.fetch_u8(props)? != 0 {
             let mut zscii = zscii::Zscii::new(self.mem);
-            Some(zscii.get_string(self.props(obj) + 1))
+            Ok(Some(zscii.get_string(props + 1)?))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    pub fn status(&self) -> u16 {
-        let global_val = self.mem.read_global(16);
+    pub fn status(&self) -> Result<u16, Error> {
+        let global_val = self.mem.read_global(16)?;
         // We need to convert u16 global val to T (u8 or u16).
         // Since T is Integer + Copy, maybe use num::cast if we add the bound, 
         // or since we know T is u8 or u16, and we want to index...
@@ -215,222 +215,341 @@ where
         // Let's use `num::cast` if available.
         // T::from_u16(global_val).unwrap()
         let obj_id = T::from(global_val).unwrap_or_else(|| T::zero()); // Fallback or panic?
-        self.mem
-            .read_u16(self.object_ptr(obj_id))
+        self.mem.checked_read_u16(self.object_ptr(obj_id))
+    }
+
+    //the object's property table entries, after its name
+    pub fn properties(&self, obj: T) -> Result<Properties<'_>, Error> {
+        Ok(Properties::new(self.mem, Object::<T>::WIDE, self.props(obj)?))
+    }
+
+    pub fn find_prop(&self, obj: T, prop_id: u8) -> Result<Option<Property<'_>>, Error> {
+        Ok(self.properties(obj)?.find(|p| p.number == prop_id))
+    }
+
+    //returns address to the property *value* not the size byte, or 0 if there's no such property
+    pub fn get_prop_addr(&self, obj: T, prop_id: u8) -> Result<u16, Error> {
+        Ok(self.find_prop(obj, prop_id)?.map_or(0, |p| p.addr))
     }
 
-    //The property number occupies the bottom 6 bits of the first size byte.
-    pub fn get_prop_len(&self, addr: u16) -> u8 {
-        //see sections 12.4.2.1.1 - 12.4.2.2 in standards doc:
-        //If the top bit (bit 7) of the first size byte is clear, then there is only one size-and-number byte.
-        //Bits 0 to 5 contain the property number; bit 6 is either clear to indicate a property data length of 1,
-        //or set to indicate a length of 2; bit 7 is clear.
+    //the length of the property whose value is at addr, from the size byte just before it.
+    //get_prop_len 0 is 0, so it can follow a get_prop_addr which found nothing
+    pub fn get_prop_len(&self, addr: u16) -> Result<u8, Error> {
         if addr == 0 {
-            0
-        } else if Object::<T>::WIDE {
-            let sz = self.mem.read_u8(addr - 1);
-            if sz == 0 {
-                if sz & 0x40 == 0 {
-                    1
-                } else {
-                    2
-                }
-            } else if sz & 0x3F == 0 {
-                //if first 6 bits 111111 are all 0
-                //From stds doc: A value of 0 as property data length (in the second byte) should be interpreted as a length of 64
-                64
-            } else {
-                sz
-            }
+            Ok(0)
         } else {
-            (self.mem.read_u8(addr - 1) >> 5) + 1
+            Ok(data_len(self.mem.checked_read_u8(addr - 1)?, Object::<T>::WIDE) as u8)
         }
     }
 
-    //returns address to the property *value* not the size byte
-    pub fn get_prop_addr(&self, obj: T, prop_id: u8) -> PropAddr {
-        let top_prop_table_addr = self.props(obj);
-        //skip name to first property
-        let mut property_addr =
-            top_prop_table_addr + self.mem.read_u8(top_prop_table_addr) as u16 * 2 + 1;
-        if Object::<T>::WIDE {
-            loop {
-                let size = self.mem.read_u8(property_addr);
-                let id = size & 0x3f; //Bits 0 to 5 contain the property number
-                if size == 0 {
-                    break;
-                }
-                if id == prop_id {
-                    //bit 7 0x80 indicates if there's one size-and-number byte or two
-                    if size & 0x80 == 0 {
-                        // bit 6 - 0x40 -  is either clear to indicate a property data length of 1, or set to indicate a length of 2
-                        return PropAddr {
-                            addr: property_addr + 1,
-                            size_bytes: 1,
-                            data_length: if size & 0x40 == 0 { 1 } else { 2 },
-                        };
-                    } else {
-                        return PropAddr {
-                            addr: property_addr + 2,
-                            size_bytes: 2,
-                            data_length: if size & 0x40 == 0 { 1 } else { 2 },
-                        };
-                    }
-                } else if size & 0x80 == 0 {
-                    if size & 0x40 == 0 {
-                        //one size & number byte, prop data length 1
-                        property_addr += 2; //1 hdr , 1 data
-                    } else {
-                        //one size & number bytes, data length 2
-                        property_addr += 3; //1 hdr, 2 data
-                    }
-                } else {
-                    let next_id = self.mem.read_u8(property_addr + 1) & 0x3f;
-                    if next_id == 0 {
-                        property_addr += 64 + 2;
-                    } else {
-                        property_addr += next_id as u16 + 2;
-                    }
-                }
-            }
-            PropAddr {
-                addr: 0,
-                size_bytes: 1,
-                data_length: 1,
-            }
-        } else {
-            //scan each property for prop_id
-            while self.mem.read_u8(property_addr) != 0 {
-                let size = self.mem.read_u8(property_addr);
-                if size & 0x1f == prop_id {
-                    return PropAddr {
-                        addr: property_addr + 1,
-                        size_bytes: 1,
-                        data_length: 1,
-                    };
-                } else {
-                    property_addr += (size >> 5) as u16 + 2;
-                }
-            }
-            PropAddr {
-                addr: 0,
-                size_bytes: 1,
-                data_length: 1,
-            }
+    //properties are stored in descending order, so the next is the one stored after prop_id,
+    //or the first one for 0. It's 0 after the last one
+    pub fn get_prop_next(&self, obj: T, prop_id: u8) -> Result<u8, Error> {
+        let mut props = self.properties(obj)?;
+        if prop_id != 0 && !props.any(|p| p.number == prop_id) {
+            return Err(Error::illegal(format!(
+                "Object {} has no property {} to find the next one after",
+                obj.into(),
+                prop_id
+            )));
         }
+        Ok(props.next().map_or(0, |p| p.number))
     }
 
-    pub fn get_prop_next(&self, obj: T, prop_id: u8) -> u8 {
-        let top_prop_table_addr = self.props(obj);
-        //skip name to first property
-        let mut property_addr =
-            top_prop_table_addr + self.mem.read_u8(top_prop_table_addr) as u16 * 2 + 1;
-        if Object::<T>::WIDE {
-            if prop_id == 0 {
-                (self.mem.read_u8(property_addr) * 2 + 1) & 0x3f
-            } else {
-                let propaddr = self.get_prop_addr(obj, prop_id);
-                let mut addr = propaddr.addr;
-                if propaddr.size_bytes & 0x80 == 0 {
-                    //prop data length 1
-                    if propaddr.size_bytes & 0x40 == 0 {
-                        //one size & number byte
-                        addr += 1;
-                    } else {
-                        //two size & number bytes
-                        addr += 2;
-                    }
-                } else {
-                    let size = self.mem.read_u8(addr - 1) & 0x3f;
-                    if size == 0 {
-                        addr += 64;
-                    } else {
-                        addr += size as u16;
-                    }
-                }
-                self.mem.read_u8(addr) & 0x3f
+    //a property the object doesn't have reads as its default from the top of the object table
+    pub fn get_prop(&self, obj: T, prop_id: u8) -> Result<u16, Error> {
+        match self.find_prop(obj, prop_id)? {
+            Some(prop) => match prop.data {
+                [byte] => Ok(*byte as u16),
+                [hi, lo] => Ok(u16::from_be_bytes([*hi, *lo])),
+                data => Err(too_long(obj.into(), prop_id, data.len())),
+            },
+            None if (1..=Object::<T>::PROPMAX).contains(&(prop_id as u16)) => {
+                self.mem.checked_read_u16(self.mem.object_table().wrapping_add((prop_id as u16 - 1) * 2))
             }
-        } else if prop_id == 0 {
-            //return first prop, bits 0-4
-            self.mem.read_u8(property_addr) & 0x1f
-        } else {
-            while self.mem.read_u8(property_addr) != 0 {
-                let size = self.mem.read_u8(property_addr);
-                if size & 0x1f == prop_id {
-                    return self.mem.read_u8(property_addr + (size >> 5) as u16 + 2) & 0x1f;
-                } else {
-                    property_addr += (size >> 5) as u16 + 2;
-                }
+            None => Err(Error::illegal(format!("Illegal property {}", prop_id))),
+        }
+    }
+
+    //only properties the object has, of a byte or a word, can be written
+    pub fn put_prop(&mut self, obj: T, prop_id: u8, val: u16) -> Result<(), Error> {
+        let (addr, len) = match self.find_prop(obj, prop_id)? {
+            Some(prop) => (prop.addr, prop.data.len()),
+            None => {
+                return Err(Error::illegal(format!(
+                    "Object {} has no property {} to write",
+                    obj.into(),
+                    prop_id
+                )))
             }
-            0
+        };
+        match len {
+            1 => self.mem.checked_write_u8(addr, val as u8),
+            2 => self.mem.checked_write_u16(addr, val),
+            _ => Err(too_long(obj.into(), prop_id, len)),
         }
     }
+}
 
-    pub fn get_prop(self, obj: T, prop_id: u8) -> u16 {
-        let prop_addr = self.get_prop_addr(obj, prop_id);
-        if Object::<T>::WIDE {
-            if prop_addr.addr == 0 {
-                //subtract 2 so we can do 1-based indexing/access
-                let prop_ptr = self.mem.object_table() - 2;
-                self.mem.read_u16(prop_ptr + (prop_id * 2) as u16) //tbd default_prop_ptr
-            } else if prop_addr.size_bytes & 0x80 == 0 {
-                if prop_addr.size_bytes & 0x40 == 0 {
-                    self.mem.read_u8(prop_addr.addr) as u16
-                } else {
-                    self.mem.read_u16(prop_addr.addr)
-                }
-            } else {
-                //DIE as property not byte or word sized - TODO error handling
-                0
+fn too_long(obj: u16, prop_id: u8, len: usize) -> Error {
+    Error::illegal(format!(
+        "Property {} of object {} is {} bytes, not a byte or a word",
+        prop_id, obj, len
+    ))
+}
+
+//see 12.4: in V1-3 the size byte has the length - 1 in its top 3 bits. From V4 bit 6 gives a
+//length of 1 or 2, unless bit 7 is set, when a second size byte with bit 7 set follows, holding
+//the length with 0 meaning 64. Either way the length comes from the byte before the data
+fn data_len(size: u8, wide: bool) -> usize {
+    if !wide {
+        (size >> 5) as usize + 1
+    } else if size & 0x80 != 0 {
+        match size & 0x3F {
+            0 => 64,
+            len => len as usize,
+        }
+    } else if size & 0x40 != 0 {
+        2
+    } else {
+        1
+    }
+}
+
+/// A read-only view of the object table which reads the same way for every version, for
+/// tools looking at a game's world while it's paused. Objects are numbered from 1, and
+/// 0 means none. Reads past the end of the story give 0 rather than panicking.
+pub struct ObjectTree<'a> {
+    mem: &'a Memory,
+    wide: bool, //V4+ objects: 48 attributes, 16 bit object numbers and 63 properties
+    count: u16,
+}
+
+/// One entry of an object's property table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Property<'a> {
+    pub number: u8,
+    pub addr: u16, //of the data, after the size byte or bytes
+    pub data: &'a [u8],
+}
+
+/// The entries of a property table, in the order they're stored (descending numbers).
+pub struct Properties<'a> {
+    mem: &'a Memory,
+    wide: bool,
+    addr: u16,
+}
+
+impl<'a> ObjectTree<'a> {
+    pub fn new(mem: &'a Memory) -> ObjectTree<'a> {
+        let mut tree = ObjectTree {
+            mem,
+            wide: mem.zmachine_version() >= 4,
+            count: 0,
+        };
+        tree.count = tree.find_count();
+        tree
+    }
+
+    //the header doesn't say how many objects there are, but the property tables
+    //conventionally follow the last one, so the lowest property table marks the end
+    fn find_count(&self) -> u16 {
+        if self.mem.object_table() == 0 {
+            return 0;
+        }
+        let max = if self.wide { 0xFFFF } else { 0xFF };
+        let mut end = self.mem.len() as u32;
+        let mut count = 0;
+        while count < max {
+            let ptr = self.ptr(count + 1);
+            if ptr + self.size() > end {
+                break;
             }
+            count += 1;
+            end = end.min(self.word(ptr + self.props_offset()) as u32);
+        }
+        count
+    }
+
+    fn size(&self) -> u32 {
+        if self.wide {
+            14
         } else {
-            let mut addr = prop_addr.addr;
-            while self.mem.read_u8(addr) != 0 {
-                let size = self.mem.read_u8(addr);
-                if size & 0x1f == prop_id {
-                    match size >> 5 {
-                        0 => return self.mem.read_u8(addr + 1) as u16,
-                        1 => return self.mem.read_u16(addr + 1),
-                        _ => {} //TODO die
-                    }
-                } else {
-                    addr += (size >> (5 + 2)) as u16;
-                }
-            }
+            9
+        }
+    }
 
-            self.mem
-                .read_u16((self.mem.object_table() - 2) + prop_id as u16 * 2)
+    fn props_offset(&self) -> u32 {
+        if self.wide {
+            12
+        } else {
+            7
         }
     }
 
-    pub fn put_prop(self, obj: T, prop_id: u8, val: u16) {
-        let prop_addr = self.get_prop_addr(obj, prop_id);
-        if Object::<T>::WIDE {
-            if prop_addr.addr == 0 {
-                //TODO DIE
-            }
-            if prop_addr.size_bytes & 0x80 == 0 {
-                if prop_addr.size_bytes & 0x40 == 0 {
-                    self.mem.write_u8(prop_addr.addr, val as u8);
-                } else {
-                    self.mem.write_u16(prop_addr.addr, val);
-                }
-            }
+    fn ptr(&self, obj: u16) -> u32 {
+        let defaults = if self.wide { 63 } else { 31 };
+        self.mem.object_table() as u32 + defaults * 2 + (obj as u32 - 1) * self.size()
+    }
+
+    fn byte(&self, addr: u32) -> u8 {
+        self.mem.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    fn word(&self, addr: u32) -> u16 {
+        (self.byte(addr) as u16) << 8 | self.byte(addr + 1) as u16
+    }
+
+    //the entry of a parent, sibling or child link
+    fn link(&self, obj: u16, narrow: u32, wide: u32) -> Option<u16> {
+        if !self.exists(obj) {
+            return None;
+        }
+        let link = if self.wide {
+            self.word(self.ptr(obj) + wide)
         } else {
-            let mut property_addr =
-                self.props(obj) + self.mem.read_u8(self.props(obj)) as u16 * 2 + 1;
-            while self.mem.read_u8(property_addr) != 0 {
-                let size = self.mem.read_u8(property_addr);
-                if size & 0x1f == prop_id {
-                    match size >> 5 {
-                        0 => return self.mem.write_u8(property_addr + 1, val as u8),
-                        1 => return self.mem.write_u16(property_addr + 1, val),
-                        _ => {} //DIE
-                    }
-                } else {
-                    property_addr += ((size >> 5) + 2) as u16;
-                }
-            }
+            self.byte(self.ptr(obj) + narrow) as u16
+        };
+        (link != 0).then_some(link)
+    }
+
+    /// The number of objects in the table.
+    pub fn count(&self) -> u16 {
+        self.count
+    }
+
+    /// The end of the last object's entry, where the property tables usually start.
+    pub fn entries_end(&self) -> u32 {
+        self.ptr(1) + self.count as u32 * self.size()
+    }
+
+    pub fn exists(&self, obj: u16) -> bool {
+        (1..=self.count).contains(&obj)
+    }
+
+    /// The object's short name, or `None` if it has none, there's no such object or the
+    /// name runs off the end of the story file.
+    pub fn name(&self, obj: u16) -> Option<String> {
+        let props = self.properties_addr(obj)?;
+        if self.byte(props as u32) == 0 {
+            return None;
         }
+        zscii::Zscii::new(self.mem).get_string(props as u32 + 1).ok()
+    }
+
+    pub fn parent(&self, obj: u16) -> Option<u16> {
+        self.link(obj, 4, 6)
+    }
+
+    pub fn sibling(&self, obj: u16) -> Option<u16> {
+        self.link(obj, 5, 8)
+    }
+
+    pub fn child(&self, obj: u16) -> Option<u16> {
+        self.link(obj, 6, 10)
+    }
+
+    /// The objects directly inside `obj`, first child first. Stops at an object which
+    /// doesn't exist, and after `count` objects in case the tree loops.
+    pub fn children(&self, obj: u16) -> impl Iterator<Item = u16> + '_ {
+        std::iter::successors(self.child(obj), |&c| self.sibling(c))
+            .take_while(|&c| self.exists(c))
+            .take(self.count as usize)
+    }
+
+    /// The objects with no parent, in number order.
+    pub fn roots(&self) -> impl Iterator<Item = u16> + '_ {
+        (1..=self.count).filter(|&obj| self.parent(obj).is_none())
+    }
+
+    pub fn has_attribute(&self, obj: u16, attr: u8) -> bool {
+        let max = if self.wide { 48 } else { 32 };
+        //attribute 0 is the top bit of the first byte
+        self.exists(obj) && attr < max && self.byte(self.ptr(obj) + attr as u32 / 8) & (0x80 >> (attr % 8)) != 0
+    }
+
+    /// The numbers of the attributes `obj` has, in ascending order.
+    pub fn attributes(&self, obj: u16) -> Vec<u8> {
+        let max = if self.wide { 48 } else { 32 };
+        (0..max).filter(|&attr| self.has_attribute(obj, attr)).collect()
+    }
+
+    /// The address of the object's property table, which starts with its name.
+    pub fn properties_addr(&self, obj: u16) -> Option<u16> {
+        self.exists(obj)
+            .then(|| self.word(self.ptr(obj) + self.props_offset()))
+    }
+
+    /// The object's properties, with their raw data. Empty if there's no such object.
+    pub fn properties(&self, obj: u16) -> Properties<'a> {
+        match self.properties_addr(obj) {
+            Some(table) => Properties::new(self.mem, self.wide, table),
+            None => Properties {
+                mem: self.mem,
+                wide: self.wide,
+                addr: self.mem.len().min(u16::MAX as usize) as u16,
+            },
+        }
+    }
+
+    /// The object's property `number`, if it has it.
+    pub fn property(&self, obj: u16, number: u8) -> Option<Property<'a>> {
+        self.properties(obj).find(|p| p.number == number)
+    }
+
+    /// The value of property `number` for objects without it, from 1 to 31 (V1-3) or 63.
+    pub fn default_property(&self, number: u8) -> Option<u16> {
+        let max = if self.wide { 63 } else { 31 };
+        if !(1..=max).contains(&number) || self.mem.object_table() == 0 {
+            return None;
+        }
+        Some(self.word(self.mem.object_table() as u32 + (number as u32 - 1) * 2))
+    }
+}
+
+impl<'a> Properties<'a> {
+    //from the property table at `table`, skipping the object's name
+    fn new(mem: &'a Memory, wide: bool, table: u16) -> Properties<'a> {
+        let name = mem.get(table as usize).copied().unwrap_or(0) as u16;
+        Properties {
+            mem,
+            wide,
+            addr: table.saturating_add(name * 2 + 1),
+        }
+    }
+
+    /// The address of the next entry, or of the 0 ending the table once all have been read.
+    pub fn addr(&self) -> u16 {
+        self.addr
     }
 }
+
+impl<'a> Iterator for Properties<'a> {
+    type Item = Property<'a>;
+
+    fn next(&mut self) -> Option<Property<'a>> {
+        let size = *self.mem.get(self.addr as usize)?;
+        if size == 0 {
+            return None;
+        }
+        let (number, header) = if self.wide {
+            (size & 0x3F, if size & 0x80 != 0 { 2 } else { 1 })
+        } else {
+            (size & 0x1F, 1)
+        };
+        //a two byte header's length is in the second byte
+        let last = self.mem.get(self.addr as usize + header - 1).copied().unwrap_or(0);
+        let len = data_len(if header == 2 { last | 0x80 } else { last }, self.wide);
+        let start = self.addr as usize + header;
+        let data = self.mem.get(start..start + len)?;
+        self.addr = (start + len).min(u16::MAX as usize) as u16;
+        Some(Property {
+            number,
+            addr: start as u16,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod object_tests;
@@ -0,0 +1,264 @@
+use super::*;
+
+// V3 story with its object table at 0x100: object 1 holds 2 and 3, and the property
+// tables start at 0x160
+fn create_v3_story() -> Vec<u8> {
+    let mut data = vec![0u8; 0x200];
+    data[0x00] = 3;
+    data[0x0A] = 0x01; // Object table at 0x100
+    data[0x0C] = 0x01;
+    data[0x0D] = 0xC0; // Globals at 0x1C0
+    data[0x0E] = 0x02; // Static memory at 0x200
+    data[0x100..0x104].copy_from_slice(&[0x00, 0x07, 0x00, 0x09]); // Defaults 1 & 2
+    // attributes 0 & 31; parent, sibling, child; properties
+    data[0x13E..0x147].copy_from_slice(&[0x80, 0x00, 0x00, 0x01, 0, 0, 2, 0x01, 0x60]);
+    data[0x147..0x150].copy_from_slice(&[0x00, 0x00, 0x00, 0x00, 1, 3, 0, 0x01, 0x66]);
+    data[0x150..0x159].copy_from_slice(&[0x00, 0x20, 0x00, 0x00, 1, 0, 0, 0x01, 0x6D]);
+    // "hi", property 5 = AB; no name, property 18 = 01 02 03 04; no name or properties
+    data[0x160..0x166].copy_from_slice(&[0x01, 0xB5, 0xC5, 0x05, 0xAB, 0x00]);
+    data[0x166..0x16F].copy_from_slice(&[0x00, 0x72, 0x01, 0x02, 0x03, 0x04, 0x00, 0x00, 0x00]);
+    data
+}
+
+#[test]
+fn test_tree() {
+    let mem = Memory::new(&create_v3_story());
+    let tree = ObjectTree::new(&mem);
+    assert_eq!(tree.count(), 3);
+    assert!(!tree.exists(0));
+    assert!(!tree.exists(4));
+    assert_eq!(tree.name(1), Some("hi".to_string()));
+    assert_eq!(tree.name(2), None);
+    assert_eq!(tree.parent(1), None);
+    assert_eq!(tree.parent(3), Some(1));
+    assert_eq!(tree.child(1), Some(2));
+    assert_eq!(tree.sibling(2), Some(3));
+    assert_eq!(tree.children(1).collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(tree.children(2).count(), 0);
+    assert_eq!(tree.roots().collect::<Vec<_>>(), vec![1]);
+    assert_eq!(tree.parent(4), None);
+}
+
+#[test]
+fn test_children_stop_at_a_loop() {
+    let mut story = create_v3_story();
+    story[0x150 + 5] = 2; // object 3's sibling is object 2
+    let mem = Memory::new(&story);
+    assert_eq!(ObjectTree::new(&mem).children(1).count(), 3);
+}
+
+#[test]
+fn test_name_at_end_of_story() {
+    let mut story = create_v3_story();
+    // object 3's properties are the last byte, a name length with no name after it
+    story[0x157..0x159].copy_from_slice(&[0x01, 0xFF]);
+    story[0x1FF] = 1;
+    let mem = Memory::new(&story);
+    assert_eq!(ObjectTree::new(&mem).name(3), None);
+}
+
+#[test]
+fn test_attributes_and_properties() {
+    let mem = Memory::new(&create_v3_story());
+    let tree = ObjectTree::new(&mem);
+    assert_eq!(tree.attributes(1), vec![0, 31]);
+    assert_eq!(tree.attributes(3), vec![10]);
+    assert!(tree.has_attribute(3, 10));
+    assert!(!tree.has_attribute(3, 32));
+
+    let props: Vec<Property> = tree.properties(2).collect();
+    assert_eq!(
+        props,
+        vec![Property {
+            number: 18,
+            addr: 0x168,
+            data: &[1, 2, 3, 4]
+        }]
+    );
+    assert_eq!(tree.property(1, 5).unwrap().data, &[0xAB]);
+    assert_eq!(tree.property(1, 6), None);
+    assert_eq!(tree.properties(3).count(), 0);
+    assert_eq!(tree.properties(9).count(), 0);
+
+    assert_eq!(tree.default_property(1), Some(7));
+    assert_eq!(tree.default_property(2), Some(9));
+    assert_eq!(tree.default_property(0), None);
+    assert_eq!(tree.default_property(32), None);
+}
+
+#[test]
+fn test_v5_tree() {
+    let mut data = vec![0u8; 0x300];
+    data[0x00] = 5;
+    data[0x0A] = 0x01; // Object table at 0x100
+    // objects at 0x17E: 1 has attribute 47 and holds 2
+    data[0x17E + 5] = 0x01;
+    data[0x17E + 11] = 2;
+    data[0x17E + 12..0x17E + 14].copy_from_slice(&[0x01, 0x9A]);
+    data[0x18C + 7] = 1;
+    data[0x18C + 12..0x18C + 14].copy_from_slice(&[0x01, 0x9C]);
+    // properties at 0x19A, 2 has property 40 = 01 02 03 with a two byte header, property 3 = 0A 0B
+    data[0x19C..0x1A5].copy_from_slice(&[0x00, 0xA8, 0x03, 1, 2, 3, 0x43, 0x0A, 0x0B]);
+    let mem = Memory::new(&data);
+    let tree = ObjectTree::new(&mem);
+    assert_eq!(tree.count(), 2);
+    assert_eq!(tree.attributes(1), vec![47]);
+    assert_eq!(tree.parent(2), Some(1));
+    assert_eq!(tree.children(1).collect::<Vec<_>>(), vec![2]);
+    let props: Vec<(u8, &[u8])> = tree.properties(2).map(|p| (p.number, p.data)).collect();
+    assert_eq!(props, vec![(40, &[1u8, 2, 3][..]), (3, &[0x0A, 0x0B][..])]);
+    assert_eq!(tree.default_property(63), Some(0));
+}
+
+// V5 story with 300 objects at 0x17E, each 14 bytes, and room for them in dynamic memory
+fn create_v5_memory() -> Memory {
+    let mut data = vec![0u8; 0x2000];
+    data[0x00] = 5;
+    data[0x0A] = 0x01; // Object table at 0x100
+    data[0x0E] = 0x18; // Static memory at 0x1800
+    let props: u16 = 0x17E + 300 * 14;
+    for obj in 0..300 {
+        let at = 0x17E + obj * 14 + 12;
+        data[at..at + 2].copy_from_slice(&props.to_be_bytes());
+    }
+    Memory::new(&data)
+}
+
+#[test]
+fn test_v5_attributes() {
+    let mut mem = create_v5_memory();
+    let mut obj = Object::<u16>::new(&mut mem);
+    obj.attr_set(2, 0).unwrap();
+    obj.attr_set(2, 47).unwrap();
+    obj.attr_set(2, 48).unwrap(); // no such attribute
+    let at = obj.object_ptr(2);
+    assert_eq!(obj.mem[at], 0x80);
+    assert_eq!(obj.mem[at + 5], 0x01);
+    assert_eq!(obj.mem[at + 6], 0x00); // the parent is untouched
+    assert!(obj.attr_test(2, 47).unwrap());
+    assert!(!obj.attr_test(2, 46).unwrap());
+    assert!(!obj.attr_test(2, 48).unwrap());
+    assert!(!obj.attr_test(3, 47).unwrap());
+
+    obj.attr_clear(2, 47).unwrap();
+    assert!(!obj.attr_test(2, 47).unwrap());
+    assert!(obj.attr_test(2, 0).unwrap());
+    assert_eq!(ObjectTree::new(&mem).attributes(2), vec![0]);
+}
+
+#[test]
+fn test_v5_tree_links() {
+    let mut mem = create_v5_memory();
+    let mut obj = Object::<u16>::new(&mut mem);
+    obj.insert(300, 2).unwrap();
+    obj.insert(299, 2).unwrap();
+    assert_eq!(obj.mem.read_u16(obj.object_ptr(300) + 6), 2);
+    assert_eq!(obj.mem.read_u16(obj.object_ptr(2) + 10), 299);
+    assert_eq!(obj.mem.read_u16(obj.object_ptr(299) + 8), 300);
+    assert!(obj.inside(300, 2).unwrap());
+    assert!(!obj.inside(2, 300).unwrap());
+
+    obj.insert(300, 299).unwrap();
+    assert_eq!(obj.sibling(299).unwrap(), 0);
+    assert_eq!(obj.parent(300).unwrap(), 299);
+    assert_eq!(obj.child(299).unwrap(), 300);
+
+    let tree = ObjectTree::new(&mem);
+    assert_eq!(tree.count(), 300);
+    assert_eq!(tree.children(2).collect::<Vec<_>>(), vec![299]);
+    assert_eq!(tree.children(299).collect::<Vec<_>>(), vec![300]);
+
+    let mut obj = Object::<u16>::new(&mut mem);
+    obj.remove(299).unwrap();
+    assert_eq!(obj.child(2).unwrap(), 0);
+    assert_eq!(obj.parent(299).unwrap(), 0);
+    assert_eq!(obj.child(299).unwrap(), 300);
+}
+
+#[test]
+fn test_links_past_memory() {
+    // object 2's sibling is object 200, whose entry is past the end of the story
+    let mut data = create_v3_story();
+    data[0x14C] = 200;
+    let mut mem = Memory::new(&data);
+    let mut obj = Object::<u8>::new(&mut mem);
+    assert!(matches!(obj.remove(3), Err(Error::OutOfBounds { .. })));
+    assert!(obj.get_prop_len(0xFFFF).is_err());
+
+    mem.permissive = true;
+    let mut obj = Object::<u8>::new(&mut mem);
+    assert_eq!(obj.sibling(200).unwrap(), 0);
+    assert!(obj.remove(3).is_ok());
+}
+
+#[test]
+fn test_v3_properties() {
+    let mut mem = Memory::new(&create_v3_story());
+    let mut obj = Object::<u8>::new(&mut mem);
+    assert_eq!(obj.get_prop(1, 5).unwrap(), 0xAB);
+    assert_eq!(obj.get_prop(1, 2).unwrap(), 9); // the default
+    assert!(obj.get_prop(2, 18).is_err()); // 4 bytes long
+    assert!(obj.get_prop(1, 0).is_err());
+    assert!(obj.get_prop(1, 32).is_err());
+
+    assert_eq!(obj.get_prop_addr(2, 18).unwrap(), 0x168);
+    assert_eq!(obj.get_prop_len(0x168).unwrap(), 4);
+    assert_eq!(obj.get_prop_addr(2, 5).unwrap(), 0);
+    assert_eq!(obj.get_prop_len(0).unwrap(), 0);
+
+    assert_eq!(obj.get_prop_next(1, 0).unwrap(), 5);
+    assert_eq!(obj.get_prop_next(1, 5).unwrap(), 0);
+    assert_eq!(obj.get_prop_next(3, 0).unwrap(), 0);
+    assert!(obj.get_prop_next(1, 6).is_err());
+
+    obj.put_prop(1, 5, 0x1234).unwrap();
+    assert_eq!(obj.get_prop(1, 5).unwrap(), 0x34);
+    assert!(obj.put_prop(1, 6, 1).is_err());
+    assert!(obj.put_prop(2, 18, 1).is_err());
+    assert_eq!(obj.mem.read_u16(0x168), 0x0102);
+}
+
+#[test]
+fn test_v5_properties() {
+    let mut mem = create_v5_memory();
+    let at = Object::<u16>::new(&mut mem).object_ptr(2) + 12;
+    mem.write_u16(at, 0x1300);
+    // no name; property 40 of 64 bytes, 20 of 3, both with two size bytes; 3 = 1234; 1 = 56
+    mem.write_u8(0x1301, 0xA8);
+    mem.write_u8(0x1302, 0x80);
+    for (addr, byte) in (0x1343..).zip([0x94, 0x83, 1, 2, 3, 0x43, 0x12, 0x34, 0x01, 0x56, 0x00]) {
+        mem.write_u8(addr, byte);
+    }
+
+    let mut obj = Object::<u16>::new(&mut mem);
+    let props: Vec<(u8, u16, usize)> = obj.properties(2).unwrap().map(|p| (p.number, p.addr, p.data.len())).collect();
+    assert_eq!(props, vec![(40, 0x1303, 64), (20, 0x1345, 3), (3, 0x1349, 2), (1, 0x134C, 1)]);
+    for (number, addr, len) in props {
+        assert_eq!(obj.get_prop_addr(2, number).unwrap(), addr);
+        assert_eq!(obj.get_prop_len(addr).unwrap() as usize, len);
+    }
+
+    let mut next = vec![];
+    let mut prop = 0;
+    loop {
+        prop = obj.get_prop_next(2, prop).unwrap();
+        if prop == 0 {
+            break;
+        }
+        next.push(prop);
+    }
+    assert_eq!(next, vec![40, 20, 3, 1]);
+
+    assert_eq!(obj.get_prop(2, 3).unwrap(), 0x1234);
+    assert_eq!(obj.get_prop(2, 1).unwrap(), 0x56);
+    assert_eq!(obj.get_prop(2, 63).unwrap(), 0);
+    assert!(obj.get_prop(2, 20).is_err());
+    assert!(obj.get_prop(2, 64).is_err());
+
+    obj.put_prop(2, 1, 0xFFEE).unwrap();
+    obj.put_prop(2, 3, 0xFFEE).unwrap();
+    assert_eq!(obj.get_prop(2, 1).unwrap(), 0xEE);
+    assert_eq!(obj.get_prop(2, 3).unwrap(), 0xFFEE);
+    assert!(obj.put_prop(2, 40, 1).is_err());
+    assert!(obj.put_prop(2, 5, 1).is_err());
+}
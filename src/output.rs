@@ -0,0 +1,122 @@
+use crate::zscreen::ZScreen;
+
+/*
+ * The output path between the print opcodes and the frontend. By default text goes
+ * straight through to ZScreen, fragment by fragment. With word wrap turned on, buffered
+ * lower window text is broken into lines at the screen width in the header and the
+ * frontend is handed whole lines: `print` with the line, then `newline`. Widths are
+ * counted in characters, as if every character were as wide as the rest; measuring
+ * proportional fonts is out of scope, so a frontend using one should wrap text itself.
+ * Text the game lays out itself - the upper window, which is never buffered, and
+ * fixed-pitch text - always goes straight through.
+ *
+ * With a MORE prompt set, lower window lines are counted from the last input and output
+ * pauses with the prompt, waiting on ZScreen::read, each time a screenful has gone by.
+ */
+pub struct Output {
+    pub wrap: bool,
+    pub buffered: bool,       //set by buffer_mode
+    pub more: Option<String>, //the MORE prompt; paging is off without one
+    pub width: usize,         //line width to wrap at, 0 for none
+    pub page: usize,          //lines on a page, 0 for no paging
+    line: Vec<char>,          //text not yet handed to the frontend
+    column: usize,            //characters already printed on the current line
+    lines: usize,             //lines output since the last input
+}
+
+impl Default for Output {
+    fn default() -> Output {
+        Output::new()
+    }
+}
+
+impl Output {
+    pub fn new() -> Output {
+        Output {
+            wrap: false,
+            buffered: true,
+            more: None,
+            width: 0,
+            page: 0,
+            line: vec![],
+            column: 0,
+            lines: 0,
+        }
+    }
+
+    //adds text to the current line, handing any lines it completes to the frontend
+    pub fn print(&mut self, screen: &dyn ZScreen, text: &str) {
+        for ch in text.chars() {
+            if ch == '\n' {
+                self.newline(screen);
+                continue;
+            }
+            self.line.push(ch);
+            while self.width > 0 && self.column + self.line.len() > self.width {
+                self.break_line(screen);
+            }
+        }
+    }
+
+    pub fn newline(&mut self, screen: &dyn ZScreen) {
+        self.flush(screen);
+        screen.newline();
+        self.column = 0;
+
+        //only lines on a page count, so upper window text doesn't bring on the prompt
+        if self.page == 0 {
+            return;
+        }
+        //keep the last line of the page for the prompt
+        self.lines += 1;
+        if let Some(more) = &self.more {
+            if self.page > 1 && self.lines >= self.page - 1 {
+                screen.print(more.clone());
+                screen.read();
+                screen.newline();
+                self.lines = 0;
+            }
+        }
+    }
+
+    //the player has read everything so far
+    pub fn reset_pager(&mut self) {
+        self.lines = 0;
+    }
+
+    //hands over the partial line, eg. before input so the prompt is shown
+    pub fn flush(&mut self, screen: &dyn ZScreen) {
+        if !self.line.is_empty() {
+            screen.print(self.line.iter().collect());
+            self.column += self.line.len();
+            self.line.clear();
+        }
+    }
+
+    //breaks an over-long line at the last space that fits, or mid-word if there is none
+    fn break_line(&mut self, screen: &dyn ZScreen) {
+        let room = self.width.saturating_sub(self.column);
+        let space = self.line[..=room.min(self.line.len() - 1)]
+            .iter()
+            .rposition(|&c| c == ' ');
+
+        let rest = match space {
+            Some(pos) => {
+                let rest = self.line.split_off(pos + 1);
+                while self.line.last() == Some(&' ') {
+                    self.line.pop();
+                }
+                rest
+            }
+            //the word started on a line which was already partly printed: move it down whole
+            None if self.column > 0 => std::mem::take(&mut self.line),
+            None => self.line.split_off(room.max(1)),
+        };
+
+        self.newline(screen);
+        self.line = rest;
+    }
+}
+
+#[cfg(test)]
+mod output_tests;
@@ -0,0 +1,126 @@
+use super::*;
+use std::cell::RefCell;
+
+// Records what the frontend is handed, with newlines as '|'
+#[derive(Default)]
+struct LineScreen {
+    output: RefCell<String>,
+}
+
+impl ZScreen for LineScreen {
+    fn newline(&self) {
+        self.output.borrow_mut().push('|');
+    }
+    fn print(&self, str: String) {
+        self.output.borrow_mut().push_str(&format!("[{}]", str));
+    }
+    fn read(&self) -> char {
+        self.output.borrow_mut().push('*');
+        ' '
+    }
+    fn readline(&self) -> String { String::new() }
+    fn exit(&self) {}
+    fn set_status(&self, _status: String) {}
+    fn get_width(&self) {}
+    fn get_height(&self) {}
+    fn restart(&self) {}
+    fn save(&self, _state: Vec<u8>) {}
+    fn restore(&self) -> Vec<u8> { vec![] }
+    fn set_window(&self, _num: u16) {}
+    fn split_window(&self, _height: u16) {}
+    fn erase_window(&self, _num: u16) {}
+    fn move_cursor(&self, _x: u8, _y: u8) {}
+    fn print_number(&self, _num: u16) {}
+    fn print_char(&self, _char: char) {}
+}
+
+#[test]
+fn test_wraps_at_spaces() {
+    let screen = LineScreen::default();
+    let mut output = Output::new();
+    output.width = 10;
+    output.print(&screen, "The quick ");
+    output.print(&screen, "brown fox jumps");
+    assert_eq!(*screen.output.borrow(), "[The quick]|[brown fox]|");
+    output.flush(&screen);
+    assert_eq!(*screen.output.borrow(), "[The quick]|[brown fox]|[jumps]");
+}
+
+#[test]
+fn test_explicit_newlines() {
+    let screen = LineScreen::default();
+    let mut output = Output::new();
+    output.width = 20;
+    output.print(&screen, "one\ntwo");
+    output.newline(&screen);
+    assert_eq!(*screen.output.borrow(), "[one]|[two]|");
+}
+
+#[test]
+fn test_long_words_break() {
+    let screen = LineScreen::default();
+    let mut output = Output::new();
+    output.width = 4;
+    output.print(&screen, "abcdefghij");
+    output.flush(&screen);
+    assert_eq!(*screen.output.borrow(), "[abcd]|[efgh]|[ij]");
+}
+
+#[test]
+fn test_text_after_flushed_prompt() {
+    let screen = LineScreen::default();
+    let mut output = Output::new();
+    output.width = 10;
+    output.print(&screen, ">");
+    output.flush(&screen);
+    // the word no longer fits after the prompt, so it moves down whole
+    output.print(&screen, "abcdefghij");
+    output.flush(&screen);
+    assert_eq!(*screen.output.borrow(), "[>]|[abcdefghij]");
+}
+
+#[test]
+fn test_exact_fit() {
+    let screen = LineScreen::default();
+    let mut output = Output::new();
+    output.width = 5;
+    output.print(&screen, "abcde fgh");
+    output.flush(&screen);
+    assert_eq!(*screen.output.borrow(), "[abcde]|[fgh]");
+}
+
+#[test]
+fn test_more_prompt() {
+    let screen = LineScreen::default();
+    let mut output = Output::new();
+    output.page = 3;
+    output.more = Some("[MORE]".to_string());
+    output.print(&screen, "a\nb\nc\n");
+    assert_eq!(*screen.output.borrow(), "[a]|[b]|[[MORE]]*|[c]|");
+
+    // Input starts a new page
+    output.reset_pager();
+    output.print(&screen, "d\n");
+    assert_eq!(*screen.output.borrow(), "[a]|[b]|[[MORE]]*|[c]|[d]|");
+}
+
+#[test]
+fn test_no_more_prompt_without_paging() {
+    let screen = LineScreen::default();
+    let mut output = Output::new();
+    output.more = Some("[MORE]".to_string());
+    output.print(&screen, "a\nb\nc\n");
+    assert_eq!(*screen.output.borrow(), "[a]|[b]|[c]|");
+}
+
+#[test]
+fn test_unpaged_lines_dont_count() {
+    let screen = LineScreen::default();
+    let mut output = Output::new();
+    output.more = Some("[MORE]".to_string());
+    // the upper window is never paged
+    output.print(&screen, "1\n2\n3\n");
+    output.page = 3;
+    output.print(&screen, "a\n");
+    assert_eq!(*screen.output.borrow(), "[1]|[2]|[3]|[a]|");
+}
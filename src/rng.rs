@@ -0,0 +1,108 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+ * The random number generator behind the RANDOM opcode (see 2.4 in the standard).
+ * A positive range gives a value in 1..=range. random(0) reseeds unpredictably and
+ * random(-S) reseeds predictably: for small S (< 1000) the "random" numbers are simply
+ * the sequence 1, 2, ... S, 1, 2, ..., otherwise a generator seeded with S.
+ * Hosts can fix the seed so that a session (or a regression test) is reproducible;
+ * a fixed seed also replaces the entropy used by random(0).
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Mode {
+    Random,
+    Sequence { counter: u16, limit: u16 },
+}
+
+pub struct Rng {
+    state: u64,
+    mode: Mode,
+    fixed_seed: Option<u64>,
+}
+
+const SEQUENCE_LIMIT: u16 = 1000;
+
+impl Rng {
+    pub fn new() -> Rng {
+        let mut rng = Rng {
+            state: 0,
+            mode: Mode::Random,
+            fixed_seed: None,
+        };
+        rng.reseed();
+        rng
+    }
+
+    //seed used by the host for reproducible runs, this also takes effect immediately
+    pub fn fix_seed(&mut self, seed: Option<u64>) {
+        self.fixed_seed = seed;
+        self.reseed();
+    }
+
+    //random(0): back to unpredictable (or the host's fixed seed)
+    pub fn reseed(&mut self) {
+        let seed = self.fixed_seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0)
+        });
+        self.seed_state(seed);
+        self.mode = Mode::Random;
+    }
+
+    //random(-seed): predictable
+    pub fn seed(&mut self, seed: u16) {
+        if seed < SEQUENCE_LIMIT {
+            self.mode = Mode::Sequence {
+                counter: 0,
+                limit: seed.max(1),
+            };
+        } else {
+            self.seed_state(seed as u64);
+            self.mode = Mode::Random;
+        }
+    }
+
+    //a value in 1..=range
+    pub fn random(&mut self, range: u16) -> u16 {
+        if range == 0 {
+            return 0;
+        }
+        match self.mode {
+            Mode::Random => (self.next_u64() % range as u64) as u16 + 1,
+            Mode::Sequence { counter, limit } => {
+                self.mode = Mode::Sequence {
+                    counter: (counter + 1) % limit,
+                    limit,
+                };
+                counter % range + 1
+            }
+        }
+    }
+
+    fn seed_state(&mut self, seed: u64) {
+        //splitmix64 finaliser so that nearby seeds give unrelated sequences - xorshift needs a non-zero state
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        self.state = (z ^ (z >> 31)) | 1;
+    }
+
+    //xorshift64*
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 32
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new()
+    }
+}
+
+#[cfg(test)]
+mod rng_tests;
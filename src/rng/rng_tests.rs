@@ -0,0 +1,52 @@
+use super::*;
+
+#[test]
+fn test_random_in_range() {
+    let mut rng = Rng::new();
+    for _ in 0..1000 {
+        let val = rng.random(6);
+        assert!((1..=6).contains(&val));
+    }
+    assert_eq!(rng.random(0), 0);
+}
+
+#[test]
+fn test_predictable_seed() {
+    let mut a = Rng::new();
+    let mut b = Rng::new();
+    a.seed(12345);
+    b.seed(12345);
+    let seq_a: Vec<u16> = (0..20).map(|_| a.random(100)).collect();
+    let seq_b: Vec<u16> = (0..20).map(|_| b.random(100)).collect();
+    assert_eq!(seq_a, seq_b);
+}
+
+#[test]
+fn test_sequence_mode() {
+    let mut rng = Rng::new();
+    rng.seed(3);
+    let seq: Vec<u16> = (0..7).map(|_| rng.random(10)).collect();
+    assert_eq!(seq, vec![1, 2, 3, 1, 2, 3, 1]);
+
+    // Values are still kept within the requested range
+    rng.seed(5);
+    let seq: Vec<u16> = (0..5).map(|_| rng.random(2)).collect();
+    assert_eq!(seq, vec![1, 2, 1, 2, 1]);
+}
+
+#[test]
+fn test_fixed_seed() {
+    let mut a = Rng::new();
+    let mut b = Rng::new();
+    a.fix_seed(Some(42));
+    b.fix_seed(Some(42));
+    assert_eq!(a.random(1000), b.random(1000));
+
+    // random(0) goes back to the fixed seed rather than the clock
+    a.seed(7);
+    a.reseed();
+    b.reseed();
+    let seq_a: Vec<u16> = (0..10).map(|_| a.random(1000)).collect();
+    let seq_b: Vec<u16> = (0..10).map(|_| b.random(1000)).collect();
+    assert_eq!(seq_a, seq_b);
+}
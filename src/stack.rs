@@ -0,0 +1,222 @@
+use crate::error::Error;
+
+/*
+ * The call stack. Every routine's locals and evaluation stack live in one contiguous
+ * vector of words: a frame's locals start at its base, and its evaluation stack follows
+ * straight after them, running to the base of the next frame (or the end, for the
+ * running routine). Suspended callers are kept as frame records in a second vector, so
+ * calling and returning are a push and a pop. Both vectors are bounded by configurable
+ * limits, and between them hold everything a Quetzal `Stks` chunk needs.
+ */
+
+pub const DEFAULT_MAX_FRAMES: usize = 1024;
+pub const DEFAULT_MAX_VALUES: usize = 0xFFFF;
+
+/// The record of one routine call.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StackFrame {
+    pub pc: u32,                      //program counter; for a suspended caller, where it resumes
+    pub routine: u32,                 //address of the routine, for backtraces
+    pub return_store_var: Option<u8>, //where the result goes, None to discard it
+    pub base: usize,                  //index of local 1 in the stack's values
+    pub locals: u8,                   //number of locals, 0-15
+    pub args: u8,                     //number of arguments supplied
+}
+
+impl StackFrame {
+    //the frame the game starts in, which has no locals
+    pub fn main(pc: u32) -> StackFrame {
+        StackFrame {
+            pc,
+            routine: pc,
+            return_store_var: None,
+            base: 0,
+            locals: 0,
+            args: 0,
+        }
+    }
+
+    //index of the first evaluation stack word
+    fn eval_base(&self) -> usize {
+        self.base + self.locals as usize
+    }
+}
+
+pub struct Stack {
+    values: Vec<u16>,
+    frames: Vec<StackFrame>, //suspended callers, outermost first
+    pub max_frames: usize,
+    pub max_values: usize,
+}
+
+impl Default for Stack {
+    fn default() -> Stack {
+        Stack::new()
+    }
+}
+
+impl Stack {
+    pub fn new() -> Stack {
+        Stack {
+            values: Vec::with_capacity(1024),
+            frames: Vec::with_capacity(64),
+            max_frames: DEFAULT_MAX_FRAMES,
+            max_values: DEFAULT_MAX_VALUES,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.frames.clear();
+    }
+
+    //number of routine calls in progress below the current one
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn overflow() -> Error {
+        Error::StackOverflow {
+            context: Default::default(),
+        }
+    }
+
+    /// Suspends `current` and starts a frame for `routine`, with a local for each of the
+    /// `initial` values, the first of which are replaced by `args`.
+    pub fn call(
+        &mut self,
+        current: &mut StackFrame,
+        routine: u32,
+        pc: u32,
+        initial: &[u16],
+        args: &[u16],
+        return_store_var: Option<u8>,
+    ) -> Result<(), Error> {
+        let locals = initial.len().min(15);
+        if self.frames.len() >= self.max_frames || self.values.len() + locals > self.max_values {
+            return Err(Stack::overflow());
+        }
+
+        let base = self.values.len();
+        self.values.extend_from_slice(&initial[..locals]);
+        let supplied = args.len().min(locals);
+        self.values[base..base + supplied].copy_from_slice(&args[..supplied]);
+
+        let caller = std::mem::replace(
+            current,
+            StackFrame {
+                pc,
+                routine,
+                return_store_var,
+                base,
+                locals: locals as u8,
+                args: args.len().min(7) as u8,
+            },
+        );
+        self.frames.push(caller);
+        Ok(())
+    }
+
+    /// Ends `current`, dropping its locals and evaluation stack, and resumes its caller.
+    /// Returns the variable the result should be stored in.
+    pub fn ret(&mut self, current: &mut StackFrame) -> Result<Option<u8>, Error> {
+        let caller = self
+            .frames
+            .pop()
+            .ok_or_else(|| Error::illegal("Attempted to return from main routine"))?;
+        self.values.truncate(current.base);
+        let store_var = current.return_store_var;
+        *current = caller;
+        Ok(store_var)
+    }
+
+    pub fn push(&mut self, val: u16) -> Result<(), Error> {
+        if self.values.len() >= self.max_values {
+            return Err(Stack::overflow());
+        }
+        self.values.push(val);
+        Ok(())
+    }
+
+    //a routine can't pop values its caller pushed
+    pub fn pop(&mut self, frame: &StackFrame) -> Result<u16, Error> {
+        if self.values.len() <= frame.eval_base() {
+            return Err(Error::StackUnderflow {
+                context: Default::default(),
+            });
+        }
+        Ok(self.values.pop().unwrap_or(0))
+    }
+
+    //the top of the stack, without popping it
+    pub fn peek(&self, frame: &StackFrame) -> Result<u16, Error> {
+        if self.values.len() <= frame.eval_base() {
+            return Err(Error::StackUnderflow {
+                context: Default::default(),
+            });
+        }
+        Ok(self.values[self.values.len() - 1])
+    }
+
+    pub fn read_local(&self, frame: &StackFrame, i: u8) -> Result<u16, Error> {
+        Ok(self.values[Stack::local_index(frame, i)?])
+    }
+
+    pub fn write_local(&mut self, frame: &StackFrame, i: u8, val: u16) -> Result<(), Error> {
+        let index = Stack::local_index(frame, i)?;
+        self.values[index] = val;
+        Ok(())
+    }
+
+    fn local_index(frame: &StackFrame, i: u8) -> Result<usize, Error> {
+        if i == 0 || i > frame.locals {
+            Err(Error::BadVariable {
+                var: i,
+                context: Default::default(),
+            })
+        } else {
+            Ok(frame.base + i as usize - 1)
+        }
+    }
+
+    /// Routine addresses from `current` out to the main routine.
+    pub fn backtrace(&self, current: &StackFrame) -> Vec<u32> {
+        std::iter::once(current.routine)
+            .chain(self.frames.iter().rev().map(|f| f.routine))
+            .collect()
+    }
+
+    /// The stack as a Quetzal `Stks` chunk body, outermost frame first. `current` is the
+    /// running routine; its PC belongs in `IFhd`, not here.
+    pub fn quetzal_stks(&self, current: &StackFrame) -> Vec<u8> {
+        let mut bytes = vec![];
+        let frames: Vec<&StackFrame> = self.frames.iter().chain(std::iter::once(current)).collect();
+        for (i, frame) in frames.iter().enumerate() {
+            //the return PC is where the caller resumes, which the caller's record holds
+            let return_pc = if i == 0 { 0 } else { frames[i - 1].pc };
+            bytes.extend_from_slice(&return_pc.to_be_bytes()[1..]);
+
+            //the dummy frame under main has nothing to discard
+            let mut flags = frame.locals;
+            if i > 0 && frame.return_store_var.is_none() {
+                flags |= 0x10;
+            }
+            bytes.push(flags);
+            bytes.push(frame.return_store_var.unwrap_or(0));
+            bytes.push(((1u16 << frame.args) - 1) as u8);
+
+            let end = frames
+                .get(i + 1)
+                .map_or(self.values.len(), |next| next.base);
+            let eval = &self.values[frame.eval_base()..end];
+            bytes.extend_from_slice(&(eval.len() as u16).to_be_bytes());
+            for word in &self.values[frame.base..end] {
+                bytes.extend_from_slice(&word.to_be_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod stack_tests;
@@ -0,0 +1,119 @@
+use super::*;
+
+fn call(stack: &mut Stack, frame: &mut StackFrame, routine: u32, locals: usize, args: &[u16]) {
+    let initial = vec![0xAAAA; locals];
+    stack
+        .call(frame, routine, routine + 1, &initial, args, Some(0x10))
+        .unwrap();
+}
+
+#[test]
+fn test_exact_locals() {
+    let mut stack = Stack::new();
+    let mut frame = StackFrame::main(0x100);
+    call(&mut stack, &mut frame, 0x200, 3, &[1, 2]);
+
+    assert_eq!(stack.read_local(&frame, 1).unwrap(), 1);
+    assert_eq!(stack.read_local(&frame, 2).unwrap(), 2);
+    assert_eq!(stack.read_local(&frame, 3).unwrap(), 0xAAAA);
+    assert!(matches!(
+        stack.read_local(&frame, 4),
+        Err(Error::BadVariable { var: 4, .. })
+    ));
+    stack.write_local(&frame, 3, 7).unwrap();
+    assert_eq!(stack.read_local(&frame, 3).unwrap(), 7);
+    assert!(stack.write_local(&frame, 0, 7).is_err());
+}
+
+#[test]
+fn test_eval_stack_per_frame() {
+    let mut stack = Stack::new();
+    let mut frame = StackFrame::main(0x100);
+    stack.push(5).unwrap();
+    call(&mut stack, &mut frame, 0x200, 1, &[]);
+
+    // The caller's values can't be reached from the routine
+    assert!(matches!(stack.pop(&frame), Err(Error::StackUnderflow { .. })));
+    stack.push(9).unwrap();
+    assert_eq!(stack.peek(&frame).unwrap(), 9);
+    stack.push(10).unwrap();
+
+    // Returning drops the routine's locals and leftover values
+    assert_eq!(stack.ret(&mut frame).unwrap(), Some(0x10));
+    assert_eq!(frame.pc, 0x100);
+    assert_eq!(stack.pop(&frame).unwrap(), 5);
+    assert!(stack.pop(&frame).is_err());
+    assert!(stack.ret(&mut frame).is_err());
+}
+
+#[test]
+fn test_depth_limits() {
+    let mut stack = Stack::new();
+    stack.max_frames = 2;
+    let mut frame = StackFrame::main(0x100);
+    call(&mut stack, &mut frame, 0x200, 0, &[]);
+    call(&mut stack, &mut frame, 0x300, 0, &[]);
+    assert_eq!(stack.depth(), 2);
+    assert!(matches!(
+        stack.call(&mut frame, 0x400, 0x401, &[], &[], None),
+        Err(Error::StackOverflow { .. })
+    ));
+
+    let mut stack = Stack::new();
+    stack.max_values = 3;
+    let mut frame = StackFrame::main(0x100);
+    call(&mut stack, &mut frame, 0x200, 2, &[]);
+    stack.push(1).unwrap();
+    assert!(stack.push(2).is_err());
+    assert!(stack.call(&mut frame, 0x300, 0x301, &[0], &[], None).is_err());
+}
+
+#[test]
+fn test_deep_recursion() {
+    let mut stack = Stack::new();
+    stack.max_frames = 100_000;
+    stack.max_values = 1_000_000;
+    let mut frame = StackFrame::main(0x100);
+    for _ in 0..50_000 {
+        call(&mut stack, &mut frame, 0x200, 15, &[1, 2, 3]);
+    }
+    assert_eq!(stack.backtrace(&frame).len(), 50_001);
+    for _ in 0..50_000 {
+        stack.ret(&mut frame).unwrap();
+    }
+    assert_eq!(frame, StackFrame::main(0x100));
+}
+
+#[test]
+fn test_backtrace() {
+    let mut stack = Stack::new();
+    let mut frame = StackFrame::main(0x100);
+    call(&mut stack, &mut frame, 0x200, 0, &[]);
+    call(&mut stack, &mut frame, 0x300, 0, &[]);
+    assert_eq!(stack.backtrace(&frame), vec![0x300, 0x200, 0x100]);
+}
+
+#[test]
+fn test_quetzal_stks() {
+    let mut stack = Stack::new();
+    let mut frame = StackFrame::main(0x100);
+    stack.push(0x1111).unwrap();
+    frame.pc = 0x123;
+    stack
+        .call(&mut frame, 0x200, 0x205, &[0, 0], &[0x2222], Some(0x03))
+        .unwrap();
+    stack.push(0x3333).unwrap();
+    frame.pc = 0x20A;
+    stack.call(&mut frame, 0x300, 0x301, &[], &[], None).unwrap();
+
+    #[rustfmt::skip]
+    let expected = vec![
+        // dummy frame: one value on its evaluation stack
+        0, 0, 0, 0x00, 0, 0x00, 0, 1, 0x11, 0x11,
+        // routine 0x200: returns to 0x123, 2 locals, stores to L03, 1 arg, 1 value
+        0x00, 0x01, 0x23, 0x02, 0x03, 0x01, 0, 1, 0x22, 0x22, 0, 0, 0x33, 0x33,
+        // routine 0x300: returns to 0x20A, no locals, result discarded
+        0x00, 0x02, 0x0A, 0x10, 0x00, 0x00, 0, 0,
+    ];
+    assert_eq!(stack.quetzal_stks(&frame), expected);
+}
@@ -0,0 +1,214 @@
+use crate::error::Error;
+use crate::memory::Memory;
+
+/*
+ * The screen model of section 8. V6 has eight windows, each with a position, size,
+ * cursor, margins, font, style, colour and attributes, all readable and writable by
+ * the game as numbered properties (8.8.3.2.3). Earlier versions only use windows 0
+ * (lower) and 1 (upper), but keeping them in the same model means split_window and
+ * erase_window behave identically everywhere. Coordinates are 1-based screen units:
+ * pixels for V6 frontends, character cells for most others.
+ */
+
+pub const WINDOW_COUNT: usize = 8;
+
+//attribute bits, see window_style
+pub const ATTR_WRAPPING: u16 = 0x01;
+pub const ATTR_SCROLLING: u16 = 0x02;
+pub const ATTR_TRANSCRIPT: u16 = 0x04;
+pub const ATTR_BUFFERED: u16 = 0x08;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Window {
+    pub y: u16,
+    pub x: u16,
+    pub height: u16,
+    pub width: u16,
+    pub cursor_y: u16,
+    pub cursor_x: u16,
+    pub left_margin: u16,
+    pub right_margin: u16,
+    pub interrupt_routine: u16,
+    pub interrupt_countdown: u16,
+    pub text_style: u16,
+    pub colour: u16, //foreground + 256 * background
+    pub font: u16,
+    pub font_size: u16, //height * 256 + width
+    pub attributes: u16,
+    pub line_count: u16,
+    pub true_foreground: u16,
+    pub true_background: u16,
+}
+
+impl Window {
+    fn new(width: u16, height: u16, font_size: u16, attributes: u16) -> Window {
+        Window {
+            y: 1,
+            x: 1,
+            height,
+            width,
+            cursor_y: 1,
+            cursor_x: 1,
+            font: 1,
+            font_size,
+            attributes,
+            ..Default::default()
+        }
+    }
+
+    /// Reads property `prop` (0-17), as for `get_wind_prop`.
+    pub fn property(&self, prop: u16) -> Option<u16> {
+        Some(match prop {
+            0 => self.y,
+            1 => self.x,
+            2 => self.height,
+            3 => self.width,
+            4 => self.cursor_y,
+            5 => self.cursor_x,
+            6 => self.left_margin,
+            7 => self.right_margin,
+            8 => self.interrupt_routine,
+            9 => self.interrupt_countdown,
+            10 => self.text_style,
+            11 => self.colour,
+            12 => self.font,
+            13 => self.font_size,
+            14 => self.attributes,
+            15 => self.line_count,
+            16 => self.true_foreground,
+            17 => self.true_background,
+            _ => return None,
+        })
+    }
+
+    /// Writes property `prop` (0-17), as for `put_wind_prop`. Returns false for an unknown property.
+    pub fn set_property(&mut self, prop: u16, val: u16) -> bool {
+        let field = match prop {
+            0 => &mut self.y,
+            1 => &mut self.x,
+            2 => &mut self.height,
+            3 => &mut self.width,
+            4 => &mut self.cursor_y,
+            5 => &mut self.cursor_x,
+            6 => &mut self.left_margin,
+            7 => &mut self.right_margin,
+            8 => &mut self.interrupt_routine,
+            9 => &mut self.interrupt_countdown,
+            10 => &mut self.text_style,
+            11 => &mut self.colour,
+            12 => &mut self.font,
+            13 => &mut self.font_size,
+            14 => &mut self.attributes,
+            15 => &mut self.line_count,
+            16 => &mut self.true_foreground,
+            17 => &mut self.true_background,
+            _ => return false,
+        };
+        *field = val;
+        true
+    }
+
+    fn home_cursor(&mut self) {
+        self.cursor_y = 1;
+        self.cursor_x = self.left_margin + 1;
+    }
+}
+
+pub struct Windows {
+    windows: [Window; WINDOW_COUNT],
+    pub current: usize,
+    screen_width: u16,
+    screen_height: u16,
+}
+
+impl Windows {
+    //8.8.3.1: window 0 fills the screen, the others start with no size
+    pub fn new(mem: &Memory) -> Windows {
+        let (screen_width, screen_height) = if mem.zmachine_version() >= 5 {
+            (mem.screen_width_units(), mem.screen_height_units())
+        } else {
+            (mem.screen_width_chars() as u16, mem.screen_height_lines() as u16)
+        };
+        let font_size = (mem.font_height() as u16) << 8 | mem.font_width() as u16;
+
+        let mut windows = [Window::new(0, 0, font_size, 0); WINDOW_COUNT];
+        windows[0] = Window::new(
+            screen_width,
+            screen_height,
+            font_size,
+            ATTR_WRAPPING | ATTR_SCROLLING | ATTR_TRANSCRIPT | ATTR_BUFFERED,
+        );
+        windows[1].width = screen_width;
+        Windows {
+            windows,
+            current: 0,
+            screen_width,
+            screen_height,
+        }
+    }
+
+    //window operands can be -3 for the current window (V6)
+    pub fn resolve(&self, num: u16) -> Result<usize, Error> {
+        match num as i16 {
+            -3 => Ok(self.current),
+            n if (0..WINDOW_COUNT as i16).contains(&n) => Ok(n as usize),
+            _ => Err(Error::illegal(format!("Illegal window {}", num as i16))),
+        }
+    }
+
+    pub fn get(&self, num: usize) -> &Window {
+        &self.windows[num]
+    }
+
+    pub fn get_mut(&mut self, num: usize) -> &mut Window {
+        &mut self.windows[num]
+    }
+
+    pub fn current(&self) -> &Window {
+        &self.windows[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Window {
+        &mut self.windows[self.current]
+    }
+
+    //8.7.2.1 & 8.8.3.1: window 1 takes the top `height` units, window 0 the rest
+    pub fn split(&mut self, height: u16) {
+        let height = height.min(self.screen_height);
+        let upper = &mut self.windows[1];
+        upper.y = 1;
+        upper.x = 1;
+        upper.height = height;
+        upper.width = self.screen_width;
+        if upper.cursor_y > height {
+            upper.home_cursor();
+        }
+
+        let lower = &mut self.windows[0];
+        lower.y = height + 1;
+        lower.height = self.screen_height - height;
+        if lower.cursor_y > lower.height {
+            lower.home_cursor();
+        }
+    }
+
+    //erase_window -1 also unsplits the screen, -2 leaves the windows where they are
+    pub fn erase(&mut self, num: u16) -> Result<(), Error> {
+        match num as i16 {
+            -1 => {
+                self.split(0);
+                self.current = 0;
+                self.windows.iter_mut().for_each(Window::home_cursor);
+            }
+            -2 => self.windows.iter_mut().for_each(Window::home_cursor),
+            _ => {
+                let num = self.resolve(num)?;
+                self.windows[num].home_cursor();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod window_tests;
@@ -0,0 +1,63 @@
+use super::*;
+
+// A V6 screen of 640x400 units with 8x16 characters
+fn create_windows() -> Windows {
+    let mut data = vec![0u8; 256];
+    data[0x00] = 6;
+    data[0x22..0x24].copy_from_slice(&640u16.to_be_bytes());
+    data[0x24..0x26].copy_from_slice(&400u16.to_be_bytes());
+    data[0x26] = 16;
+    data[0x27] = 8;
+    Windows::new(&Memory::new(&data))
+}
+
+#[test]
+fn test_initial_windows() {
+    let windows = create_windows();
+    let lower = windows.get(0);
+    assert_eq!((lower.y, lower.x, lower.height, lower.width), (1, 1, 400, 640));
+    assert_eq!(lower.font_size, 16 << 8 | 8);
+    assert_eq!(lower.attributes, 0x0f);
+
+    let upper = windows.get(1);
+    assert_eq!((upper.height, upper.width), (0, 640));
+    assert_eq!(windows.get(7).height, 0);
+}
+
+#[test]
+fn test_split_and_unsplit() {
+    let mut windows = create_windows();
+    windows.get_mut(0).cursor_y = 390;
+    windows.split(32);
+    assert_eq!(windows.get(1).height, 32);
+    assert_eq!((windows.get(0).y, windows.get(0).height), (33, 368));
+    // the lower window's cursor was pushed off the bottom so goes back to the top
+    assert_eq!(windows.get(0).cursor_y, 1);
+
+    windows.current = 1;
+    windows.erase(0xffff).unwrap();
+    assert_eq!(windows.get(1).height, 0);
+    assert_eq!(windows.get(0).height, 400);
+    assert_eq!(windows.current, 0);
+}
+
+#[test]
+fn test_resolve_window() {
+    let mut windows = create_windows();
+    windows.current = 5;
+    assert_eq!(windows.resolve(0xfffd).unwrap(), 5);
+    assert_eq!(windows.resolve(7).unwrap(), 7);
+    assert!(windows.resolve(8).is_err());
+}
+
+#[test]
+fn test_properties() {
+    let mut window = create_windows().get(2).to_owned();
+    for prop in 0..18 {
+        assert!(window.set_property(prop, prop + 100));
+        assert_eq!(window.property(prop), Some(prop + 100));
+    }
+    assert_eq!(window.true_background, 117);
+    assert!(!window.set_property(18, 0));
+    assert_eq!(window.property(18), None);
+}
@@ -1,7 +1,17 @@
-use crate::memory::{Memory, StackFrame};
+use crate::babel::StoryInfo;
+use crate::blorb::Blorb;
+use crate::error::Context;
+use crate::graphics::{Framebuffer, Graphics};
+use crate::output::Output;
+use crate::memory::Memory;
+use crate::object::ObjectTree;
+use crate::stack::StackFrame;
 use crate::zscreen::ZScreen;
-use crate::instruction::Instruction;
+use crate::decode::{BranchTarget, Decoded, InstructionCache, OperandKind};
+use crate::instruction::{op, Op, OpTable, Operands};
 use crate::error::Error;
+use crate::rng::Rng;
+use crate::window::Windows;
 
 pub struct State<'a> {
     pub mem: Memory,
@@ -9,563 +19,854 @@ pub struct State<'a> {
     pub frame: StackFrame,
     pub running: bool,
     pub version: u8,
+    pub rng: Rng,
+    pub sound_routine: Option<(u16, u16)>, //sound number & routine to call when it finishes
+    pub blorb: Option<Blorb>,
+    pub windows: Windows,
+    pub graphics: Graphics,
+    pub mouse_window: u16, //clicks outside this window are ignored (V6); 0xFFFF for none
+    pub output: Output,
 }
 
 impl<'a> State<'a> {
-    pub fn new(story: &[u8], zscreen: Box<dyn ZScreen + 'a>) -> Self {
+    pub fn new(story: &[u8], zscreen: Box<dyn ZScreen + 'a>) -> Result<Self, Error> {
+        Memory::check_header(story)?;
         let mem = Memory::new(story);
-        let frame = StackFrame::main(&mem);
+        let frame = StackFrame::main(mem.initial_pc() as u32);
         let version = mem.zmachine_version();
-        State {
+        let windows = Windows::new(&mem);
+        Ok(State {
             mem,
             zscreen,
             frame,
             running: true,
             version,
-        }
-    }
-
-    pub fn next_u8(&mut self) -> u8 {
-        let val = self.mem.read_u8(self.frame.pc as u16); // TODO: PC is u32, handle high mem?
-        self.frame.pc = self.frame.pc.wrapping_add(1);
-        val
-    }
-
-    pub fn next_u16(&mut self) -> u16 {
-        let val = self.mem.read_u16(self.frame.pc as u16);
-        self.frame.pc = self.frame.pc.wrapping_add(2);
-        val
+            rng: Rng::new(),
+            sound_routine: None,
+            blorb: None,
+            windows,
+            graphics: Graphics::new(),
+            mouse_window: 1,
+            output: Output::new(),
+        })
     }
 
     pub fn read_variable(&mut self, id: u8) -> Result<u16, Error> {
-        self.mem.load(id, &mut self.frame)
+        self.mem.load(id, &self.frame)
     }
     
     pub fn store_variable(&mut self, id: u8, val: u16) -> Result<(), Error> {
-        self.mem.store(id, val, &mut self.frame)
-    }
-
-    pub fn branch(&mut self, condition: bool) {
-        let offset_byte_1 = self.next_u8();
-        
-        // Logic from ZMachine.java Branch(cond)
-        // int x = U8(frame.pc++);
-        // if((x & 0x80) == 0) cond = !cond;
-        let branch_on_true = (offset_byte_1 & 0x80) != 0;
-        
-        if branch_on_true == condition {
-            // Condition matched, take branch
-            let offset: i16;
-            if (offset_byte_1 & 0x40) != 0 {
-                // Short branch (6 bits)
-                offset = (offset_byte_1 & 0x3f) as i16;
-            } else {
-                 // Long branch (14 bits, signed)
-                 // x = x | 0xffffffc0; ?? Java logic is weird for sign extension
-                 // x = (x << 8) | U8(frame.pc++);
-                 
-                 let offset_byte_2 = self.next_u8();
-                 let val = ((offset_byte_1 & 0x3f) as u16) << 8 | (offset_byte_2 as u16);
-                 
-                 // Sign extend 14-bit value
-                 if (val & 0x2000) != 0 {
-                     offset = (val | 0xC000) as i16;
-                 } else {
-                     offset = val as i16;
-                 }
+        self.mem.store(id, val, &self.frame)
+    }
+
+    // Enter the routine at packed address `routine`. The result is stored in `store_var`
+    // on return, or discarded if there is none (eg interrupt routines)
+    pub fn call_routine(&mut self, routine: u16, args: &[u16], store_var: Option<u8>) -> Result<(), Error> {
+        let routine_addr = self.mem.unpack_routine(routine);
+        let num_locals = self.mem.fetch_u8(routine_addr)?.min(15);
+
+        // V1-4 routines give initial values for their locals, V5+ locals start at 0
+        let mut pc = routine_addr + 1;
+        let mut initial = [0u16; 15];
+        for local in initial.iter_mut().take(num_locals as usize) {
+            if self.version <= 4 {
+                *local = self.mem.fetch_u16(pc)?;
+                pc += 2;
             }
+        }
 
-            if offset == 0 {
-                // Return 0 (RFALSE)
-                // TODO: Implement Return logic or fake it
-                // self.return_val(0);
-                println!("BRANCH RETURN 0 (Not Implemented)");
-            } else if offset == 1 {
-                // Return 1 (RTRUE)
-                // self.return_val(1);
-                 println!("BRANCH RETURN 1 (Not Implemented)");
-            } else {
-                // Branch to offset
-                // frame.pc = frame.pc + x - 2;
-                // Note: The offset is calculated from the address of the branch instruction 
-                // but the PC has already advanced past the offset bytes.
-                // ZMachine.java says: frame.pc = frame.pc + x - 2;
-                // because it incremented PC while reading offset bytes.
-                // My next_u8 also increments PC.
-                // So this logic should hold.
-                 self.frame.pc = (self.frame.pc as i32 + offset as i32 - 2) as u32;
+        self.mem.stack.call(
+            &mut self.frame,
+            routine_addr,
+            pc,
+            &initial[..num_locals as usize],
+            args,
+            store_var,
+        )
+    }
+
+    // Leave the current routine, storing `value` in the caller if it asked for it
+    pub fn return_routine(&mut self, value: u16) -> Result<(), Error> {
+        match self.mem.stack.ret(&mut self.frame)? {
+            Some(var_id) => self.store_variable(var_id, value),
+            None => Ok(()),
+        }
+    }
+
+    // Call the end-of-sound routine as an interrupt once the frontend says the sound is done
+    pub fn poll_sound(&mut self) -> Result<(), Error> {
+        if let Some((number, routine)) = self.sound_routine {
+            if self.zscreen.sound_finished(number) {
+                self.sound_routine = None;
+                self.call_routine(routine, &[], None)?;
             }
+        }
+        Ok(())
+    }
+
+    // Word wrap applies to buffered text in the lower window, given a screen width. Fixed-pitch
+    // text, from text style 8 or Flags 2, is laid out by the game and is never reflowed
+    fn wrapping(&self) -> bool {
+        let fixed_pitch = self.windows.current().text_style & 8 != 0 || self.mem.forced_fixed_pitch();
+        self.output.wrap
+            && self.output.buffered
+            && self.windows.current == 0
+            && !fixed_pitch
+            && self.mem.screen_width_chars() > 0
+    }
+
+    // Lower window text goes through the output layer when it is wrapped or paged
+    fn layout_output(&mut self) -> bool {
+        let paging = self.output.more.is_some() && self.windows.current == 0;
+        self.output.width = if self.wrapping() {
+            self.mem.screen_width_chars() as usize
+        } else {
+            0
+        };
+        self.output.page = if paging && self.mem.screen_height_lines() != 255 {
+            self.lower_window_lines()
         } else {
-            // Condition failed, skip branch info
-             if (offset_byte_1 & 0x40) == 0 {
-                 // It was a long branch, skip the second byte
-                 self.next_u8();
-             }
+            0
+        };
+        self.output.width > 0 || self.output.page > 0
+    }
+
+    fn lower_window_lines(&self) -> usize {
+        let height = self.windows.get(0).height as usize;
+        if self.version >= 5 {
+            height / (self.mem.font_height() as usize).max(1)
+        } else {
+            height
+        }
+    }
+
+    // All game text goes through here on its way to the frontend
+    pub fn print(&mut self, text: &str) {
+        if self.layout_output() {
+            self.output.print(&*self.zscreen, text);
+        } else {
+            self.flush_output();
+            self.zscreen.print(text.to_string());
+        }
+    }
+
+    pub fn print_char(&mut self, ch: char) {
+        if self.layout_output() {
+            self.print(&ch.to_string());
+        } else {
+            self.flush_output();
+            self.zscreen.print_char(ch);
+        }
+    }
+
+    // Numbers are signed, and printed as text whichever way the text goes
+    pub fn print_number(&mut self, num: u16) {
+        self.print(&(num as i16).to_string());
+    }
+
+    pub fn newline(&mut self) {
+        self.layout_output();
+        self.output.newline(&*self.zscreen);
+    }
+
+    // Hands any partial line to the frontend: before input, and before the window changes
+    pub fn flush_output(&mut self) {
+        self.output.flush(&*self.zscreen);
+    }
+
+    // Whether a click at (y, x) falls inside the mouse window. Only V6 constrains clicks
+    pub fn in_mouse_window(&self, y: u16, x: u16) -> bool {
+        if self.version != 6 || self.mouse_window == 0xFFFF {
+            return true;
+        }
+        let w = self.windows.get(self.mouse_window as usize);
+        y >= w.y && y < w.y.saturating_add(w.height) && x >= w.x && x < w.x.saturating_add(w.width)
+    }
+
+    // Where the machine is, for an error in the instruction at `pc`
+    pub fn context(&self, pc: u32) -> Context {
+        Context {
+            pc,
+            opcode: self.mem.get(pc as usize).copied().unwrap_or(0),
+            backtrace: self.mem.stack.backtrace(&self.frame),
+        }
+    }
+
+    // Stores an instruction's result, if it has a store variable
+    pub fn store_result(&mut self, decoded: &Decoded, val: u16) -> Result<(), Error> {
+        match decoded.store {
+            Some(var_id) => self.store_variable(var_id, val),
+            None => Ok(()),
+        }
+    }
+
+    // Takes an instruction's branch if `condition` is the one it branches on
+    pub fn branch(&mut self, decoded: &Decoded, condition: bool) -> Result<(), Error> {
+        match decoded.branch {
+            Some(branch) if branch.on_true == condition => match branch.target {
+                BranchTarget::ReturnFalse => self.return_routine(0),
+                BranchTarget::ReturnTrue => self.return_routine(1),
+                BranchTarget::Address(addr) => {
+                    self.frame.pc = addr;
+                    Ok(())
+                }
+            },
+            _ => Ok(()),
         }
     }
 }
 
 pub struct ZMachine<'a> {
     pub state: State<'a>,
-    pub instructions: Vec<Box<dyn Instruction>>,
+    pub ops: OpTable,
+    cache: InstructionCache,
 }
 
-impl<'a> ZMachine<'a> {
-    pub fn new(story: &[u8], zscreen: Box<dyn ZScreen + 'a>) -> Self {
-        let mut instructions: Vec<Box<dyn Instruction>> = vec![];
-        for i in 0..=255 {
-            instructions.push(Box::new(crate::instruction::IllegalInstruction(i as u8)));
+// The instructions for each opcode in `version`
+pub(crate) fn op_table(version: u8) -> OpTable {
+    // Opcodes left as None are illegal
+    let mut instructions: [Option<Op>; 256] = [None; 256];
+    let mut ext_instructions: [Option<Op>; 256] = [None; 256];
+
+    // Register JE (Opcode 1)
+    // 2OP forms: 0x01, 0x21, 0x41, 0x61
+    // Variable form: 0xC1
+    instructions[0x01] = op!(Je);
+    instructions[0x21] = op!(Je);
+    instructions[0x41] = op!(Je);
+    instructions[0x61] = op!(Je);
+    instructions[0xC1] = op!(Je);
+
+    // Register JZ (Opcode 128 -> 0x80)
+    // 1OP forms: 0x80, 0x90, 0xA0
+    instructions[0x80] = op!(Jz);
+    instructions[0x90] = op!(Jz);
+    instructions[0xA0] = op!(Jz);
+
+    // Register JL (Opcode 2 -> 0x02)
+    // 2OP forms: 0x02, 0x22, 0x42, 0x62
+    // Variable form: 0xC2
+    instructions[0x02] = op!(Jl);
+    instructions[0x22] = op!(Jl);
+    instructions[0x42] = op!(Jl);
+    instructions[0x62] = op!(Jl);
+    instructions[0xC2] = op!(Jl);
+
+    // Register JG (Opcode 3 -> 0x03)
+    // 2OP forms: 0x03, 0x23, 0x43, 0x63
+    // Variable form: 0xC3
+    instructions[0x03] = op!(Jg);
+    instructions[0x23] = op!(Jg);
+    instructions[0x43] = op!(Jg);
+    instructions[0x63] = op!(Jg);
+    instructions[0xC3] = op!(Jg);
+
+    // Register JUMP (Opcode 140 -> 0x8C)
+    // 1OP forms: 0x8C, 0x9C, 0xAC
+    instructions[0x8C] = op!(Jump);
+    instructions[0x9C] = op!(Jump);
+    instructions[0xAC] = op!(Jump);
+
+    // Register JIN (Opcode 6 -> 0x06)
+    // 2OP forms: 0x06, 0x26, 0x46, 0x66
+    // Variable form: 0xC6
+    instructions[0x06] = op!(Jin);
+    instructions[0x26] = op!(Jin);
+    instructions[0x46] = op!(Jin);
+    instructions[0x66] = op!(Jin);
+    instructions[0xC6] = op!(Jin);
+
+    // Register TEST (Opcode 7 -> 0x07)
+    // 2OP forms: 0x07, 0x27, 0x47, 0x67
+    // Variable form: 0xC7
+    instructions[0x07] = op!(Test);
+    instructions[0x27] = op!(Test);
+    instructions[0x47] = op!(Test);
+    instructions[0x67] = op!(Test);
+    instructions[0xC7] = op!(Test);
+
+    // Register STORE (0x0D)
+    instructions[0x0D] = op!(Store);
+    instructions[0x2D] = op!(Store);
+    instructions[0x4D] = op!(Store);
+    instructions[0x6D] = op!(Store);
+    instructions[0xCD] = op!(Store);
+
+    // Register LOAD (0x8E)
+    instructions[0x8E] = op!(Load);
+    instructions[0x9E] = op!(Load);
+    instructions[0xAE] = op!(Load);
+
+    // Register STOREW (0xE1)
+    instructions[0xE1] = op!(StoreW);
+
+    // Register STOREB (0xE2)
+    instructions[0xE2] = op!(StoreB);
+
+    // Register LOADW (0x0F)
+    instructions[0x0F] = op!(LoadW);
+    instructions[0x2F] = op!(LoadW);
+    instructions[0x4F] = op!(LoadW);
+    instructions[0x6F] = op!(LoadW);
+    instructions[0xCF] = op!(LoadW);
+
+    // Register LOADB (0x10)
+    instructions[0x10] = op!(LoadB);
+    instructions[0x30] = op!(LoadB);
+    instructions[0x50] = op!(LoadB);
+    instructions[0x70] = op!(LoadB);
+    instructions[0xD0] = op!(LoadB);
+
+    // Register SET_ATTR (0x0B)
+    instructions[0x0B] = op!(SetAttr);
+    instructions[0x2B] = op!(SetAttr);
+    instructions[0x4B] = op!(SetAttr);
+    instructions[0x6B] = op!(SetAttr);
+    instructions[0xCB] = op!(SetAttr);
+
+    // Register CLEAR_ATTR (0x0C)
+    instructions[0x0C] = op!(ClearAttr);
+    instructions[0x2C] = op!(ClearAttr);
+    instructions[0x4C] = op!(ClearAttr);
+    instructions[0x6C] = op!(ClearAttr);
+    instructions[0xCC] = op!(ClearAttr);
+
+    // Register TEST_ATTR (0x0A)
+    instructions[0x0A] = op!(TestAttr);
+    instructions[0x2A] = op!(TestAttr);
+    instructions[0x4A] = op!(TestAttr);
+    instructions[0x6A] = op!(TestAttr);
+    instructions[0xCA] = op!(TestAttr);
+
+    // Register INSERT_OBJ (0x0E)
+    instructions[0x0E] = op!(InsertObj);
+    instructions[0x2E] = op!(InsertObj);
+    instructions[0x4E] = op!(InsertObj);
+    instructions[0x6E] = op!(InsertObj);
+    instructions[0xCE] = op!(InsertObj);
+
+    // Register REMOVE_OBJ (0x89)
+    instructions[0x89] = op!(RemoveObj);
+    instructions[0x99] = op!(RemoveObj);
+    instructions[0xA9] = op!(RemoveObj);
+
+    // Register GET_PARENT (0x83)
+    instructions[0x83] = op!(GetParent);
+    instructions[0x93] = op!(GetParent);
+    instructions[0xA3] = op!(GetParent);
+
+    // Register GET_CHILD (0x82)
+    instructions[0x82] = op!(GetChild);
+    instructions[0x92] = op!(GetChild);
+    instructions[0xA2] = op!(GetChild);
+
+    // Register GET_SIBLING (0x81)
+    instructions[0x81] = op!(GetSibling);
+    instructions[0x91] = op!(GetSibling);
+    instructions[0xA1] = op!(GetSibling);
+
+    // Register GET_PROP (0x11)
+    instructions[0x11] = op!(GetProp);
+    instructions[0x31] = op!(GetProp);
+    instructions[0x51] = op!(GetProp);
+    instructions[0x71] = op!(GetProp);
+    instructions[0xD1] = op!(GetProp);
+
+    // Register GET_PROP_ADDR (0x12)
+    instructions[0x12] = op!(GetPropAddr);
+    instructions[0x32] = op!(GetPropAddr);
+    instructions[0x52] = op!(GetPropAddr);
+    instructions[0x72] = op!(GetPropAddr);
+    instructions[0xD2] = op!(GetPropAddr);
+
+    // Register GET_PROP_LEN (0x84)
+    instructions[0x84] = op!(GetPropLen);
+    instructions[0x94] = op!(GetPropLen);
+    instructions[0xA4] = op!(GetPropLen);
+
+    // Register GET_NEXT_PROP (0x13)
+    instructions[0x13] = op!(GetNextProp);
+    instructions[0x33] = op!(GetNextProp);
+    instructions[0x53] = op!(GetNextProp);
+    instructions[0x73] = op!(GetNextProp);
+    instructions[0xD3] = op!(GetNextProp);
+
+    // Register PUT_PROP (0xE3)
+    instructions[0xE3] = op!(PutProp);
+
+    // Register CALL (VAR:E0 -> 0xE0, 1OP:88 -> 0x88, etc)
+    // CALL_VS (0xE0)
+    instructions[0xE0] = op!(Call);
+    // CALL_VS2 (0xEC), with up to 7 arguments
+    instructions[0xEC] = op!(Call);
+    // CALL_1S (0x88)
+    instructions[0x88] = op!(Call);
+    instructions[0x98] = op!(Call);
+    instructions[0xA8] = op!(Call);
+    // CALL_2S (0x19)
+    instructions[0x19] = op!(Call);
+    instructions[0x39] = op!(Call);
+    instructions[0x59] = op!(Call);
+    instructions[0x79] = op!(Call);
+    instructions[0xD9] = op!(Call);
+    // CALL_VN (0xF9), CALL_VN2 (0xFA) and CALL_2N (0x1A) discard the result
+    instructions[0xF9] = op!(CallN);
+    instructions[0xFA] = op!(CallN);
+    instructions[0x1A] = op!(CallN);
+    instructions[0x3A] = op!(CallN);
+    instructions[0x5A] = op!(CallN);
+    instructions[0x7A] = op!(CallN);
+    instructions[0xDA] = op!(CallN);
+    
+    // Register RET (0x8B)
+    instructions[0x8B] = op!(Ret);
+    instructions[0x9B] = op!(Ret);
+    instructions[0xAB] = op!(Ret);
+
+    // Register RTRUE (0xB0)
+    instructions[0xB0] = op!(RTrue);
+
+    // Register RFALSE (0xB1)
+    instructions[0xB1] = op!(RFalse);
+
+    // Register RET_POPPED (0xB8)
+    instructions[0xB8] = op!(RetPopped);
+
+    // Register RESTART (0xB7)
+    instructions[0xB7] = op!(Restart);
+
+    // Register QUIT (0xBA)
+    instructions[0xBA] = op!(Quit);
+
+    // Register PRINT (0xB2)
+    instructions[0xB2] = op!(Print);
+
+    // Register PRINT_RET (0xB3)
+    instructions[0xB3] = op!(PrintRet);
+
+    // Register PRINT_ADDR (0x87)
+    instructions[0x87] = op!(PrintAddr);
+    instructions[0x97] = op!(PrintAddr);
+    instructions[0xA7] = op!(PrintAddr);
+
+    // Register PRINT_PADDR (0x8D)
+    instructions[0x8D] = op!(PrintPAddr);
+    instructions[0x9D] = op!(PrintPAddr);
+    instructions[0xAD] = op!(PrintPAddr);
+
+    // Register PRINT_OBJ (0x8A)
+    instructions[0x8A] = op!(PrintObj);
+    instructions[0x9A] = op!(PrintObj);
+    instructions[0xAA] = op!(PrintObj);
+
+    // Register BUFFER_MODE (0xF2)
+    instructions[0xF2] = op!(BufferMode);
+
+    // Register PRINT_CHAR (0xE5)
+    instructions[0xE5] = op!(PrintChar);
+
+    // Register PRINT_NUM (0xE6)
+    instructions[0xE6] = op!(PrintNum);
+
+    // Register NEW_LINE (0xBB)
+    instructions[0xBB] = op!(NewLine);
+
+    // Register SREAD (0xE4)
+    instructions[0xE4] = op!(Sread);
+
+    // Register READ_CHAR (0xF6)
+    instructions[0xF6] = op!(ReadChar);
+
+    // Register PUSH (0xE8)
+    instructions[0xE8] = op!(Push);
+
+    // Register PULL (0xE9)
+    instructions[0xE9] = op!(Pull);
+
+    // Register INC (0x85)
+    instructions[0x85] = op!(Inc);
+    instructions[0x95] = op!(Inc);
+    instructions[0xA5] = op!(Inc);
+
+    // Register DEC (0x86)
+    instructions[0x86] = op!(Dec);
+    instructions[0x96] = op!(Dec);
+    instructions[0xA6] = op!(Dec);
+
+    // Register INC_CHK (0x05)
+    instructions[0x05] = op!(IncChk);
+    instructions[0x25] = op!(IncChk);
+    instructions[0x45] = op!(IncChk);
+    instructions[0x65] = op!(IncChk);
+    instructions[0xC5] = op!(IncChk);
+
+    // Register DEC_CHK (0x04)
+    instructions[0x04] = op!(DecChk);
+    instructions[0x24] = op!(DecChk);
+    instructions[0x44] = op!(DecChk);
+    instructions[0x64] = op!(DecChk);
+    instructions[0xC4] = op!(DecChk);
+
+    // Register SPLIT_WINDOW (0xEA)
+    instructions[0xEA] = op!(SplitWindow);
+
+    // Register SET_WINDOW (0xEB)
+    instructions[0xEB] = op!(SetWindow);
+
+    // Register ERASE_WINDOW (0xED)
+    instructions[0xED] = op!(EraseWindow);
+
+    // Register MOVE_CURSOR (0xEF)
+    instructions[0xEF] = op!(MoveCursor);
+
+    // Register SOUND_EFFECT (0xF5)
+    instructions[0xF5] = op!(SoundEffect);
+
+    // Register SET_COLOR (0x1B)
+    instructions[0x1B] = op!(SetColor);
+    instructions[0x3B] = op!(SetColor);
+    instructions[0x5B] = op!(SetColor);
+    instructions[0x7B] = op!(SetColor);
+    instructions[0xDB] = op!(SetColor);
+
+    // Register SET_TEXT_STYLE (0xF1)
+    instructions[0xF1] = op!(SetTextStyle);
+
+    // Register RANDOM (0xE7)
+    instructions[0xE7] = op!(Random);
+
+    // Register VERIFY (0xBD)
+    instructions[0xBD] = op!(Verify);
+
+    // Register ADD (0x14)
+    // 2OP forms: 0x14, 0x34, 0x54, 0x74
+    // Variable form: 0xD4
+    instructions[0x14] = op!(Add);
+    instructions[0x34] = op!(Add);
+    instructions[0x54] = op!(Add);
+    instructions[0x74] = op!(Add);
+    instructions[0xD4] = op!(Add);
+
+    // Register SUB (Opcode 21 -> 0x15)
+    instructions[0x15] = op!(Sub);
+    instructions[0x35] = op!(Sub);
+    instructions[0x55] = op!(Sub);
+    instructions[0x75] = op!(Sub);
+    instructions[0xD5] = op!(Sub);
+
+    // Register MUL (Opcode 22 -> 0x16)
+    instructions[0x16] = op!(Mul);
+    instructions[0x36] = op!(Mul);
+    instructions[0x56] = op!(Mul);
+    instructions[0x76] = op!(Mul);
+    instructions[0xD6] = op!(Mul);
+
+    // Register DIV (Opcode 23 -> 0x17)
+    instructions[0x17] = op!(Div);
+    instructions[0x37] = op!(Div);
+    instructions[0x57] = op!(Div);
+    instructions[0x77] = op!(Div);
+    instructions[0xD7] = op!(Div);
+
+    // Register MOD (Opcode 24 -> 0x18)
+    instructions[0x18] = op!(Mod);
+    instructions[0x38] = op!(Mod);
+    instructions[0x58] = op!(Mod);
+    instructions[0x78] = op!(Mod);
+    instructions[0xD8] = op!(Mod);
+
+    // Register OR (Opcode 8 -> 0x08)
+    instructions[0x08] = op!(Or);
+    instructions[0x28] = op!(Or);
+    instructions[0x48] = op!(Or);
+    instructions[0x68] = op!(Or);
+    instructions[0xC8] = op!(Or);
+
+    // Register AND (Opcode 9 -> 0x09)
+    instructions[0x09] = op!(And);
+    instructions[0x29] = op!(And);
+    instructions[0x49] = op!(And);
+    instructions[0x69] = op!(And);
+    instructions[0xC9] = op!(And);
+
+    // Opcodes which mean different things in different versions
+    if version <= 4 {
+        // SAVE (0xB5) and RESTORE (0xB6) branch in V1-3 and store in V4; V5+ use EXT
+        instructions[0xB5] = op!(Save);
+        instructions[0xB6] = op!(Restore);
+        // POP (0xB9)
+        instructions[0xB9] = op!(Pop);
+        // NOT (1OP: 0x8F, 0x9F, 0xAF)
+        instructions[0x8F] = op!(Not);
+        instructions[0x9F] = op!(Not);
+        instructions[0xAF] = op!(Not);
+    } else {
+        // CATCH (0xB9)
+        instructions[0xB9] = op!(Catch);
+        // CALL_1N (0x8F)
+        instructions[0x8F] = op!(CallN);
+        instructions[0x9F] = op!(CallN);
+        instructions[0xAF] = op!(CallN);
+        // NOT moved to VAR (0xF8)
+        instructions[0xF8] = op!(Not);
+    }
+    // SHOW_STATUS (0xBC); only V3 has it, but later games may still contain it, so it
+    // is a no-op there rather than an illegal instruction (see 15 @ show_status)
+    instructions[0xBC] = op!(ShowStatus);
+
+    // EXTENDED (0xBE xx) opcodes, indexed by the second byte
+
+    // Register SAVE (EXT:0x00)
+    ext_instructions[0x00] = op!(Save);
+
+    // Register RESTORE (EXT:0x01)
+    ext_instructions[0x01] = op!(Restore);
+
+    // Register SET_FONT (EXT:0x04)
+    ext_instructions[0x04] = op!(SetFont);
+
+    // Register DRAW_PICTURE (EXT:0x05)
+    ext_instructions[0x05] = op!(DrawPicture);
+
+    // Register PICTURE_DATA (EXT:0x06)
+    ext_instructions[0x06] = op!(PictureData);
+
+    // Register ERASE_PICTURE (EXT:0x07)
+    ext_instructions[0x07] = op!(ErasePicture);
+
+    // Register SET_MARGINS (EXT:0x08)
+    ext_instructions[0x08] = op!(SetMargins);
+
+    // Register MOVE_WINDOW (EXT:0x10)
+    ext_instructions[0x10] = op!(MoveWindow);
+
+    // Register WINDOW_SIZE (EXT:0x11)
+    ext_instructions[0x11] = op!(WindowSize);
+
+    // Register WINDOW_STYLE (EXT:0x12)
+    ext_instructions[0x12] = op!(WindowStyle);
+
+    // Register GET_WIND_PROP (EXT:0x13)
+    ext_instructions[0x13] = op!(GetWindProp);
+
+    // Register SCROLL_WINDOW (EXT:0x14)
+    ext_instructions[0x14] = op!(ScrollWindow);
+
+    // Register READ_MOUSE (EXT:0x16)
+    ext_instructions[0x16] = op!(ReadMouse);
+
+    // Register MOUSE_WINDOW (EXT:0x17)
+    ext_instructions[0x17] = op!(MouseWindow);
+
+    // Register PUT_WIND_PROP (EXT:0x19)
+    ext_instructions[0x19] = op!(PutWindProp);
+
+    // Register PICTURE_TABLE (EXT:0x1C)
+    ext_instructions[0x1C] = op!(PictureTable);
+
+    // Register PRINT_UNICODE (EXT:0x0B)
+    ext_instructions[0x0B] = op!(PrintUnicode);
+
+    // Register CHECK_UNICODE (EXT:0x0C)
+    ext_instructions[0x0C] = op!(CheckUnicode);
+
+    // Drop whatever the version doesn't have
+    for (opcode, entry) in instructions.iter_mut().enumerate() {
+        if !legal(opcode as u8, version) {
+            *entry = None;
         }
+    }
+    if version < 5 {
+        ext_instructions = [None; 256];
+    }
+
+    OpTable {
+        main: instructions,
+        ext: ext_instructions,
+    }
+}
+
+// Whether `opcode` exists in `version`, whatever it means there
+fn legal(opcode: u8, version: u8) -> bool {
+    match opcode {
+        // CALL_2S, CALL_1S, CALL_VS2, READ_CHAR, SET_CURSOR, BUFFER_MODE...
+        0x19 | 0x39 | 0x59 | 0x79 | 0xD9 => version >= 4,
+        0x88 | 0x98 | 0xA8 => version >= 4,
+        0xEC | 0xED | 0xEE | 0xEF | 0xF0 | 0xF1 | 0xF2 | 0xF6 | 0xF7 => version >= 4,
+        // SET_COLOUR, THROW, CALL_2N
+        0x1B..=0x1C | 0x3B..=0x3C | 0x5B..=0x5C | 0x7B..=0x7C | 0xDB..=0xDC => version >= 5,
+        0x1A | 0x3A | 0x5A | 0x7A | 0xDA => version >= 5,
+        // CALL_VN, CALL_VN2, TOKENISE, ENCODE_TEXT, COPY_TABLE, PRINT_TABLE, CHECK_ARG_COUNT
+        0xF9..=0xFF => version >= 5,
+        // EXT, PIRACY
+        0xBE | 0xBF => version >= 5,
+        // SPLIT_WINDOW, SET_WINDOW, OUTPUT_STREAM, INPUT_STREAM, SOUND_EFFECT
+        0xEA | 0xEB | 0xF3 | 0xF4 | 0xF5 => version >= 3,
+        // VERIFY
+        0xBD => version >= 3,
+        _ => true,
+    }
+}
+
+impl<'a> ZMachine<'a> {
+    /// Creates a machine for a story file. Fails if the file is too short to have a header.
+    pub fn new(story: &[u8], zscreen: Box<dyn ZScreen + 'a>) -> Result<Self, Error> {
+        let state = State::new(story, zscreen)?;
+        let ops = op_table(state.version);
+        let cache = InstructionCache::new(&state.mem);
+        Ok(ZMachine {
+            state,
+            ops,
+            cache,
+        })
+    }
+
+    /// Creates a machine from a Blorb file (`.zblorb`), loading the story from its
+    /// executable chunk and keeping its pictures and sounds available to the game.
+    pub fn from_blorb(data: &[u8], zscreen: Box<dyn ZScreen + 'a>) -> Result<Self, Error> {
+        let blorb = Blorb::parse(data)?;
+        let mut machine = ZMachine::new(blorb.story()?, zscreen)?;
+        machine.state.blorb = Some(blorb);
+        Ok(machine)
+    }
+
+    /// The resources of the Blorb file this machine was loaded from, if any.
+    pub fn blorb(&self) -> Option<&Blorb> {
+        self.state.blorb.as_ref()
+    }
+
+    /// Identification (IFID, release, serial) and any iFiction details of the loaded story.
+    pub fn story_info(&self) -> StoryInfo {
+        let ifiction = self.state.blorb.as_ref().and_then(|b| b.metadata.as_deref());
+        StoryInfo::from_memory(&self.state.mem, ifiction)
+    }
+
+    /// A read-only view of the game's objects as they are now: their names, attributes,
+    /// properties and places in the object tree.
+    pub fn objects(&self) -> ObjectTree<'_> {
+        ObjectTree::new(&self.state.mem)
+    }
 
-        // Register JE (Opcode 1)
-        // 2OP forms: 0x01, 0x21, 0x41, 0x61
-        // Variable form: 0xC1
-        instructions[0x01] = Box::new(crate::instruction::Je);
-        instructions[0x21] = Box::new(crate::instruction::Je);
-        instructions[0x41] = Box::new(crate::instruction::Je);
-        instructions[0x61] = Box::new(crate::instruction::Je);
-        instructions[0xC1] = Box::new(crate::instruction::Je);
-
-        // Register JZ (Opcode 128 -> 0x80)
-        // 1OP forms: 0x80, 0x90, 0xA0
-        instructions[0x80] = Box::new(crate::instruction::Jz);
-        instructions[0x90] = Box::new(crate::instruction::Jz);
-        instructions[0xA0] = Box::new(crate::instruction::Jz);
-
-        // Register JL (Opcode 2 -> 0x02)
-        // 2OP forms: 0x02, 0x22, 0x42, 0x62
-        // Variable form: 0xC2
-        instructions[0x02] = Box::new(crate::instruction::Jl);
-        instructions[0x22] = Box::new(crate::instruction::Jl);
-        instructions[0x42] = Box::new(crate::instruction::Jl);
-        instructions[0x62] = Box::new(crate::instruction::Jl);
-        instructions[0xC2] = Box::new(crate::instruction::Jl);
-
-        // Register JG (Opcode 3 -> 0x03)
-        // 2OP forms: 0x03, 0x23, 0x43, 0x63
-        // Variable form: 0xC3
-        instructions[0x03] = Box::new(crate::instruction::Jg);
-        instructions[0x23] = Box::new(crate::instruction::Jg);
-        instructions[0x43] = Box::new(crate::instruction::Jg);
-        instructions[0x63] = Box::new(crate::instruction::Jg);
-        instructions[0xC3] = Box::new(crate::instruction::Jg);
-
-        // Register JUMP (Opcode 140 -> 0x8C)
-        // 1OP forms: 0x8C, 0x9C, 0xAC
-        instructions[0x8C] = Box::new(crate::instruction::Jump);
-        instructions[0x9C] = Box::new(crate::instruction::Jump);
-        instructions[0xAC] = Box::new(crate::instruction::Jump);
-
-        // Register JIN (Opcode 6 -> 0x06)
-        // 2OP forms: 0x06, 0x26, 0x46, 0x66
-        // Variable form: 0xC6
-        instructions[0x06] = Box::new(crate::instruction::Jin);
-        instructions[0x26] = Box::new(crate::instruction::Jin);
-        instructions[0x46] = Box::new(crate::instruction::Jin);
-        instructions[0x66] = Box::new(crate::instruction::Jin);
-        instructions[0xC6] = Box::new(crate::instruction::Jin);
-
-        // Register TEST (Opcode 7 -> 0x07)
-        // 2OP forms: 0x07, 0x27, 0x47, 0x67
-        // Variable form: 0xC7
-        instructions[0x07] = Box::new(crate::instruction::Test);
-        instructions[0x27] = Box::new(crate::instruction::Test);
-        instructions[0x47] = Box::new(crate::instruction::Test);
-        instructions[0x67] = Box::new(crate::instruction::Test);
-        instructions[0xC7] = Box::new(crate::instruction::Test);
-
-        // Register STORE (0x0D)
-        instructions[0x0D] = Box::new(crate::instruction::Store);
-        instructions[0x2D] = Box::new(crate::instruction::Store);
-        instructions[0x4D] = Box::new(crate::instruction::Store);
-        instructions[0x6D] = Box::new(crate::instruction::Store);
-        instructions[0xCD] = Box::new(crate::instruction::Store);
-
-        // Register LOAD (0x8E)
-        instructions[0x8E] = Box::new(crate::instruction::Load);
-        instructions[0x9E] = Box::new(crate::instruction::Load);
-        instructions[0xAE] = Box::new(crate::instruction::Load);
-
-        // Register STOREW (0xE1)
-        instructions[0xE1] = Box::new(crate::instruction::StoreW);
-
-        // Register STOREB (0xE2)
-        instructions[0xE2] = Box::new(crate::instruction::StoreB);
-
-        // Register LOADW (0x0F)
-        instructions[0x0F] = Box::new(crate::instruction::LoadW);
-        instructions[0x2F] = Box::new(crate::instruction::LoadW);
-        instructions[0x4F] = Box::new(crate::instruction::LoadW);
-        instructions[0x6F] = Box::new(crate::instruction::LoadW);
-        instructions[0xCF] = Box::new(crate::instruction::LoadW);
-
-        // Register LOADB (0x10)
-        instructions[0x10] = Box::new(crate::instruction::LoadB);
-        instructions[0x30] = Box::new(crate::instruction::LoadB);
-        instructions[0x50] = Box::new(crate::instruction::LoadB);
-        instructions[0x70] = Box::new(crate::instruction::LoadB);
-        instructions[0xD0] = Box::new(crate::instruction::LoadB);
-
-        // Register SET_ATTR (0x0B)
-        instructions[0x0B] = Box::new(crate::instruction::SetAttr);
-        instructions[0x2B] = Box::new(crate::instruction::SetAttr);
-        instructions[0x4B] = Box::new(crate::instruction::SetAttr);
-        instructions[0x6B] = Box::new(crate::instruction::SetAttr);
-        instructions[0xCB] = Box::new(crate::instruction::SetAttr);
-
-        // Register CLEAR_ATTR (0x0C)
-        instructions[0x0C] = Box::new(crate::instruction::ClearAttr);
-        instructions[0x2C] = Box::new(crate::instruction::ClearAttr);
-        instructions[0x4C] = Box::new(crate::instruction::ClearAttr);
-        instructions[0x6C] = Box::new(crate::instruction::ClearAttr);
-        instructions[0xCC] = Box::new(crate::instruction::ClearAttr);
-
-        // Register TEST_ATTR (0x0A)
-        instructions[0x0A] = Box::new(crate::instruction::TestAttr);
-        instructions[0x2A] = Box::new(crate::instruction::TestAttr);
-        instructions[0x4A] = Box::new(crate::instruction::TestAttr);
-        instructions[0x6A] = Box::new(crate::instruction::TestAttr);
-        instructions[0xCA] = Box::new(crate::instruction::TestAttr);
-
-        // Register INSERT_OBJ (0x0E)
-        instructions[0x0E] = Box::new(crate::instruction::InsertObj);
-        instructions[0x2E] = Box::new(crate::instruction::InsertObj);
-        instructions[0x4E] = Box::new(crate::instruction::InsertObj);
-        instructions[0x6E] = Box::new(crate::instruction::InsertObj);
-        instructions[0xCE] = Box::new(crate::instruction::InsertObj);
-
-        // Register REMOVE_OBJ (0x89)
-        instructions[0x89] = Box::new(crate::instruction::RemoveObj);
-        instructions[0x99] = Box::new(crate::instruction::RemoveObj);
-        instructions[0xA9] = Box::new(crate::instruction::RemoveObj);
-
-        // Register GET_PARENT (0x83)
-        instructions[0x83] = Box::new(crate::instruction::GetParent);
-        instructions[0x93] = Box::new(crate::instruction::GetParent);
-        instructions[0xA3] = Box::new(crate::instruction::GetParent);
-
-        // Register GET_CHILD (0x82)
-        instructions[0x82] = Box::new(crate::instruction::GetChild);
-        instructions[0x92] = Box::new(crate::instruction::GetChild);
-        instructions[0xA2] = Box::new(crate::instruction::GetChild);
-
-        // Register GET_SIBLING (0x81)
-        instructions[0x81] = Box::new(crate::instruction::GetSibling);
-        instructions[0x91] = Box::new(crate::instruction::GetSibling);
-        instructions[0xA1] = Box::new(crate::instruction::GetSibling);
-
-        // Register GET_PROP (0x11)
-        instructions[0x11] = Box::new(crate::instruction::GetProp);
-        instructions[0x31] = Box::new(crate::instruction::GetProp);
-        instructions[0x51] = Box::new(crate::instruction::GetProp);
-        instructions[0x71] = Box::new(crate::instruction::GetProp);
-        instructions[0xD1] = Box::new(crate::instruction::GetProp);
-
-        // Register GET_PROP_ADDR (0x12)
-        instructions[0x12] = Box::new(crate::instruction::GetPropAddr);
-        instructions[0x32] = Box::new(crate::instruction::GetPropAddr);
-        instructions[0x52] = Box::new(crate::instruction::GetPropAddr);
-        instructions[0x72] = Box::new(crate::instruction::GetPropAddr);
-        instructions[0xD2] = Box::new(crate::instruction::GetPropAddr);
-
-        // Register GET_PROP_LEN (0x84)
-        instructions[0x84] = Box::new(crate::instruction::GetPropLen);
-        instructions[0x94] = Box::new(crate::instruction::GetPropLen);
-        instructions[0xA4] = Box::new(crate::instruction::GetPropLen);
-
-        // Register GET_NEXT_PROP (0x13)
-        instructions[0x13] = Box::new(crate::instruction::GetNextProp);
-        instructions[0x33] = Box::new(crate::instruction::GetNextProp);
-        instructions[0x53] = Box::new(crate::instruction::GetNextProp);
-        instructions[0x73] = Box::new(crate::instruction::GetNextProp);
-        instructions[0xD3] = Box::new(crate::instruction::GetNextProp);
-
-        // Register PUT_PROP (0xE3)
-        instructions[0xE3] = Box::new(crate::instruction::PutProp);
-
-        // Register CALL (VAR:E0 -> 0xE0, 1OP:88 -> 0x88, etc)
-        // CALL_VS (0xE0)
-        instructions[0xE0] = Box::new(crate::instruction::Call);
-        // CALL_1S (0x88)
-        instructions[0x88] = Box::new(crate::instruction::Call);
-        instructions[0x98] = Box::new(crate::instruction::Call);
-        instructions[0xA8] = Box::new(crate::instruction::Call);
-        // CALL_2S (0x19)
-        instructions[0x19] = Box::new(crate::instruction::Call);
-        instructions[0x39] = Box::new(crate::instruction::Call);
-        instructions[0x59] = Box::new(crate::instruction::Call);
-        instructions[0x79] = Box::new(crate::instruction::Call);
-        instructions[0xD9] = Box::new(crate::instruction::Call);
-        // CALL_VN (0xF9) - V5+ ? Logic might be different (no store?)
-        // Instructions like 2N, VN don't store.
-        // For now, mapping all CALLs to the same struct.
-        // We might need `CallN` for calls that don't store.
-        
-        // Register RET (0x8B)
-        instructions[0x8B] = Box::new(crate::instruction::Ret);
-        instructions[0x9B] = Box::new(crate::instruction::Ret);
-        instructions[0xAB] = Box::new(crate::instruction::Ret);
-
-        // Register RTRUE (0xB0)
-        instructions[0xB0] = Box::new(crate::instruction::RTrue);
-
-        // Register RFALSE (0xB1)
-        instructions[0xB1] = Box::new(crate::instruction::RFalse);
-
-        // Register RET_POPPED (0xB8)
-        instructions[0xB8] = Box::new(crate::instruction::RetPopped);
-
-        // Register RESTART (0xB7)
-        instructions[0xB7] = Box::new(crate::instruction::Restart);
-
-        // Register QUIT (0xBA)
-        instructions[0xBA] = Box::new(crate::instruction::Quit);
-
-        // Register PRINT (0xB2)
-        instructions[0xB2] = Box::new(crate::instruction::Print);
-
-        // Register PRINT_RET (0xB3)
-        instructions[0xB3] = Box::new(crate::instruction::PrintRet);
-
-        // Register PRINT_ADDR (0x87)
-        instructions[0x87] = Box::new(crate::instruction::PrintAddr);
-        instructions[0x97] = Box::new(crate::instruction::PrintAddr);
-        instructions[0xA7] = Box::new(crate::instruction::PrintAddr);
-
-        // Register PRINT_PADDR (0x8D)
-        instructions[0x8D] = Box::new(crate::instruction::PrintPAddr);
-        instructions[0x9D] = Box::new(crate::instruction::PrintPAddr);
-        instructions[0xAD] = Box::new(crate::instruction::PrintPAddr);
-
-        // Register PRINT_OBJ (0x8A)
-        instructions[0x8A] = Box::new(crate::instruction::PrintObj);
-        instructions[0x9A] = Box::new(crate::instruction::PrintObj);
-        instructions[0xAA] = Box::new(crate::instruction::PrintObj);
-
-        // Register PRINT_CHAR (0xE5)
-        instructions[0xE5] = Box::new(crate::instruction::PrintChar);
-
-        // Register PRINT_NUM (0xE6)
-        instructions[0xE6] = Box::new(crate::instruction::PrintNum);
-
-        // Register NEW_LINE (0xBB)
-        instructions[0xBB] = Box::new(crate::instruction::NewLine);
-
-        // Register SREAD (0xE4)
-        instructions[0xE4] = Box::new(crate::instruction::Sread);
-
-        // Register READ_CHAR (0xF6)
-        instructions[0xF6] = Box::new(crate::instruction::ReadChar);
-
-        // Register PUSH (0xE8)
-        instructions[0xE8] = Box::new(crate::instruction::Push);
-
-        // Register PULL (0xE9)
-        instructions[0xE9] = Box::new(crate::instruction::Pull);
-
-        // Register INC (0x85)
-        instructions[0x85] = Box::new(crate::instruction::Inc);
-        instructions[0x95] = Box::new(crate::instruction::Inc);
-        instructions[0xA5] = Box::new(crate::instruction::Inc);
-
-        // Register DEC (0x86)
-        instructions[0x86] = Box::new(crate::instruction::Dec);
-        instructions[0x96] = Box::new(crate::instruction::Dec);
-        instructions[0xA6] = Box::new(crate::instruction::Dec);
-
-        // Register INC_CHK (0x05)
-        instructions[0x05] = Box::new(crate::instruction::IncChk);
-        instructions[0x25] = Box::new(crate::instruction::IncChk);
-        instructions[0x45] = Box::new(crate::instruction::IncChk);
-        instructions[0x65] = Box::new(crate::instruction::IncChk);
-        instructions[0xC5] = Box::new(crate::instruction::IncChk);
-
-        // Register DEC_CHK (0x04)
-        instructions[0x04] = Box::new(crate::instruction::DecChk);
-        instructions[0x24] = Box::new(crate::instruction::DecChk);
-        instructions[0x44] = Box::new(crate::instruction::DecChk);
-        instructions[0x64] = Box::new(crate::instruction::DecChk);
-        instructions[0xC4] = Box::new(crate::instruction::DecChk);
-
-        // Register SPLIT_WINDOW (0xEA)
-        instructions[0xEA] = Box::new(crate::instruction::SplitWindow);
-
-        // Register SET_WINDOW (0xEB)
-        instructions[0xEB] = Box::new(crate::instruction::SetWindow);
-
-        // Register ERASE_WINDOW (0xED)
-        instructions[0xED] = Box::new(crate::instruction::EraseWindow);
-
-        // Register MOVE_CURSOR (0xEF)
-        instructions[0xEF] = Box::new(crate::instruction::MoveCursor);
-
-        // Register SET_COLOR (0x1B)
-        instructions[0x1B] = Box::new(crate::instruction::SetColor);
-        instructions[0x3B] = Box::new(crate::instruction::SetColor);
-        instructions[0x5B] = Box::new(crate::instruction::SetColor);
-        instructions[0x7B] = Box::new(crate::instruction::SetColor);
-        instructions[0xDB] = Box::new(crate::instruction::SetColor);
-
-        // Register RANDOM (0xE7)
-        instructions[0xE7] = Box::new(crate::instruction::Random);
-
-        // Register SAVE (0xB5)
-        instructions[0xB5] = Box::new(crate::instruction::Save);
-
-        // Register RESTORE (0xB6)
-        instructions[0xB6] = Box::new(crate::instruction::Restore);
-
-        // Register VERIFY (0xBD)
-        instructions[0xBD] = Box::new(crate::instruction::Verify);
-
-        // Register SHOW_STATUS (0xBC)
-        instructions[0xBC] = Box::new(crate::instruction::ShowStatus);
-
-        // Register ADD (0x14)
-        // 2OP forms: 0x14, 0x34, 0x54, 0x74
-        // Variable form: 0xD4
-        instructions[0x14] = Box::new(crate::instruction::Add);
-        instructions[0x34] = Box::new(crate::instruction::Add);
-        instructions[0x54] = Box::new(crate::instruction::Add);
-        instructions[0x74] = Box::new(crate::instruction::Add);
-        instructions[0xD4] = Box::new(crate::instruction::Add);
-
-        // Register SUB (Opcode 21 -> 0x15)
-        instructions[0x15] = Box::new(crate::instruction::Sub);
-        instructions[0x35] = Box::new(crate::instruction::Sub);
-        instructions[0x55] = Box::new(crate::instruction::Sub);
-        instructions[0x75] = Box::new(crate::instruction::Sub);
-        instructions[0xD5] = Box::new(crate::instruction::Sub);
-
-        // Register MUL (Opcode 22 -> 0x16)
-        instructions[0x16] = Box::new(crate::instruction::Mul);
-        instructions[0x36] = Box::new(crate::instruction::Mul);
-        instructions[0x56] = Box::new(crate::instruction::Mul);
-        instructions[0x76] = Box::new(crate::instruction::Mul);
-        instructions[0xD6] = Box::new(crate::instruction::Mul);
-
-        // Register DIV (Opcode 23 -> 0x17)
-        instructions[0x17] = Box::new(crate::instruction::Div);
-        instructions[0x37] = Box::new(crate::instruction::Div);
-        instructions[0x57] = Box::new(crate::instruction::Div);
-        instructions[0x77] = Box::new(crate::instruction::Div);
-        instructions[0xD7] = Box::new(crate::instruction::Div);
-
-        // Register MOD (Opcode 24 -> 0x18)
-        instructions[0x18] = Box::new(crate::instruction::Mod);
-        instructions[0x38] = Box::new(crate::instruction::Mod);
-        instructions[0x58] = Box::new(crate::instruction::Mod);
-        instructions[0x78] = Box::new(crate::instruction::Mod);
-        instructions[0xD8] = Box::new(crate::instruction::Mod);
-
-        // Register OR (Opcode 8 -> 0x08)
-        instructions[0x08] = Box::new(crate::instruction::Or);
-        instructions[0x28] = Box::new(crate::instruction::Or);
-        instructions[0x48] = Box::new(crate::instruction::Or);
-        instructions[0x68] = Box::new(crate::instruction::Or);
-        instructions[0xC8] = Box::new(crate::instruction::Or);
-
-        // Register AND (Opcode 9 -> 0x09)
-        instructions[0x09] = Box::new(crate::instruction::And);
-        instructions[0x29] = Box::new(crate::instruction::And);
-        instructions[0x49] = Box::new(crate::instruction::And);
-        instructions[0x69] = Box::new(crate::instruction::And);
-        instructions[0xC9] = Box::new(crate::instruction::And);
-
-        // Register NOT (Opcode 143/248 -> 0x8F, 0xF8)
-        // 1OP forms: 0x8F, 0x9F, 0xAF
-        // VAR form: 0xF8 (V5/6)
-        instructions[0x8F] = Box::new(crate::instruction::Not);
-        instructions[0x9F] = Box::new(crate::instruction::Not);
-        instructions[0xAF] = Box::new(crate::instruction::Not);
-        instructions[0xF8] = Box::new(crate::instruction::Not);
-        
-        ZMachine {
-            state: State::new(story, zscreen),
-            instructions,
+    /// Tells the game the screen size in units (pixels, or character cells for text
+    /// frontends) and the size of a character in the same units. Resets the windows.
+    pub fn set_screen_size(&mut self, width: u16, height: u16, font_width: u8, font_height: u8) {
+        let mem = &mut self.state.mem;
+        mem.write_u8(0x20, (height / font_height.max(1) as u16).min(255) as u8);
+        mem.write_u8(0x21, (width / font_width.max(1) as u16).min(255) as u8);
+        if self.state.version >= 5 {
+            mem.write_u16(0x22, width);
+            mem.write_u16(0x24, height);
+            let (first, second) = if self.state.version == 6 {
+                (font_height, font_width)
+            } else {
+                (font_width, font_height)
+            };
+            mem.write_u8(0x26, first);
+            mem.write_u8(0x27, second);
         }
+        self.state.windows = Windows::new(&self.state.mem);
+    }
+
+    /// The picture framebuffer of window `num` (V6), for frontends without their own
+    /// renderer and for checking frames in tests. It is made when a picture is first drawn,
+    /// cut to the screen size in the header, and empty again after the window is erased.
+    pub fn framebuffer(&self, num: u16) -> Option<&Framebuffer> {
+        self.state.graphics.framebuffer(num as usize)
+    }
+
+    /// Lets illegal memory accesses by the game through instead of stopping with an error:
+    /// writes outside dynamic memory go ahead, reads past the end of the story give 0, and
+    /// object opcodes given a bad object, such as 0, do nothing.
+    /// For old or broken story files which rely on this.
+    pub fn set_permissive_memory(&mut self, permissive: bool) {
+        self.state.mem.permissive = permissive;
+    }
+
+    /// Limits the call stack to `frames` nested routine calls and `values` words of locals
+    /// and evaluation stack between them. Going past either stops the game with a stack
+    /// overflow error. The defaults are 1024 calls and 65535 words.
+    pub fn set_stack_limits(&mut self, frames: usize, values: usize) {
+        self.state.mem.stack.max_frames = frames;
+        self.state.mem.stack.max_values = values;
+    }
+
+    /// Fixes the seed of the random number generator so that runs are reproducible.
+    /// `None` goes back to seeding from the clock.
+    pub fn set_random_seed(&mut self, seed: Option<u64>) {
+        self.state.rng.fix_seed(seed);
+    }
+
+    /// Turns on word wrap in the core: lower window text is broken into lines at the
+    /// screen width in the header, and `ZScreen::print` receives whole lines, each
+    /// followed by `ZScreen::newline`. Widths are counted in characters. Fixed-pitch text,
+    /// from text style 8 or bit 1 of Flags 2, isn't wrapped. Off by default.
+    pub fn set_word_wrap(&mut self, wrap: bool) {
+        self.state.flush_output();
+        self.state.output.wrap = wrap;
+    }
+
+    /// Turns on paging: once a screenful of lower window text has been output since the
+    /// last input, `prompt` is printed and output waits for a key from `ZScreen::read`.
+    /// `None` turns paging off, which is the default.
+    pub fn set_more_prompt(&mut self, prompt: Option<&str>) {
+        self.state.output.more = prompt.map(str::to_string);
     }
 
     pub fn run(&mut self) -> Result<(), Error> {
+        if !(1..=8).contains(&self.state.version) {
+            return Err(Error::UnsupportedVersion {
+                version: self.state.version,
+                context: Context::default(),
+            });
+        }
         while self.state.running {
+            self.state.poll_sound()?;
             self.execute_instruction()?;
         }
+        self.state.flush_output();
         Ok(())
     }
 
+    // Runs one instruction, attaching where it was to any error
     fn execute_instruction(&mut self) -> Result<(), Error> {
-        let op = self.state.next_u8();
-        let mut operands: Vec<u16> = Vec::new();
-        
-        let op_high = op >> 4;
-        
-        // DECODE OPERANDS
-        // Based on ZMachine.java logic
-        match op_high {
-            0x00 | 0x01 => { // 2OP, sconst, sconst
-                 operands.push(self.state.next_u8() as u16);
-                 operands.push(self.state.next_u8() as u16);
-            }
-            0x02 | 0x03 => { // 2OP, sconst, var
-                 operands.push(self.state.next_u8() as u16);
-                 let var_id = self.state.next_u8();
-                 operands.push(self.state.read_variable(var_id)?);
-            }
-            0x04 | 0x05 => { // 2OP, var, sconst
-                 let var_id = self.state.next_u8();
-                 operands.push(self.state.read_variable(var_id)?);
-                 operands.push(self.state.next_u8() as u16);
-            }
-             0x06 | 0x07 => { // 2OP, var, var
-                 let var_id1 = self.state.next_u8();
-                 operands.push(self.state.read_variable(var_id1)?);
-                 let var_id2 = self.state.next_u8();
-                 operands.push(self.state.read_variable(var_id2)?);
-            }
-            0x08 => { // 1OP, lconst (word)
-                 operands.push(self.state.next_u16());
-            }
-            0x09 => { // 1OP, sconst
-                 operands.push(self.state.next_u8() as u16);
-            }
-            0x0A => { // 1OP, var
-                 let var_id = self.state.next_u8();
-                 operands.push(self.state.read_variable(var_id)?);
-            }
-            0x0B => { // 0OP or Extended
-                 if op == 0xBE {
-                      return Err(Error::ZMachineError("Extended opcode 0xBE not supported yet".to_string()));
-                 }
-                 // 0OP has no operands.
-            }
-            0x0C | 0x0D | 0x0E | 0x0F => { // VAR (Variable operands)
-                 let types_byte = self.state.next_u8();
-                 for i in 0..4 {
-                     let shift = 6 - (i * 2);
-                     let type_bits = (types_byte >> shift) & 0x03;
-                     
-                     match type_bits {
-                         0 => { // lconst
-                              operands.push(self.state.next_u16());
-                         }
-                         1 => { // sconst
-                              operands.push(self.state.next_u8() as u16);
-                         }
-                         2 => { // var
-                              let var_id = self.state.next_u8();
-                              operands.push(self.state.read_variable(var_id)?);
-                         }
-                         3 => { // none
-                              break;
-                         }
-                         _ => unreachable!(),
-                     }
-                 }
-            }
-            _ => unreachable!(),
+        let pc = self.state.frame.pc;
+        self.decode_and_execute().map_err(|e| e.with_context(self.state.context(pc)))
+    }
+
+    fn decode_and_execute(&mut self) -> Result<(), Error> {
+        let state = &mut self.state;
+        let decoded = self.cache.get(&state.mem, &self.ops, state.frame.pc, state.version);
+
+        // Variable operands are read now, in order, as they may pop the stack
+        let mut operands = Operands::default();
+        for (kind, value) in decoded.operands() {
+            operands.push(match kind {
+                OperandKind::Variable => state.read_variable(value as u8)?,
+                _ => value,
+            });
         }
+        state.frame.pc = decoded.next_pc;
+
+        let op = self.ops.get(decoded.opcode, decoded.ext);
+        dispatch(op, decoded.ext.unwrap_or(decoded.opcode), state, &operands, decoded)
+    }
+}
 
-        let instruction = &self.instructions[op as usize];
-        instruction.execute(&mut self.state, operands)
+// Runs the instruction registered for `opcode`, if there is one
+fn dispatch(op: Option<Op>, opcode: u8, state: &mut State, operands: &[u16], decoded: &Decoded) -> Result<(), Error> {
+    match op {
+        Some(op) if !op.instruction.operand_count(state.version).contains(&operands.len()) => {
+            Err(Error::illegal(format!(
+                "{} given {} operands",
+                op.instruction.name(),
+                operands.len()
+            )))
+        }
+        Some(op) => (op.execute)(state, operands, decoded),
+        None => Err(Error::IllegalOpcode {
+            opcode,
+            version: state.version,
+            context: Context::default(),
+        }),
     }
-}
\ No newline at end of file
+}
@@ -1,74 +1,197 @@
+use crate::error::Error;
 use crate::memory::Memory;
 
 // See 3.5.3 @ https://www.inform-fiction.org/zmachine/standards/z1point1/sect03.html
-const ZSCII_MAP234: [[char; 32]; 3] = [
-    [
-        //A0
-        ' ', '\0', '\0', '\0', '\0', '\0', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k',
-        'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
-    ],
-    [
-        //A1
-        '\0', '\0', '\0', '\0', '\0', '\0', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K',
-        'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
-    ],
-    [
-        //A2
-        '\0', '\0', '\0', '\0', '\0', '\0', ' ', '\n', '0', '1', '2', '3', '4', '5', '6', '7', '8',
-        '9', '.', ',', '!', '?', '_', '#', '\'', '"', '/', '\\', '-', ':', '(', ')',
-    ],
+// Rows hold the ZSCII codes for Z-chars 6-31. In A2, Z-char 6 is the 10-bit escape and
+// Z-char 7 is newline (ZSCII 13) whatever the table says, except in V1 where it is '0'.
+const ALPHABET_V234: [[u8; 26]; 3] = [
+    *b"abcdefghijklmnopqrstuvwxyz",
+    *b"ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+    *b" \r0123456789.,!?_#'\"/\\-:()",
 ];
 
-const ZSCII_MAP1: [[char; 32]; 3] = [
-    [
-        //A0
-        ' ', '\0', '\0', '\0', '\0', '\0', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k',
-        'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
-    ],
-    [
-        //A1
-        '\0', '\0', '\0', '\0', '\0', '\0', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K',
-        'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
-    ],
-    [
-        //A2
-        '\0', '\0', '\0', '\0', '\0', '\0', ' ', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
-        '.', ',', '!', '?', '_', '#', '\'', '"', '/', '\\', '<', '-', ':', '(', ')',
-    ],
+const ALPHABET_V1: [[u8; 26]; 3] = [
+    *b"abcdefghijklmnopqrstuvwxyz",
+    *b"ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+    *b" 0123456789.,!?_#'\"/\\<-:()",
 ];
 
+/// The alphabet table in use by a story: either one of the built-in tables or,
+/// for V5+ stories with a non-zero header word 0x34, the 78 byte custom table.
+/// A custom table running past the end of the story is ignored.
+#[derive(Copy, Clone)]
+pub struct Alphabet {
+    rows: [[u8; 26]; 3],
+    newline: bool, //A2 Z-char 7 is forced to newline
+}
+
+impl Alphabet {
+    pub fn new(mem: &Memory) -> Alphabet {
+        let version = mem.zmachine_version();
+        if version == 1 {
+            return Alphabet {
+                rows: ALPHABET_V1,
+                newline: false,
+            };
+        }
+
+        let mut rows = ALPHABET_V234;
+        let table = mem.alphabet_table() as usize;
+        //a table which doesn't fit in the story is ignored, leaving the default alphabets
+        if let Some(custom) = mem.get(table..table + 78).filter(|_| version >= 5 && table != 0) {
+            for (row, codes) in rows.iter_mut().zip(custom.chunks_exact(26)) {
+                row.copy_from_slice(codes);
+            }
+        }
+        Alphabet {
+            rows,
+            newline: true,
+        }
+    }
+
+    //ZSCII code for Z-char `ch` (6-31) in alphabet row `row`
+    pub fn lookup(&self, row: usize, ch: u8) -> u8 {
+        if row == 2 && ch == 7 && self.newline {
+            13
+        } else {
+            self.rows[row][ch as usize - 6]
+        }
+    }
+
+    //reverse lookup for the encoder: the alphabet row & Z-char which produce `zscii`, if any
+    pub fn find(&self, zscii: u8) -> Option<(usize, u8)> {
+        for (row, codes) in self.rows.iter().enumerate() {
+            for (i, &code) in codes.iter().enumerate() {
+                let ch = i as u8 + 6;
+                if row == 2 && (ch == 6 || (ch == 7 && self.newline)) {
+                    continue;
+                }
+                if code == zscii {
+                    return Some((row, ch));
+                }
+            }
+        }
+        if self.newline && zscii == 13 {
+            return Some((2, 7));
+        }
+        None
+    }
+}
+
+// Default Unicode translations for ZSCII 155-223, see 3.8.5.3 & table 1
+const DEFAULT_UNICODE: [char; 69] = [
+    'ä', 'ö', 'ü', 'Ä', 'Ö', 'Ü', 'ß', '»', '«', 'ë', 'ï', 'ÿ', 'Ë', 'Ï', 'á', 'é', 'í', 'ó',
+    'ú', 'ý', 'Á', 'É', 'Í', 'Ó', 'Ú', 'Ý', 'à', 'è', 'ì', 'ò', 'ù', 'À', 'È', 'Ì', 'Ò', 'Ù',
+    'â', 'ê', 'î', 'ô', 'û', 'Â', 'Ê', 'Î', 'Ô', 'Û', 'å', 'Å', 'ø', 'Ø', 'ã', 'ñ', 'õ', 'Ã',
+    'Ñ', 'Õ', 'æ', 'Æ', 'ç', 'Ç', 'þ', 'ð', 'Þ', 'Ð', '£', 'œ', 'Œ', '¡', '¿',
+];
+
+//The extra characters (ZSCII 155 upwards): the story's Unicode translation table
+//from the header extension if it has one, otherwise the default table
+fn extra_char(mem: &Memory, index: u16) -> Option<char> {
+    let table = mem.unicode_table();
+    if table == 0 {
+        DEFAULT_UNICODE.get(index as usize).copied()
+    } else if index < extra_count(mem) {
+        let code = mem.fetch_u16(table as u32 + 1 + index as u32 * 2).ok()?;
+        char::from_u32(code as u32)
+    } else {
+        None
+    }
+}
+
+fn extra_count(mem: &Memory) -> u16 {
+    match mem.unicode_table() {
+        0 => DEFAULT_UNICODE.len() as u16,
+        table => mem.fetch_u8(table as u32).unwrap_or(0) as u16,
+    }
+}
+
+/// Converts a ZSCII output code to Unicode. Returns None for codes which
+/// print nothing (null, input-only and undefined codes).
+pub fn zscii_to_unicode(mem: &Memory, zscii: u16) -> Option<char> {
+    match zscii {
+        9 if mem.zmachine_version() == 6 => Some('\t'),
+        11 if mem.zmachine_version() == 6 => Some(' '), //sentence space
+        13 => Some('\n'),
+        32..=126 => char::from_u32(zscii as u32),
+        155..=251 => extra_char(mem, zscii - 155),
+        _ => None,
+    }
+}
+
+/// Converts a Unicode character to the ZSCII code which represents it, if any.
+pub fn unicode_to_zscii(mem: &Memory, ch: char) -> Option<u16> {
+    match ch {
+        '\n' | '\r' => Some(13),
+        ' '..='~' => Some(ch as u16),
+        _ => (0..extra_count(mem))
+            .find(|&i| extra_char(mem, i) == Some(ch))
+            .map(|i| i + 155),
+    }
+}
+
+//font 3 (character graphics) glyphs 32-126 as Unicode. Beyond Zork's runes, 97-122,
+//come out as the letters they stand for
+const FONT3: [char; 95] = [
+    ' ', '←', '→', '╱', '╲', ' ', '─', '─', '│', '│', '┴', '┬', '├', '┤', '└', '┌',
+    '┐', '┘', '└', '┌', '┐', '┘', '█', '▀', '▄', '▌', '▐', '▄', '▀', '▌', '▐', '▜',
+    '▛', '▙', '▟', '▖', '▗', '▘', '▝', '▔', '▁', '▏', '▕', '·', '·', '·', '·', '▒',
+    '▒', '▒', '▒', '▒', '▒', '▒', '▒', '▒', '▒', '▒', '▒', '▒', '▒', '▒', '▒', '▒',
+    '?', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '↑', '↓', '↕', '?',
+];
+
+/// Maps a font 3 (character graphics) ZSCII code to the nearest Unicode character, for
+/// frontends which draw the graphics font as text. Codes outside 32-126 give None.
+pub fn font3_to_unicode(zscii: u16) -> Option<char> {
+    match zscii {
+        32..=126 => Some(FONT3[zscii as usize - 32]),
+        _ => None,
+    }
+}
+
 #[derive(Copy, Clone)]
 enum Mode {
-    A0,
-    A1,
-    A2,
-    ABBREV(u8),
-    ZCODE1,
-    ZCODE2(u8),
+    Text,
+    Abbrev(u8),
+    Zcode1,
+    Zcode2(u8),
 }
 
 pub struct Zscii<'a> {
-    ptr: u16,
+    ptr: u32,
     mode: Mode,
     buf: Vec<char>,
-    shift_mode: Option<Mode>,
+    lock: usize,          //current alphabet row; only changed by shift lock in V1 & V2
+    shift: Option<usize>, //alphabet row for the next character only
+    version: u8,
+    alphabet: Alphabet,
+    abbreviations: bool, //off while expanding one, as they may not nest (3.3.1)
     mem: &'a Memory,
 }
 
 impl<'a> Zscii<'a> {
     pub fn new(mem: &Memory) -> Zscii<'_> {
         Zscii {
-            ptr: 0u16,
-            mode: Mode::A0,
+            ptr: 0u32,
+            mode: Mode::Text,
             buf: vec![],
-            shift_mode: None,
+            lock: 0,
+            shift: None,
+            version: mem.zmachine_version(),
+            alphabet: Alphabet::new(mem),
+            abbreviations: true,
             mem,
         }
     }
 
-    pub fn get_string(&mut self, ptr: u16) -> String {
+    //fails rather than reading past the end of the story file
+    pub fn get_string(&mut self, ptr: u32) -> Result<String, Error> {
         self.ptr = ptr;
-        self.mode = Mode::A0;
+        self.mode = Mode::Text;
+        self.lock = 0;
+        self.shift = None;
         self.buf.clear();
         //ZSTRINGs are read in 2 byte pairs. 3 chars are packed into each byte pair, the first bit is set only on the last char
         loop {
@@ -77,170 +200,88 @@ impl<'a> Zscii<'a> {
             // the 'X' bit is discarded, we do some shifts/bytewise stuff to extract the rest
             // dummy's guide:
             // 0x1f = 00011111 = extract last 5 bits. Of course, 3 = 0x11
-            let byte1 = self.mem.read_u8(self.ptr);
+            let byte1 = self.mem.fetch_u8(self.ptr)?;
             self.ptr += 1;
-            let byte2 = self.mem.read_u8(self.ptr);
+            let byte2 = self.mem.fetch_u8(self.ptr)?;
             self.ptr += 1;
 
-            self.decode_zchar((byte1 >> 2) & 0x1f); //AAAAA
-            self.decode_zchar((byte1 & 3u8) << 3 | (byte2 >> 5)); //BBBBB
-            self.decode_zchar(byte2 & 0x1f); //CCCCC
+            self.decode_zchar((byte1 >> 2) & 0x1f)?; //AAAAA
+            self.decode_zchar((byte1 & 3u8) << 3 | (byte2 >> 5))?; //BBBBB
+            self.decode_zchar(byte2 & 0x1f)?; //CCCCC
 
             //check the X bit
             if (byte1 & 0x80) != 0 {
                 break;
             }
         }
-        self.buf.iter().collect()
+        Ok(self.buf.iter().collect())
     }
 
-    pub fn get_ptr(&self) -> u16 {
+    pub fn get_ptr(&self) -> u32 {
         self.ptr
     }
 
-    fn decode_zchar(&mut self, ch: u8) {
+    fn decode_zchar(&mut self, ch: u8) -> Result<(), Error> {
         self.mode = match self.mode {
-            Mode::A0 => match ch {
-                1 => Mode::ABBREV(0),
-                2 => {
-                    if self.mem.zmachine_version() < 3 {
-                        Mode::A1
-                    } else {
-                        Mode::ABBREV(1)
-                    }
-                }
-                3 => {
-                    if self.mem.zmachine_version() < 3 {
-                        Mode::A2
-                    } else {
-                        Mode::ABBREV(2)
-                    }
-                }
-                4 => {
-                    if self.mem.zmachine_version() < 3 {
-                        self.shift_mode = Some(Mode::A1);
-                    }
-                    Mode::A1
-                }
-                5 => {
-                    if self.mem.zmachine_version() < 3 {
-                        self.shift_mode = Some(Mode::A2);
-                    }
-                    Mode::A2
-                }
-                _ => {
-                    self.buf.push(self.zscii_lookup(ch, 0));
-                    match self.shift_mode {
-                        Some(mode) => mode,
-                        None => Mode::A0,
-                    }
-                }
-            },
-            Mode::A1 => match ch {
-                1 => Mode::ABBREV(0),
-                2 => {
-                    if self.mem.zmachine_version() < 3 {
-                        Mode::A2
-                    } else {
-                        Mode::ABBREV(1)
-                    }
-                }
-                3 => {
-                    if self.mem.zmachine_version() < 3 {
-                        Mode::A0
-                    } else {
-                        Mode::ABBREV(2)
-                    }
-                }
-                4 => {
-                    if self.mem.zmachine_version() < 3 {
-                        self.shift_mode = Some(Mode::A2);
-                        Mode::A2
-                    } else {
-                        Mode::A1
-                    }
-                }
-                5 => {
-                    if self.mem.zmachine_version() < 3 {
-                        self.shift_mode = Some(Mode::A0);
-                        Mode::A0
-                    } else {
-                        Mode::A2
-                    }
-                }
-                _ => {
-                    self.buf.push(self.zscii_lookup(ch, 1));
-                    match self.shift_mode {
-                        Some(mode) => mode,
-                        None => Mode::A0,
-                    }
-                }
-            },
-            Mode::A2 => match ch {
-                1 => Mode::ABBREV(0),
-                2 => {
-                    if self.mem.zmachine_version() < 3 {
-                        Mode::A0
-                    } else {
-                        Mode::ABBREV(1)
-                    }
-                }
-                3 => {
-                    if self.mem.zmachine_version() < 3 {
-                        Mode::A1
-                    } else {
-                        Mode::ABBREV(2)
-                    }
-                }
-                4 => {
-                    if self.mem.zmachine_version() < 3 {
-                        self.shift_mode = Some(Mode::A0);
-                        Mode::A0
-                    } else {
-                        Mode::A1
-                    }
-                }
-                5 => {
-                    if self.mem.zmachine_version() < 3 {
-                        self.shift_mode = Some(Mode::A1);
-                        Mode::A1
-                    } else {
-                        Mode::A2
-                    }
-                }
-                6 => Mode::ZCODE1,
-                _ => {
-                    self.buf.push(self.zscii_lookup(ch, 2));
-                    Mode::A0
+            Mode::Text => self.decode_text(ch),
+            Mode::Abbrev(bank) => {
+                self.expand_abbrev(bank * 32 + ch)?;
+                Mode::Text
+            }
+            Mode::Zcode1 => Mode::Zcode2(ch),
+            Mode::Zcode2(code1) => {
+                let code = ch as u16 | (code1 as u16) << 5;
+                if let Some(c) = zscii_to_unicode(self.mem, code) {
+                    self.buf.push(c);
                 }
-            },
-            Mode::ABBREV(table) => {
-                let abbrev = table * 32 + ch;
-                let mut zscii = Zscii::new(self.mem);
-                let str = zscii.get_string(
-                    self.mem
-                        .read_u16(self.mem.abbreviations_table() + (2 * abbrev as u16)),
-                );
-                let mut abbrev_vec: Vec<char> = str.chars().collect::<Vec<_>>();
-                self.buf.append(&mut abbrev_vec);
-                Mode::A0
+                Mode::Text
             }
-            Mode::ZCODE1 => Mode::ZCODE2(ch),
-            Mode::ZCODE2(code1) => {
-                let code: [u16; 1] = [ch as u16 | (code1 as u16) << 5];
-                let mut char: Vec<char> =
-                    char::decode_utf16(code).map(|r| r.unwrap_or(' ')).collect();
-                self.buf.append(&mut char);
-                Mode::A0
+        };
+        Ok(())
+    }
+
+    //See 3.2 & 3.3: the meaning of Z-chars 1-5 differs between V1, V2 and V3+
+    fn decode_text(&mut self, ch: u8) -> Mode {
+        let row = self.shift.take().unwrap_or(self.lock);
+        match ch {
+            0 => self.buf.push(' '),
+            1 if self.version == 1 => self.buf.push('\n'),
+            1 => return Mode::Abbrev(0),
+            2 | 3 if self.version >= 3 => return Mode::Abbrev(ch - 1),
+            //V1 & V2: 2 & 3 shift to the next/previous alphabet for one character,
+            //4 & 5 do the same permanently (shift lock)
+            2 | 3 => self.shift = Some((self.lock + ch as usize - 1) % 3),
+            4 | 5 if self.version < 3 => self.lock = (self.lock + ch as usize - 3) % 3,
+            4 | 5 => self.shift = Some(ch as usize - 3),
+            6 if row == 2 => return Mode::Zcode1,
+            _ => {
+                let c = self.zscii_lookup(ch, row);
+                self.buf.push(c);
             }
         }
+        Mode::Text
     }
 
-    fn zscii_lookup(&self, ch: u8, mode: usize) -> char {
-        if self.mem.zmachine_version() == 1 {
-            ZSCII_MAP1[mode][ch as usize]
-        } else {
-            ZSCII_MAP234[mode][ch as usize]
+    //abbreviation table entries are word addresses. One inside an abbreviation prints nothing,
+    //so an abbreviation which refers to itself can't recurse forever
+    fn expand_abbrev(&mut self, abbrev: u8) -> Result<(), Error> {
+        if !self.abbreviations {
+            return Ok(());
         }
+        let addr = self
+            .mem
+            .fetch_u16(self.mem.abbreviations_table() as u32 + 2 * abbrev as u32)?;
+        let mut zscii = Zscii::new(self.mem);
+        zscii.abbreviations = false;
+        let str = zscii.get_string(addr as u32 * 2)?;
+        self.buf.extend(str.chars());
+        Ok(())
+    }
+
+    fn zscii_lookup(&self, ch: u8, mode: usize) -> char {
+        zscii_to_unicode(self.mem, self.alphabet.lookup(mode, ch) as u16).unwrap_or('?')
     }
 }
+
+#[cfg(test)]
+mod zscii_tests;
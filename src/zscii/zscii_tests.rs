@@ -0,0 +1,234 @@
+use super::*;
+
+// Packs Z-chars three to a word, setting the end bit on the last word
+fn zstring(zchars: &[u8]) -> Vec<u8> {
+    let mut zchars = zchars.to_vec();
+    while !zchars.len().is_multiple_of(3) {
+        zchars.push(5);
+    }
+    let mut bytes = vec![];
+    let words = zchars.len() / 3;
+    for (i, c) in zchars.chunks(3).enumerate() {
+        let mut word = (c[0] as u16) << 10 | (c[1] as u16) << 5 | c[2] as u16;
+        if i == words - 1 {
+            word |= 0x8000;
+        }
+        bytes.extend_from_slice(&word.to_be_bytes());
+    }
+    bytes
+}
+
+fn create_memory(version: u8, text: &[u8]) -> Memory {
+    let mut data = vec![0u8; 1024];
+    data[0x00] = version;
+    data[0x100..0x100 + text.len()].copy_from_slice(text);
+    Memory::new(&data)
+}
+
+#[test]
+fn test_default_alphabet() {
+    // "Hi, 7" + newline: shift A1 'h', 'i', shift A2 ',', space, shift A2 '7', shift A2 newline
+    let mem = create_memory(5, &zstring(&[4, 13, 14, 5, 19, 0, 5, 15, 5, 7]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "Hi, 7\n");
+}
+
+#[test]
+fn test_custom_alphabet() {
+    let mut mem = create_memory(5, &zstring(&[6, 7, 4, 6, 5, 7, 5, 8]));
+    // Custom table at 0x200: A0 is reversed, A1 is digits, A2 is punctuation
+    let mut table = vec![];
+    table.extend_from_slice(b"zyxwvutsrqponmlkjihgfedcba");
+    table.extend_from_slice(b"0123456789!!!!!!!!!!!!!!!!");
+    table.extend_from_slice(b"XY*+-/=<>?@[]^_{}|~.,;:()&");
+    for (i, b) in table.iter().enumerate() {
+        mem.write_u8(0x200 + i as u16, *b);
+    }
+    mem.write_u16(0x34, 0x200);
+
+    // A2 Z-char 7 stays newline regardless of the table contents
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "zy0\n*");
+}
+
+#[test]
+fn test_custom_alphabet_ignored_before_v5() {
+    let mut mem = create_memory(4, &zstring(&[6, 7]));
+    for i in 0..78 {
+        mem.write_u8(0x200 + i, b'!');
+    }
+    mem.write_u16(0x34, 0x200);
+
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "ab");
+}
+
+#[test]
+fn test_custom_alphabet_past_end_of_story() {
+    let mut mem = create_memory(5, &zstring(&[6, 7]));
+    mem.write_u16(0x34, 0x3F0);
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "ab");
+}
+
+#[test]
+fn test_alphabet_find() {
+    let mut mem = create_memory(5, &[]);
+    for i in 0..78 {
+        mem.write_u8(0x200 + i, b'a' + (i % 26) as u8);
+    }
+    mem.write_u8(0x200 + 52 + 2, b'%');
+    mem.write_u16(0x34, 0x200);
+
+    let alphabet = Alphabet::new(&mem);
+    assert_eq!(alphabet.find(b'c'), Some((0, 8)));
+    assert_eq!(alphabet.find(b'%'), Some((2, 8)));
+    assert_eq!(alphabet.find(13), Some((2, 7)));
+    assert_eq!(alphabet.find(b'A'), None);
+}
+
+#[test]
+fn test_default_unicode_table() {
+    let mem = create_memory(5, &[]);
+    assert_eq!(zscii_to_unicode(&mem, 155), Some('ä'));
+    assert_eq!(zscii_to_unicode(&mem, 223), Some('¿'));
+    assert_eq!(zscii_to_unicode(&mem, 224), None);
+    assert_eq!(zscii_to_unicode(&mem, 13), Some('\n'));
+    assert_eq!(zscii_to_unicode(&mem, 0), None);
+    assert_eq!(zscii_to_unicode(&mem, 129), None);
+
+    assert_eq!(unicode_to_zscii(&mem, 'é'), Some(170));
+    assert_eq!(unicode_to_zscii(&mem, 'A'), Some(65));
+    assert_eq!(unicode_to_zscii(&mem, '€'), None);
+}
+
+#[test]
+fn test_custom_unicode_table() {
+    let mut mem = create_memory(5, &[]);
+    // Header extension at 0x300 with 3 words, word 3 points at the table at 0x320
+    mem.write_u16(0x36, 0x300);
+    mem.write_u16(0x300, 3);
+    mem.write_u16(0x306, 0x320);
+    mem.write_u8(0x320, 2);
+    mem.write_u16(0x321, 0x20AC); // €
+    mem.write_u16(0x323, 0x0416); // Ж
+
+    assert_eq!(zscii_to_unicode(&mem, 155), Some('€'));
+    assert_eq!(zscii_to_unicode(&mem, 156), Some('Ж'));
+    assert_eq!(zscii_to_unicode(&mem, 157), None);
+    assert_eq!(unicode_to_zscii(&mem, 'Ж'), Some(156));
+    assert_eq!(unicode_to_zscii(&mem, 'ä'), None);
+}
+
+#[test]
+fn test_unicode_table_out_of_range() {
+    // The header extension table is past the end of the story
+    let mut mem = create_memory(5, &[]);
+    mem.write_u16(0x36, 0xFFFF);
+    assert_eq!(zscii_to_unicode(&mem, 157), Some('ü'));
+
+    // The Unicode table's entries run off the end of the story
+    mem.write_u16(0x36, 0x300);
+    mem.write_u16(0x300, 3);
+    mem.write_u16(0x306, 0x3FE);
+    mem.write_u8(0x3FE, 10);
+    assert_eq!(zscii_to_unicode(&mem, 157), Some('ü'));
+    assert_eq!(unicode_to_zscii(&mem, 'ä'), Some(155));
+}
+
+#[test]
+fn test_zscii_escape() {
+    // A2 escape followed by ZSCII 170 (é) split over two Z-chars: 5, 10
+    let mem = create_memory(5, &zstring(&[5, 6, 5, 10]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "é");
+}
+
+fn write_bytes(mem: &mut Memory, addr: u16, bytes: &[u8]) {
+    for (i, b) in bytes.iter().enumerate() {
+        mem.write_u8(addr + i as u16, *b);
+    }
+}
+
+#[test]
+fn test_v1_newline_and_shift() {
+    // Shift to A1 for 'H' only, then 'i' and Z-char 1 (newline in V1)
+    let mem = create_memory(1, &zstring(&[2, 13, 14, 1]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "Hi\n");
+}
+
+#[test]
+fn test_v1_a2_row() {
+    // Shift down from A0 to A2: Z-char 7 is '0' and 27 is '<' in V1
+    let mem = create_memory(1, &zstring(&[3, 7, 3, 27]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "0<");
+}
+
+#[test]
+fn test_v2_shift_lock() {
+    // Lock A1 for 'A' 'B', lock back down to A0 for 'a'
+    let mut mem = create_memory(2, &zstring(&[4, 6, 7, 5, 6]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "ABa");
+
+    // Shift from a locked alphabet is relative to it: A1 -> A2 for one char, then back to A1
+    write_bytes(&mut mem, 0x100, &zstring(&[4, 2, 8, 6]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "0A");
+}
+
+#[test]
+fn test_v2_shift_lock_reset_between_strings() {
+    let mut mem = create_memory(2, &zstring(&[4, 6]));
+    write_bytes(&mut mem, 0x110, &zstring(&[6, 6]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "A");
+    assert_eq!(zscii.get_string(0x110).unwrap(), "aa");
+}
+
+#[test]
+fn test_v2_abbreviation() {
+    // Abbreviation 0 at word address 0x180 (byte address 0x300)
+    let mut mem = create_memory(2, &zstring(&[1, 0, 2, 6]));
+    mem.write_u16(0x18, 0x200);
+    mem.write_u16(0x200, 0x180);
+    write_bytes(&mut mem, 0x300, &zstring(&[6, 7]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "abA");
+}
+
+#[test]
+fn test_v3_abbreviation_banks() {
+    // Z-char 2 selects bank 1: abbreviation 32
+    let mut mem = create_memory(3, &zstring(&[2, 0, 6]));
+    mem.write_u16(0x18, 0x200);
+    mem.write_u16(0x200 + 64, 0x180);
+    write_bytes(&mut mem, 0x300, &zstring(&[0, 7]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), " ba");
+}
+
+#[test]
+fn test_abbreviation_in_abbreviation() {
+    // Abbreviation 0 is "a", then itself
+    let mut mem = create_memory(3, &zstring(&[1, 0, 7]));
+    mem.write_u16(0x18, 0x200);
+    mem.write_u16(0x200, 0x180);
+    write_bytes(&mut mem, 0x300, &zstring(&[6, 1, 0]));
+    let mut zscii = Zscii::new(&mem);
+    assert_eq!(zscii.get_string(0x100).unwrap(), "ab");
+}
+
+#[test]
+fn test_font3_to_unicode() {
+    assert_eq!(font3_to_unicode(32), Some(' '));
+    assert_eq!(font3_to_unicode(33), Some('←'));
+    assert_eq!(font3_to_unicode(47), Some('┌'));
+    assert_eq!(font3_to_unicode(54), Some('█'));
+    assert_eq!(font3_to_unicode(97), Some('A'));
+    assert_eq!(font3_to_unicode(126), Some('?'));
+    assert_eq!(font3_to_unicode(127), None);
+    assert_eq!(font3_to_unicode(13), None);
+}
@@ -1,10 +1,40 @@
+use crate::graphics::Framebuffer;
+use crate::window::Window;
+
+/// The action requested by `sound_effect` for a sound resource.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SoundEffect {
+    Prepare,
+    Start,
+    Stop,
+    Finish,
+}
+
+/// A keypress or mouse click, as delivered to `read_char`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Key(char),
+    /// A click at screen position (y, x), 1-based, in units.
+    Click { y: u16, x: u16, double: bool },
+}
+
+/// The mouse position and button state reported by `read_mouse`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MouseState {
+    pub y: u16,
+    pub x: u16,
+    /// Bit 0 is the leftmost button.
+    pub buttons: u16,
+    /// Menu number (high byte) and item (low byte) selected from a frontend menu, or 0.
+    pub menu: u16,
+}
+
 pub trait ZScreen {
     fn newline(&self);
     fn print(&self, str: String);
     fn read(&self) -> char;
     fn readline(&self) -> String;
     fn exit(&self);
-    fn random(&self, limit: u16) -> u16;
     fn set_status(&self, status: String);
     fn get_width(&self);
     fn get_height(&self);
@@ -15,6 +45,60 @@ pub trait ZScreen {
     fn split_window(&self, height: u16);
     fn erase_window(&self, num: u16);
     fn move_cursor(&self, x: u8, y: u8);
+    /// Not called by the core, which prints numbers as signed text through `print`.
     fn print_number(&self, num: u16);
     fn print_char(&self, char: char);
+
+    /// Whether the frontend can display `char`. Used by `check_unicode`.
+    fn can_print_char(&self, _char: char) -> bool {
+        true
+    }
+
+    /// Whether the frontend can receive `char` from the keyboard. Used by `check_unicode`.
+    fn can_input_char(&self, _char: char) -> bool {
+        true
+    }
+
+    /// Plays one of the built-in bleeps: 1 is high-pitched, 2 is low-pitched.
+    fn beep(&self, _number: u16) {}
+
+    /// Acts on sound resource `number` (3 upwards). `volume` is 1-8, or 255 for loudest;
+    /// `repeats` is the number of times to play, 255 meaning forever.
+    fn sound_effect(&self, _number: u16, _effect: SoundEffect, _volume: u8, _repeats: u8) {}
+
+    /// Polled between instructions while a sound started with an end-of-sound routine (V5+)
+    /// may still be playing. Frontends which don't play sound can leave this returning true.
+    fn sound_finished(&self, _number: u16) -> bool {
+        true
+    }
+
+    /// Called when the game changes a window's position, size, margins, style or other
+    /// properties (V6 window model).
+    fn window_changed(&self, _num: u16, _window: &Window) {}
+
+    /// Scrolls window `num` up by `pixels`, or down if negative.
+    fn scroll_window(&self, _num: u16, _pixels: i16) {}
+
+    /// Called after a picture is drawn or erased in window `num`, with its updated framebuffer.
+    fn framebuffer_updated(&self, _num: u16, _framebuffer: &Framebuffer) {}
+
+    /// Reads a keypress or mouse click for `read_char`. Frontends without a mouse can
+    /// leave this to `read`.
+    fn read_input(&self) -> Input {
+        Input::Key(self.read())
+    }
+
+    /// The current mouse position and buttons, for `read_mouse`.
+    fn mouse_state(&self) -> MouseState {
+        MouseState::default()
+    }
+
+    /// Whether the frontend has font `font`: 1 is the normal font, 2 pictures, 3 character
+    /// graphics (see `font3_to_unicode`) and 4 fixed pitch.
+    fn font_available(&self, font: u16) -> bool {
+        matches!(font, 1 | 3 | 4)
+    }
+
+    /// Called when the game changes the font of window `num`.
+    fn set_font(&self, _num: u16, _font: u16) {}
 }